@@ -0,0 +1,352 @@
+//! Editing the recipients of a directory: its `.gpg-id` (or
+//! `.age-recipients` in an age store) and the secrets encrypted to it.
+
+use crate::crypto::{self, CryptoBackend, KeyInfo};
+use crate::fsck;
+use crate::recipients;
+use crate::tree;
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// A key or other `.gpg-id` entry that can be a recipient.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    label: String,
+    /// The line written to `.gpg-id`.
+    value: String,
+    selected: bool,
+}
+
+/// The GnuPG search for a `.gpg-id` entry. Plain email addresses are looked
+/// up as `<address>`, an exact match, so that `bob@example.com` does not also
+/// name `alice+bob@example.com`.
+fn gpg_query(recipient: &str) -> String {
+    if recipient.contains('@') && !recipient.contains(['<', ' ']) {
+        format!("<{}>", recipient)
+    } else {
+        recipient.to_string()
+    }
+}
+
+/// Key IDs of the keys and subkeys a `.gpg-id` entry names.
+fn resolve_gpg_entry(recipient: &str) -> HashSet<u64> {
+    crypto::recipient_key_ids(&gpg_query(recipient)).unwrap_or_else(|e| {
+        debug!("Recipient '{}' not resolved: {}", recipient, e);
+        HashSet::new()
+    })
+}
+
+/// Key ID of the primary key, the last 16 hex digits of its fingerprint.
+fn key_id(key: &KeyInfo) -> Option<u64> {
+    let fingerprint = &key.fingerprint;
+    u64::from_str_radix(&fingerprint[fingerprint.len().saturating_sub(16)..], 16).ok()
+}
+
+/// Outcome of saving the recipients.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub reencrypted: usize,
+    /// Secrets that could not be re-encrypted, with the reason.
+    pub failed: Vec<String>,
+}
+
+/// Messages for recipients panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// The keys and current recipients of a directory were read, with an
+    /// error if its recipients file could not be read.
+    Loaded(PathBuf, Vec<Entry>, Option<String>),
+    Toggle(usize, bool),
+    /// Write the `.gpg-id` and re-encrypt the secrets using it.
+    Save,
+    Saved(Result<Summary, String>),
+    Close,
+}
+
+/// Write the recipients file of `dir` and re-encrypt every secret it applies to.
+fn save(root: &Path, dir: &Path, recipients: &[String]) -> Result<Summary, String> {
+    let backend = crypto::store_backend(root);
+    let gpg_id = recipients::write_gpg_id(backend, dir, recipients).map_err(|e| e.to_string())?;
+    let files = tree::secret_files(dir).map_err(|e| e.to_string())?;
+
+    let mut summary = Summary {
+        reencrypted: 0,
+        failed: Vec::new(),
+    };
+    for file in files {
+        // Subdirectories with their own `.gpg-id` keep their recipients, and
+        // secrets of the other backend use another file
+        if recipients::find_gpg_id(&file, root).as_ref() != Some(&gpg_id) {
+            continue;
+        }
+        match fsck::reencrypt(root, &file) {
+            Ok(()) => summary.reencrypted += 1,
+            Err(e) => {
+                warn!("Failed to re-encrypt '{}': {}", file.display(), e);
+                let name = file.strip_prefix(root).unwrap_or(&file).display();
+                summary.failed.push(format!("{}: {}", name, e));
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// The keys that can be selected, with the recipients that currently apply
+/// to `dir` selected.
+fn load(root: &Path, dir: &Path) -> (Vec<Entry>, Option<String>) {
+    let mut error = None;
+    let current = match recipients::find_gpg_id(dir, root) {
+        Some(gpg_id) => recipients::read_gpg_id(&gpg_id).unwrap_or_else(|e| {
+            error = Some(e.to_string());
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    let backend = crypto::store_backend(root);
+    let keys = backend.encryption_keys().unwrap_or_else(|e| {
+        log::error!("Failed to list keys: {}", e);
+        Vec::new()
+    });
+
+    // age recipients are the keys themselves, GnuPG entries may also be key
+    // IDs or email addresses
+    let resolved: HashMap<&str, HashSet<u64>> =
+        if backend.recipients_file() == recipients::GPG_ID_FILE {
+            current
+                .iter()
+                .map(|recipient| (recipient.as_str(), resolve_gpg_entry(recipient)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+    let names_key = |recipient: &String, key: &KeyInfo| match resolved.get(recipient.as_str()) {
+        Some(ids) => key_id(key).is_some_and(|id| ids.contains(&id)),
+        None => *recipient == key.fingerprint,
+    };
+
+    let mut entries: Vec<Entry> = keys
+        .iter()
+        .map(|key| Entry {
+            label: key.to_string(),
+            value: key.fingerprint.clone(),
+            selected: current.iter().any(|recipient| names_key(recipient, key)),
+        })
+        .collect();
+    // Keep entries for keys that are not in the keyring
+    entries.extend(
+        current
+            .iter()
+            .filter(|recipient| !keys.iter().any(|key| names_key(recipient, key)))
+            .map(|recipient| Entry {
+                label: format!("{} (unknown {} key)", recipient, backend.name()),
+                value: recipient.clone(),
+                selected: true,
+            }),
+    );
+    (entries, error)
+}
+
+/// State of the recipients panel of a directory.
+pub struct State {
+    root: PathBuf,
+    pub dir: PathBuf,
+    backend: &'static dyn CryptoBackend,
+    entries: Vec<Entry>,
+    /// Whether the keys are being listed in the background.
+    loading: bool,
+    saving: bool,
+    error: Option<String>,
+}
+
+impl State {
+    /// Start from the recipients that currently apply to `dir`, reading
+    /// them and listing the keys in the background.
+    pub fn new(root: PathBuf, dir: PathBuf) -> (Self, Task<Message>) {
+        let backend = crypto::store_backend(&root);
+        let task = {
+            let (root, dir) = (root.clone(), dir.clone());
+            Task::perform(
+                async move {
+                    let (entries, error) = load(&root, &dir);
+                    (dir, entries, error)
+                },
+                |(dir, entries, error)| Message::Loaded(dir, entries, error),
+            )
+        };
+        let state = Self {
+            root,
+            dir,
+            backend,
+            entries: Vec::new(),
+            loading: true,
+            saving: false,
+            error: None,
+        };
+        (state, task)
+    }
+
+    fn selected(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.selected)
+            .map(|entry| entry.value.clone())
+            .collect()
+    }
+
+    /// Save the recipients in the background.
+    pub fn save(&mut self) -> Task<Message> {
+        let recipients = self.selected();
+        if recipients.is_empty() || self.saving {
+            return Task::none();
+        }
+        debug!(
+            "Setting {} recipients for '{}'",
+            recipients.len(),
+            self.dir.display()
+        );
+        self.saving = true;
+        self.error = None;
+        let (root, dir) = (self.root.clone(), self.dir.clone());
+        Task::perform(
+            async move { save(&root, &dir, &recipients) },
+            Message::Saved,
+        )
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::Loaded(dir, entries, error) => {
+                if self.loading && dir == self.dir {
+                    self.loading = false;
+                    self.entries = entries;
+                    self.error = error;
+                }
+            }
+            Message::Toggle(index, selected) => {
+                if let Some(entry) = self.entries.get_mut(index) {
+                    entry.selected = selected;
+                }
+            }
+            Message::Saved(Err(e)) => {
+                self.saving = false;
+                self.error = Some(e);
+            }
+            Message::Saved(Ok(_)) => self.saving = false,
+            Message::Save | Message::Close => {}
+        }
+    }
+}
+
+/// Renders the recipients panel: the keys to encrypt the directory to.
+///
+/// # Arguments
+/// * `state` - The current recipients panel state
+/// * `on_message` - Function to wrap recipients messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let name = state
+        .dir
+        .strip_prefix(&state.root)
+        .unwrap_or(&state.dir)
+        .display();
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let header = widget::row![
+        widget::text(format!("Recipients of {}", name)).size(16),
+        widget::horizontal_space(),
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let recipients_file = state.backend.recipients_file();
+    let signing = if recipients_file != recipients::GPG_ID_FILE {
+        format!("The {} file is not signed.", recipients_file)
+    } else if recipients::signing_keys().is_empty() {
+        format!(
+            "The {} file is not signed. Set {} to sign it.",
+            recipients::GPG_ID_FILE,
+            recipients::SIGNING_KEY_VAR
+        )
+    } else {
+        format!(
+            "The {} file is signed with your key from {}.",
+            recipients::GPG_ID_FILE,
+            recipients::SIGNING_KEY_VAR
+        )
+    };
+    let info = widget::text(format!(
+        "Secrets in this directory are re-encrypted to the selected keys. {}",
+        signing
+    ))
+    .size(14);
+
+    let error = state.error.as_ref().map(|e| {
+        widget::text(e)
+            .size(14)
+            .color(iced::Color::from_rgb(0.7, 0.1, 0.1))
+    });
+
+    let mut keys = widget::column![]
+        .push_maybe(
+            state
+                .loading
+                .then(|| widget::text("Listing keys...").size(14)),
+        )
+        .spacing(4);
+    for (index, entry) in state.entries.iter().enumerate() {
+        keys = keys.push(
+            widget::checkbox(entry.label.as_str(), entry.selected)
+                .on_toggle(move |selected| Message::Toggle(index, selected)),
+        );
+    }
+
+    let save_button =
+        widget::button(widget::text(if state.saving { "Saving..." } else { "Save" }).size(14))
+            .on_press_maybe(
+                (!state.saving && state.entries.iter().any(|e| e.selected))
+                    .then_some(Message::Save),
+            );
+
+    let content: Element<'a, Message> = widget::column![header, info]
+        .push_maybe(error)
+        .push(widget::scrollable(keys).height(Length::Fill))
+        .push(widget::row![widget::horizontal_space(), save_button])
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_email_addresses_exactly() {
+        assert_eq!(gpg_query("bob@example.com"), "<bob@example.com>");
+        assert_eq!(gpg_query("<bob@example.com>"), "<bob@example.com>");
+        assert_eq!(gpg_query("Bob <bob@example.com>"), "Bob <bob@example.com>");
+        assert_eq!(gpg_query("0x1234567890ABCDEF"), "0x1234567890ABCDEF");
+    }
+
+    #[test]
+    fn takes_the_key_id_from_the_fingerprint() {
+        let key = KeyInfo {
+            fingerprint: "0123456789ABCDEF0123456789ABCDEF01234567".to_string(),
+            user_id: "Bob <bob@example.com>".to_string(),
+        };
+        assert_eq!(key_id(&key), Some(0x89ABCDEF01234567));
+        let age = KeyInfo {
+            fingerprint: "age1qyqszqgpqyqszqgpqyqszqgpqyqszqgp".to_string(),
+            user_id: "Your age key".to_string(),
+        };
+        assert_eq!(key_id(&age), None);
+    }
+}
@@ -10,3 +10,5 @@ pub const FOLDER_LOGO: &[u8] = include_bytes!("../assets/folder.svg");
 pub const SECRET_FILE_LOGO: &[u8] = include_bytes!("../assets/secret-file.svg");
 pub const SECRET_LOGO: &[u8] = include_bytes!("../assets/secret.svg");
 pub const FILE_LOGO: &[u8] = include_bytes!("../assets/file.svg");
+pub const WARNING_LOGO: &[u8] = include_bytes!("../assets/warning.svg");
+pub const LOCKED_LOGO: &[u8] = include_bytes!("../assets/locked.svg");
@@ -0,0 +1,462 @@
+use crate::breach;
+use crate::crypto::{self, CryptoError};
+use crate::git;
+use crate::job::{self, CancelFlag, Job};
+use crate::secret::Secret;
+use crate::strength;
+use crate::tree;
+use iced::futures::{SinkExt, Stream};
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Passwords scoring below this are reported as weak.
+const MIN_SCORE: u8 = 3;
+const DEFAULT_MAX_AGE_DAYS: u64 = 365;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// What is wrong with a secret.
+#[derive(Debug, Clone)]
+pub enum Problem {
+    /// Found in the password hash file.
+    Breached,
+    /// Strength score from 0 to 4, see `strength::score`.
+    Weak(u8),
+    /// Number of other secrets with the same password.
+    Reused(usize),
+    /// Days since the secret last changed.
+    Old(u64),
+    Unreadable(String),
+}
+
+impl Problem {
+    fn describe(&self) -> String {
+        match self {
+            Problem::Breached => "Password appears in a data breach".to_string(),
+            Problem::Weak(score) => format!("Weak password (strength {} of 4)", score),
+            Problem::Reused(1) => "Password shared with 1 other secret".to_string(),
+            Problem::Reused(count) => format!("Password shared with {} other secrets", count),
+            Problem::Old(days) => format!("Unchanged for {} days", days),
+            Problem::Unreadable(e) => format!("Could not decrypt: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub path: PathBuf,
+    pub problem: Problem,
+}
+
+/// Outcome of an audit, with breached, weak, reused, old and unreadable
+/// secrets in that order.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checked: usize,
+    pub findings: Vec<Finding>,
+    /// Whether passwords were looked up in a password hash file.
+    pub breach_checked: bool,
+    pub error: Option<String>,
+}
+
+impl Report {
+    fn count(&self, matches: fn(&Problem) -> bool) -> usize {
+        self.findings.iter().filter(|f| matches(&f.problem)).count()
+    }
+
+    /// Secrets whose password was found in the password hash file.
+    pub fn breached(&self) -> impl Iterator<Item = &Path> {
+        self.findings
+            .iter()
+            .filter(|f| matches!(f.problem, Problem::Breached))
+            .map(|f| f.path.as_path())
+    }
+}
+
+/// Messages for audit panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    MaxAgeChanged(String),
+    /// Choose a Have I Been Pwned password file to check against.
+    PickBreachFile,
+    ClearBreachFile,
+    Start,
+    Cancel,
+    /// Number of secrets checked so far, out of the total.
+    Progress(usize, usize),
+    Finished(Report),
+    /// Show the secret of a finding.
+    Open(PathBuf),
+    Close,
+}
+
+/// Seconds since the Unix epoch at which each secret last changed, from git
+/// if possible and the modification time otherwise.
+fn change_time(changes: &HashMap<PathBuf, u64>, path: &Path) -> Option<u64> {
+    if let Some(time) = changes.get(path) {
+        return Some(*time);
+    }
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Decrypt and check every secret below `root`, reporting progress to `output`.
+async fn audit(
+    root: &Path,
+    max_age_days: u64,
+    breach_file: Option<&Path>,
+    cancel: &CancelFlag,
+    output: &mut iced::futures::channel::mpsc::Sender<Message>,
+) -> Report {
+    let mut hash_file = match breach_file.map(breach::Index::open).transpose() {
+        Ok(hash_file) => hash_file,
+        Err(e) => {
+            return Report {
+                error: Some(format!("Could not open the password hash file: {}", e)),
+                ..Report::default()
+            }
+        }
+    };
+    let files = match tree::secret_files(root) {
+        Ok(files) => files,
+        Err(e) => {
+            return Report {
+                error: Some(format!("Could not list the secrets: {}", e)),
+                ..Report::default()
+            }
+        }
+    };
+    let changes = if git::is_repository(root) {
+        git::last_changes(root).unwrap_or_else(|e| {
+            warn!("Falling back to modification times: {}", e);
+            HashMap::new()
+        })
+    } else {
+        HashMap::new()
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut breached = Vec::new();
+    let mut weak = Vec::new();
+    let mut old = Vec::new();
+    let mut unreadable = Vec::new();
+    // Only hashes of the passwords are kept in memory
+    let mut hashes: HashMap<[u8; 32], Vec<PathBuf>> = HashMap::new();
+
+    for (index, path) in files.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let _ = output.send(Message::Progress(index, files.len())).await;
+
+        let plaintext = fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| match crypto::backend(path).decrypt_text(&data) {
+                // Binary secrets like certificates have no password to check
+                Err(CryptoError::Utf8Error(_)) => Ok(String::new()),
+                result => result.map_err(|e| e.to_string()),
+            })
+            .map(Zeroizing::new);
+        match plaintext {
+            Ok(plaintext) => {
+                let secret = Secret::parse(&plaintext);
+                if !secret.password.is_empty() {
+                    if let Some(hash_file) = &mut hash_file {
+                        match hash_file.contains(&secret.password) {
+                            Ok(true) => breached.push(Finding {
+                                path: path.clone(),
+                                problem: Problem::Breached,
+                            }),
+                            Ok(false) => {}
+                            Err(e) => warn!("Failed to look up '{}': {}", path.display(), e),
+                        }
+                    }
+                    let score = strength::score(&secret.password);
+                    if score < MIN_SCORE {
+                        weak.push(Finding {
+                            path: path.clone(),
+                            problem: Problem::Weak(score),
+                        });
+                    }
+                    let hash = Sha256::digest(secret.password.as_bytes()).into();
+                    hashes.entry(hash).or_default().push(path.clone());
+                }
+            }
+            Err(e) => unreadable.push(Finding {
+                path: path.clone(),
+                problem: Problem::Unreadable(e),
+            }),
+        }
+
+        if let Some(time) = change_time(&changes, path) {
+            let days = now.saturating_sub(time) / SECONDS_PER_DAY;
+            if days >= max_age_days {
+                old.push(Finding {
+                    path: path.clone(),
+                    problem: Problem::Old(days),
+                });
+            }
+        }
+    }
+
+    let mut reused: Vec<Finding> = hashes
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .flat_map(|paths| {
+            let others = paths.len() - 1;
+            paths.into_iter().map(move |path| Finding {
+                path,
+                problem: Problem::Reused(others),
+            })
+        })
+        .collect();
+    reused.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut findings = breached;
+    findings.append(&mut weak);
+    findings.append(&mut reused);
+    findings.append(&mut old);
+    findings.append(&mut unreadable);
+    Report {
+        checked: files.len(),
+        findings,
+        breach_checked: hash_file.is_some(),
+        error: None,
+    }
+}
+
+/// Run an audit of the store at `root`, yielding progress and then the report.
+fn run(
+    root: PathBuf,
+    max_age_days: u64,
+    breach_file: Option<PathBuf>,
+    cancel: CancelFlag,
+) -> impl Stream<Item = Message> {
+    iced::stream::channel(16, move |mut output| async move {
+        let report = audit(
+            &root,
+            max_age_days,
+            breach_file.as_deref(),
+            &cancel,
+            &mut output,
+        )
+        .await;
+        debug!(
+            "Audit of {} secrets found {} problems",
+            report.checked,
+            report.findings.len()
+        );
+        let _ = output.send(Message::Finished(report)).await;
+    })
+}
+
+/// State of the password audit panel.
+pub struct State {
+    root: PathBuf,
+    max_age: String,
+    /// Have I Been Pwned password file, sorted by hash.
+    breach_file: Option<PathBuf>,
+    /// Cancels the running audit when dropped.
+    running: Option<Job>,
+    progress: (usize, usize),
+    report: Option<Report>,
+}
+
+impl State {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            max_age: DEFAULT_MAX_AGE_DAYS.to_string(),
+            breach_file: None,
+            running: None,
+            progress: (0, 0),
+            report: None,
+        }
+    }
+
+    fn max_age_days(&self) -> Option<u64> {
+        self.max_age.trim().parse().ok()
+    }
+
+    /// Start an audit, cancelling the one in progress if any.
+    pub fn start(&mut self) -> Task<Message> {
+        let Some(max_age_days) = self.max_age_days() else {
+            return Task::none();
+        };
+        debug!("Auditing '{}'", self.root.display());
+        let (root, breach_file) = (self.root.clone(), self.breach_file.clone());
+        let (task, job) = job::spawn(|cancel| run(root, max_age_days, breach_file, cancel));
+        self.running = Some(job);
+        self.progress = (0, 0);
+        self.report = None;
+        task
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::MaxAgeChanged(value) => self.max_age = value,
+            Message::PickBreachFile => {
+                if let Some(file) = rfd::FileDialog::new()
+                    .set_title("Have I Been Pwned password file")
+                    .pick_file()
+                {
+                    self.breach_file = Some(file);
+                }
+            }
+            Message::ClearBreachFile => self.breach_file = None,
+            Message::Cancel => {
+                debug!("Audit cancelled");
+                self.running = None;
+            }
+            Message::Progress(done, total) => self.progress = (done, total),
+            Message::Finished(report) => {
+                self.running = None;
+                self.report = Some(report);
+            }
+            Message::Start | Message::Open(_) | Message::Close => {}
+        }
+    }
+}
+
+fn view_report<'a>(state: &'a State, report: &'a Report) -> Element<'a, Message> {
+    if let Some(e) = &report.error {
+        return widget::text(e).size(14).into();
+    }
+
+    let breached = if report.breach_checked {
+        format!(
+            "{} breached, ",
+            report.count(|p| matches!(p, Problem::Breached))
+        )
+    } else {
+        String::new()
+    };
+    let summary = widget::text(format!(
+        "Checked {} secrets: {}{} weak, {} reused, {} old, {} unreadable.",
+        report.checked,
+        breached,
+        report.count(|p| matches!(p, Problem::Weak(_))),
+        report.count(|p| matches!(p, Problem::Reused(_))),
+        report.count(|p| matches!(p, Problem::Old(_))),
+        report.count(|p| matches!(p, Problem::Unreadable(_))),
+    ))
+    .size(14);
+
+    let mut findings = widget::column![].spacing(2);
+    for finding in &report.findings {
+        let name = finding
+            .path
+            .strip_prefix(&state.root)
+            .unwrap_or(&finding.path)
+            .with_extension("");
+        let label = widget::column![
+            widget::text(name.display().to_string()).size(14),
+            widget::text(finding.problem.describe()).size(12),
+        ];
+        findings = findings.push(
+            widget::button(label)
+                .on_press(Message::Open(finding.path.clone()))
+                .style(widget::button::text)
+                .width(Length::Fill),
+        );
+    }
+
+    widget::column![summary, widget::scrollable(findings).height(Length::Fill)]
+        .spacing(10)
+        .into()
+}
+
+/// Renders the audit panel: the audit options, its progress and the findings.
+///
+/// # Arguments
+/// * `state` - The current audit panel state
+/// * `on_message` - Function to wrap audit messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let header = widget::row![
+        widget::text("Password audit").size(16),
+        widget::horizontal_space(),
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let action = if state.running.is_some() {
+        widget::button(widget::text("Cancel").size(14)).on_press(Message::Cancel)
+    } else {
+        widget::button(widget::text("Start audit").size(14))
+            .on_press_maybe(state.max_age_days().map(|_| Message::Start))
+    };
+    let options = widget::row![
+        widget::text("Flag passwords unchanged for").size(14),
+        widget::text_input("365", &state.max_age)
+            .on_input(Message::MaxAgeChanged)
+            .width(80),
+        widget::text("days").size(14),
+        widget::horizontal_space(),
+        action
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let breach_label = state
+        .breach_file
+        .as_ref()
+        .map(|f| format!("Check against {}", f.display()))
+        .unwrap_or_else(|| "No password hash file selected".to_string());
+    let breach_row =
+        widget::row![
+            widget::text(breach_label).size(14).width(Length::Fill),
+            widget::button(widget::text("Choose hash file...").size(14))
+                .on_press(Message::PickBreachFile),
+        ]
+        .push_maybe(state.breach_file.as_ref().map(|_| {
+            widget::button(widget::text("Clear").size(14)).on_press(Message::ClearBreachFile)
+        }))
+        .spacing(8)
+        .align_y(iced::Alignment::Center);
+
+    let body: Element<'a, Message> = if state.running.is_some() {
+        let (done, total) = state.progress;
+        widget::column![
+            widget::text(format!("Decrypting secret {} of {}...", done + 1, total)).size(14),
+            widget::progress_bar(0.0..=total.max(1) as f32, done as f32),
+        ]
+        .spacing(8)
+        .into()
+    } else if let Some(report) = &state.report {
+        view_report(state, report)
+    } else {
+        widget::text(
+            "The audit decrypts every secret to find weak and reused passwords \
+             and passwords that have not been changed for a long time. With a \
+             downloaded Have I Been Pwned password file it also finds breached \
+             passwords, without sending anything over the network.",
+        )
+        .size(14)
+        .into()
+    };
+
+    let content: Element<'a, Message> = widget::column![header, options, breach_row, body]
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
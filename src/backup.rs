@@ -0,0 +1,676 @@
+use crate::crypto::{self, CryptoError, KeyInfo};
+use crate::recipients::{self, RecipientsError};
+use crate::tree;
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Name of the manifest listing every secret and its checksum.
+const MANIFEST: &str = "manifest.json";
+/// Directory of the archive holding the decrypted secrets.
+const SECRETS_DIR: &str = "secrets/";
+const FORMAT_VERSION: u64 = 1;
+/// Upper bound of the bytes a zip archive adds per file besides its name and
+/// data: local header, central directory entry and extra fields.
+const ZIP_ENTRY_OVERHEAD: usize = 256;
+const DEFAULT_FILE_NAME: &str = "defira-backup.zip.gpg";
+
+#[derive(Debug)]
+pub enum BackupError {
+    IoError(std::io::Error),
+    Crypto(PathBuf, CryptoError),
+    Recipients(RecipientsError),
+    Zip(zip::result::ZipError),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::IoError(e) => write!(f, "I/O error: {}", e),
+            BackupError::Crypto(path, e) => write!(f, "{}: {}", path.display(), e),
+            BackupError::Recipients(e) => write!(f, "{}", e),
+            BackupError::Zip(e) => write!(f, "Invalid archive: {}", e),
+            BackupError::Json(e) => write!(f, "Invalid manifest: {}", e),
+            BackupError::Invalid(what) => write!(f, "Corrupted backup: {}", what),
+        }
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        BackupError::IoError(e)
+    }
+}
+
+impl From<RecipientsError> for BackupError {
+    fn from(e: RecipientsError) -> Self {
+        BackupError::Recipients(e)
+    }
+}
+
+impl From<zip::result::ZipError> for BackupError {
+    fn from(e: zip::result::ZipError) -> Self {
+        BackupError::Zip(e)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> Self {
+        BackupError::Json(e)
+    }
+}
+
+fn invalid(what: impl Into<String>) -> BackupError {
+    BackupError::Invalid(what.into())
+}
+
+/// How a backup archive is encrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Protection {
+    Passphrase,
+    Key(KeyInfo),
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protection::Passphrase => write!(f, "Passphrase"),
+            Protection::Key(key) => write!(f, "{}", key),
+        }
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Path of `path` relative to `root` with forward slashes, as stored in the archive.
+fn archive_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Write decrypted secrets and their manifest into a zip archive.
+///
+/// The secrets are stored uncompressed, so that the size of the archive is
+/// known up front and its buffer never reallocates, which would leave copies
+/// of the secrets behind. The backup is compressed when it is encrypted.
+fn write_archive(secrets: &Contents) -> Result<Zeroizing<Vec<u8>>, BackupError> {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let listed: Vec<Value> = secrets
+        .iter()
+        .map(|(path, plaintext)| json!({ "path": path, "sha256": sha256_hex(plaintext) }))
+        .collect();
+    let manifest = json!({
+        "version": FORMAT_VERSION,
+        "created": created,
+        "secrets": listed,
+    });
+    let manifest = serde_json::to_string_pretty(&manifest)?;
+
+    let size = secrets
+        .iter()
+        .map(|(path, plaintext)| plaintext.len() + 2 * (SECRETS_DIR.len() + path.len()))
+        .sum::<usize>()
+        + manifest.len()
+        + 2 * MANIFEST.len()
+        + (secrets.len() + 2) * ZIP_ENTRY_OVERHEAD;
+    let mut archive = Zeroizing::new(Vec::with_capacity(size));
+    let capacity = archive.capacity();
+
+    let mut writer = zip::ZipWriter::new(Cursor::new(&mut *archive));
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (path, plaintext) in secrets {
+        writer.start_file(format!("{}{}", SECRETS_DIR, path), options)?;
+        writer.write_all(plaintext)?;
+    }
+    writer.start_file(MANIFEST, options)?;
+    writer.write_all(manifest.as_bytes())?;
+    writer.finish()?;
+
+    if archive.capacity() != capacity {
+        warn!(
+            "Backup archive grew beyond its expected size of {} bytes",
+            size
+        );
+    }
+    Ok(archive)
+}
+
+/// Decrypt every secret of the store into a single archive, encrypted with `protection`.
+///
+/// Returns the number of secrets in the backup.
+pub fn export(
+    root: &Path,
+    destination: &Path,
+    protection: &Protection,
+    passphrase: &str,
+) -> Result<usize, BackupError> {
+    let files = tree::secret_files(root)?;
+    debug!(
+        "Backing up {} secrets to '{}'",
+        files.len(),
+        destination.display()
+    );
+
+    let mut secrets = Contents::with_capacity(files.len());
+    for file in &files {
+        let plaintext = crypto::backend(file)
+            .decrypt(&fs::read(file)?)
+            .map_err(|e| BackupError::Crypto(file.clone(), e))?;
+        secrets.push((archive_path(root, file), plaintext));
+    }
+    let archive = write_archive(&secrets)?;
+    drop(secrets);
+
+    let ciphertext = match protection {
+        Protection::Passphrase => crypto::encrypt_symmetric(&archive, passphrase),
+        Protection::Key(key) => crypto::encrypt(&archive, std::slice::from_ref(&key.fingerprint)),
+    }
+    .map_err(|e| BackupError::Crypto(destination.to_path_buf(), e))?;
+    fs::write(destination, ciphertext)?;
+    Ok(files.len())
+}
+
+/// Whether an archive path is a plain relative path to a secret.
+fn is_safe_path(path: &str) -> bool {
+    let path = Path::new(path);
+    crypto::backend_of(path).is_some()
+        && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Decrypted secrets of a backup, by their path relative to the store root.
+type Contents = Vec<(String, Zeroizing<Vec<u8>>)>;
+
+/// Read the secrets of a backup and check them against the manifest.
+fn read_archive(archive: &[u8]) -> Result<Contents, BackupError> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive))?;
+
+    let mut manifest = String::new();
+    zip.by_name(MANIFEST)?.read_to_string(&mut manifest)?;
+    let manifest: Value = serde_json::from_str(&manifest)?;
+    let version = manifest.get("version").and_then(Value::as_u64);
+    if version != Some(FORMAT_VERSION) {
+        return Err(invalid(format!("unsupported version {:?}", version)));
+    }
+    let listed = manifest
+        .get("secrets")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid("no secrets in manifest"))?;
+
+    let mut secrets = Vec::new();
+    let mut seen = HashSet::new();
+    for entry in listed {
+        let path = entry
+            .get("path")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let checksum = entry
+            .get("sha256")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !is_safe_path(path) || !seen.insert(path.to_string()) {
+            return Err(invalid(format!("invalid path '{}'", path)));
+        }
+
+        let mut plaintext = Zeroizing::new(Vec::new());
+        zip.by_name(&format!("{}{}", SECRETS_DIR, path))?
+            .read_to_end(&mut plaintext)?;
+        if sha256_hex(&plaintext) != checksum {
+            return Err(invalid(format!("checksum mismatch for '{}'", path)));
+        }
+        secrets.push((path.to_string(), plaintext));
+    }
+
+    let unlisted = zip
+        .file_names()
+        .filter_map(|name| name.strip_prefix(SECRETS_DIR))
+        .find(|name| !seen.contains(*name));
+    if let Some(name) = unlisted {
+        return Err(invalid(format!("'{}' is not in the manifest", name)));
+    }
+    Ok(secrets)
+}
+
+/// Restore a backup into the store, encrypting every secret to the current
+/// recipients of its directory. Existing secrets with the same path are replaced.
+///
+/// The whole backup is verified before anything is written. Returns the
+/// number of restored secrets.
+pub fn restore(root: &Path, source: &Path, passphrase: Option<&str>) -> Result<usize, BackupError> {
+    let archive = crypto::decrypt_bytes(&fs::read(source)?, passphrase)
+        .map_err(|e| BackupError::Crypto(source.to_path_buf(), e))?;
+    let secrets = read_archive(&archive)?;
+    debug!(
+        "Restoring {} secrets from '{}'",
+        secrets.len(),
+        source.display()
+    );
+
+    // Encrypt everything before writing anything, so that a secret that
+    // cannot be encrypted leaves the store as it was
+    let mut encrypted = Vec::with_capacity(secrets.len());
+    for (relative, plaintext) in &secrets {
+        let path = root.join(relative);
+        let recipients = recipients::resolve(&path, root)?;
+        let ciphertext = crypto::backend(&path)
+            .encrypt(plaintext, &recipients)
+            .map_err(|e| BackupError::Crypto(path.clone(), e))?;
+        encrypted.push((path, ciphertext));
+    }
+    drop(secrets);
+
+    for (path, ciphertext) in &encrypted {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, ciphertext)?;
+    }
+    Ok(encrypted.len())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Export,
+    Restore,
+}
+
+/// State of the backup panel.
+pub struct State {
+    pub mode: Mode,
+    /// Passphrase and the public keys a backup can be encrypted to.
+    options: Vec<Protection>,
+    /// Whether the public keys are being listed in the background.
+    listing_keys: bool,
+    protection: Protection,
+    passphrase: Zeroizing<String>,
+    confirmation: Zeroizing<String>,
+    /// Backup to restore.
+    file: Option<PathBuf>,
+    /// Whether a backup is being written or restored in the background.
+    busy: bool,
+}
+
+/// Messages for backup panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    SelectMode(Mode),
+    ProtectionSelected(Protection),
+    PassphraseChanged(String),
+    ConfirmationChanged(String),
+    PickFile,
+    /// The public keys a backup can be encrypted to were listed.
+    KeysListed(Result<Vec<KeyInfo>, String>),
+    /// Write a backup of the whole store.
+    Export,
+    /// Restore the selected backup.
+    Restore,
+    /// The backup was written, with its destination and number of secrets.
+    Exported(Result<(PathBuf, usize), String>),
+    /// The backup was restored, with the number of secrets.
+    Restored(Result<usize, String>),
+    Close,
+}
+
+impl State {
+    /// Start with passphrase protection, listing the keys in the background.
+    pub fn new() -> (Self, Task<Message>) {
+        let state = Self {
+            mode: Mode::Export,
+            options: vec![Protection::Passphrase],
+            listing_keys: true,
+            protection: Protection::Passphrase,
+            passphrase: Zeroizing::new(String::new()),
+            confirmation: Zeroizing::new(String::new()),
+            file: None,
+            busy: false,
+        };
+        let task = Task::perform(
+            async { crypto::encryption_keys().map_err(|e| e.to_string()) },
+            Message::KeysListed,
+        );
+        (state, task)
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::SelectMode(mode) => {
+                self.mode = mode;
+                self.passphrase = Zeroizing::new(String::new());
+                self.confirmation = Zeroizing::new(String::new());
+            }
+            Message::ProtectionSelected(protection) => self.protection = protection,
+            Message::PassphraseChanged(passphrase) => self.passphrase = Zeroizing::new(passphrase),
+            Message::ConfirmationChanged(confirmation) => {
+                self.confirmation = Zeroizing::new(confirmation)
+            }
+            Message::PickFile => {
+                if let Some(file) = rfd::FileDialog::new()
+                    .add_filter("defira backup", &["gpg"])
+                    .pick_file()
+                {
+                    self.file = Some(file);
+                }
+            }
+            // A panel opened again may get the keys of the previous listing
+            Message::KeysListed(_) if !self.listing_keys => {}
+            Message::KeysListed(result) => {
+                self.listing_keys = false;
+                match result {
+                    Ok(keys) => self.options.extend(keys.into_iter().map(Protection::Key)),
+                    Err(e) => log::error!("Failed to list keys: {}", e),
+                }
+            }
+            Message::Exported(_) | Message::Restored(_) => self.busy = false,
+            Message::Export | Message::Restore | Message::Close => {}
+        }
+    }
+
+    fn can_export(&self) -> bool {
+        if self.busy {
+            return false;
+        }
+        match self.protection {
+            Protection::Passphrase => {
+                !self.passphrase.is_empty() && self.passphrase == self.confirmation
+            }
+            Protection::Key(_) => true,
+        }
+    }
+
+    /// Ask where to save the backup and write it in the background.
+    pub fn export(&mut self, root: &Path) -> Task<Message> {
+        if !self.can_export() {
+            return Task::none();
+        }
+        let Some(destination) = rfd::FileDialog::new()
+            .set_file_name(DEFAULT_FILE_NAME)
+            .save_file()
+        else {
+            return Task::none();
+        };
+        if destination.starts_with(root) {
+            let e = invalid("the backup must be saved outside of the store");
+            return Task::done(Message::Exported(Err(e.to_string())));
+        }
+
+        self.busy = true;
+        let root = root.to_path_buf();
+        let protection = self.protection.clone();
+        let passphrase = self.passphrase.clone();
+        Task::perform(
+            async move {
+                export(&root, &destination, &protection, &passphrase)
+                    .map(|count| (destination, count))
+                    .map_err(|e| e.to_string())
+            },
+            Message::Exported,
+        )
+    }
+
+    /// Restore the selected backup into the store in the background.
+    pub fn restore(&mut self, root: &Path) -> Task<Message> {
+        let Some(file) = self.file.clone() else {
+            return Task::none();
+        };
+        if self.busy {
+            return Task::none();
+        }
+        self.busy = true;
+        let root = root.to_path_buf();
+        let passphrase = (!self.passphrase.is_empty()).then(|| self.passphrase.clone());
+        Task::perform(
+            async move {
+                restore(&root, &file, passphrase.as_deref().map(String::as_str))
+                    .map_err(|e| e.to_string())
+            },
+            Message::Restored,
+        )
+    }
+}
+
+fn view_export(state: &State) -> Element<'_, Message> {
+    let info = widget::text(
+        "Decrypts every secret of the store into a single archive encrypted with a \
+         passphrase or a backup key. Keep it outside of the store.",
+    )
+    .size(14);
+    let protection_row = widget::row![
+        widget::text("Encrypt with").size(14),
+        widget::pick_list(
+            state.options.as_slice(),
+            Some(state.protection.clone()),
+            Message::ProtectionSelected
+        ),
+    ]
+    .push_maybe(
+        state
+            .listing_keys
+            .then(|| widget::text("Listing keys...").size(14)),
+    )
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let mut column = widget::column![info, protection_row].spacing(10);
+    if state.protection == Protection::Passphrase {
+        column = column
+            .push(
+                widget::text_input("Passphrase", &state.passphrase)
+                    .secure(true)
+                    .on_input(Message::PassphraseChanged),
+            )
+            .push(
+                widget::text_input("Repeat passphrase", &state.confirmation)
+                    .secure(true)
+                    .on_input(Message::ConfirmationChanged),
+            );
+    }
+    let export_button = widget::button(widget::text("Export backup...").size(14))
+        .on_press_maybe(state.can_export().then_some(Message::Export));
+    column
+        .push(widget::row![widget::horizontal_space(), export_button])
+        .into()
+}
+
+fn view_restore(state: &State) -> Element<'_, Message> {
+    let info = widget::text(
+        "Restores every secret of a backup, encrypted to the current recipients. \
+         Existing secrets with the same name are replaced.",
+    )
+    .size(14);
+    let file_label = state
+        .file
+        .as_ref()
+        .map(|f| f.display().to_string())
+        .unwrap_or_else(|| "No backup selected".to_string());
+    let file_row = widget::row![
+        widget::text(file_label).size(14).width(Length::Fill),
+        widget::button(widget::text("Choose backup...").size(14)).on_press(Message::PickFile),
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let passphrase = widget::text_input("Passphrase (if protected by one)", &state.passphrase)
+        .secure(true)
+        .on_input(Message::PassphraseChanged);
+    let restore_button = widget::button(widget::text("Restore").size(14))
+        .on_press_maybe((!state.busy && state.file.is_some()).then_some(Message::Restore));
+
+    widget::column![
+        info,
+        file_row,
+        passphrase,
+        widget::row![widget::horizontal_space(), restore_button]
+    ]
+    .spacing(10)
+    .into()
+}
+
+/// Renders the backup panel with its export and restore modes.
+///
+/// # Arguments
+/// * `state` - The current backup panel state
+/// * `on_message` - Function to wrap backup messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let mode_button = |label, mode| {
+        widget::button(widget::text(label).size(14))
+            .on_press_maybe((state.mode != mode).then_some(Message::SelectMode(mode)))
+    };
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let header = widget::row![
+        widget::text("Backup").size(16),
+        mode_button("Export", Mode::Export),
+        mode_button("Restore", Mode::Restore),
+        widget::horizontal_space(),
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let body = match state.mode {
+        Mode::Export => view_export(state),
+        Mode::Restore => view_restore(state),
+    };
+
+    let content: Element<'a, Message> = widget::column![header, body]
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret(path: &str, text: &str) -> (String, Zeroizing<Vec<u8>>) {
+        (path.to_string(), Zeroizing::new(text.as_bytes().to_vec()))
+    }
+
+    /// An archive holding `files`, with a manifest listing the paths and
+    /// checksums of `listed`.
+    fn archive(listed: &[(&str, &str)], files: &[(&str, &str)]) -> Vec<u8> {
+        let secrets: Vec<Value> = listed
+            .iter()
+            .map(|(path, text)| json!({ "path": path, "sha256": sha256_hex(text.as_bytes()) }))
+            .collect();
+        let manifest = json!({ "version": FORMAT_VERSION, "secrets": secrets });
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (path, text) in files {
+            writer
+                .start_file(format!("{}{}", SECRETS_DIR, path), options)
+                .unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+        writer.start_file(MANIFEST, options).unwrap();
+        writer.write_all(manifest.to_string().as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn error(archive: &[u8]) -> String {
+        match read_archive(archive) {
+            Ok(_) => panic!("archive was accepted"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn safe_paths() {
+        assert!(is_safe_path("mail.gpg"));
+        assert!(is_safe_path("work/servers/db.age"));
+        assert!(!is_safe_path("../evil.gpg"));
+        assert!(!is_safe_path("work/../../evil.gpg"));
+        assert!(!is_safe_path("/etc/evil.gpg"));
+        assert!(!is_safe_path("./mail.gpg"));
+        assert!(!is_safe_path("notes.txt"));
+        assert!(!is_safe_path(""));
+    }
+
+    #[test]
+    fn round_trip() {
+        let secrets = vec![
+            secret("mail.gpg", "hunter2\nusername: alice\n"),
+            secret("work/vpn.age", "vpn-secret\n"),
+            secret("empty.gpg", ""),
+        ];
+        let archive = write_archive(&secrets).unwrap();
+        let read = read_archive(&archive).unwrap();
+        assert_eq!(read.len(), 3);
+        for ((path, text), (read_path, read_text)) in secrets.iter().zip(&read) {
+            assert_eq!(path, read_path);
+            assert_eq!(text, read_text);
+        }
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_store() {
+        let traversal = archive(&[("../evil.gpg", "x")], &[("../evil.gpg", "x")]);
+        assert!(error(&traversal).contains("invalid path '../evil.gpg'"));
+        let absolute = archive(&[("/tmp/evil.gpg", "x")], &[("/tmp/evil.gpg", "x")]);
+        assert!(error(&absolute).contains("invalid path '/tmp/evil.gpg'"));
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let tampered = archive(&[("mail.gpg", "hunter2")], &[("mail.gpg", "changed")]);
+        assert!(error(&tampered).contains("checksum mismatch for 'mail.gpg'"));
+    }
+
+    #[test]
+    fn rejects_unlisted_and_duplicate_secrets() {
+        let unlisted = archive(
+            &[("mail.gpg", "a")],
+            &[("mail.gpg", "a"), ("extra.gpg", "b")],
+        );
+        assert!(error(&unlisted).contains("'extra.gpg' is not in the manifest"));
+        let duplicate = archive(
+            &[("mail.gpg", "a"), ("mail.gpg", "a")],
+            &[("mail.gpg", "a")],
+        );
+        assert!(error(&duplicate).contains("invalid path 'mail.gpg'"));
+        let missing = archive(&[("mail.gpg", "a")], &[]);
+        assert!(read_archive(&missing).is_err());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(MANIFEST, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .write_all(br#"{ "version": 2, "secrets": [] }"#)
+            .unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+        assert!(error(&archive).contains("unsupported version Some(2)"));
+    }
+}
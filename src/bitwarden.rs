@@ -0,0 +1,242 @@
+use crate::import::{Import, ImportError, ImportedEntry};
+use crate::secret::{self, Secret};
+use log::{debug, warn};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const TYPE_LOGIN: u64 = 1;
+const TYPE_CARD: u64 = 3;
+const TYPE_IDENTITY: u64 = 4;
+const TYPE_SSH_KEY: u64 = 5;
+/// Custom field type referring to another field of the item.
+const FIELD_LINKED: u64 = 3;
+
+fn string<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Map `id` to name for the folders or collections of the export.
+fn names(export: &Value, key: &str) -> HashMap<String, String> {
+    array(export, key)
+        .iter()
+        .map(|folder| {
+            (
+                string(folder, "id").to_string(),
+                string(folder, "name").to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Add every string of an object as a field, e.g. the details of a card or identity.
+fn push_object(secret: &mut Secret, object: Option<&Value>) {
+    let Some(object) = object.and_then(Value::as_object) else {
+        return;
+    };
+    for (key, value) in object {
+        if let Some(value) = value.as_str() {
+            secret.push_field(key.as_str(), value);
+        }
+    }
+}
+
+fn read_item(item: &Value, folder: Vec<String>, import: &mut Import) {
+    let name = string(item, "name").to_string();
+    let mut secret = Secret::new("");
+    let item_type = item.get("type").and_then(Value::as_u64).unwrap_or_default();
+
+    match item_type {
+        TYPE_LOGIN => {
+            let login = item.get("login").unwrap_or(&Value::Null);
+            secret.password = string(login, "password").to_string();
+            secret.push_field("username", string(login, "username"));
+            for uri in array(login, "uris") {
+                secret.push_field("url", string(uri, "uri"));
+            }
+
+            let totp = string(login, "totp");
+            if totp.starts_with("steam://") {
+                // Steam codes are not standard TOTP, keep them as a field
+                secret.push_field("totp", totp);
+            } else if !totp.is_empty() {
+                secret.otp = Some(secret::otp_uri(&name, totp));
+            }
+
+            if !array(login, "fido2Credentials").is_empty() {
+                import.skipped.push(format!("{}: passkey", name));
+            }
+        }
+        TYPE_CARD => push_object(&mut secret, item.get("card")),
+        TYPE_IDENTITY => push_object(&mut secret, item.get("identity")),
+        TYPE_SSH_KEY => push_object(&mut secret, item.get("sshKey")),
+        _ => {}
+    }
+
+    for field in array(item, "fields") {
+        if field.get("type").and_then(Value::as_u64) == Some(FIELD_LINKED) {
+            continue;
+        }
+        secret.push_field(string(field, "name"), string(field, "value"));
+    }
+    secret.push_field("notes", string(item, "notes"));
+
+    let attachments = array(item, "attachments").len();
+    if attachments > 0 {
+        warn!("Skipping {} attachments of '{}'", attachments, name);
+        import
+            .skipped
+            .push(format!("{}: {} attachments", name, attachments));
+    }
+
+    import.entries.push(ImportedEntry {
+        folder,
+        name,
+        secret,
+    });
+}
+
+/// Read an unencrypted Bitwarden JSON export.
+///
+/// Folders become directories, nested folders (`Work/Servers`) nested
+/// directories. Items of an organization export without a folder are placed
+/// in a directory named after their first collection.
+pub fn read(path: &Path) -> Result<Import, ImportError> {
+    let data = Zeroizing::new(fs::read_to_string(path)?);
+    let export: Value = serde_json::from_str(&data)?;
+
+    if export.get("encrypted").and_then(Value::as_bool) == Some(true) {
+        return Err(ImportError::Unsupported(
+            "encrypted Bitwarden exports; export as unencrypted JSON".to_string(),
+        ));
+    }
+    let Some(items) = export.get("items").and_then(Value::as_array) else {
+        return Err(ImportError::Invalid(
+            "no items in Bitwarden export".to_string(),
+        ));
+    };
+
+    let folders = names(&export, "folders");
+    let collections = names(&export, "collections");
+    debug!(
+        "Bitwarden export with {} items in {} folders",
+        items.len(),
+        folders.len()
+    );
+
+    let mut import = Import::default();
+    for item in items {
+        let folder = folders
+            .get(string(item, "folderId"))
+            .or_else(|| {
+                array(item, "collectionIds")
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|id| collections.get(id))
+            })
+            .map(|name| {
+                name.split('/')
+                    .filter(|part| !part.trim().is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        read_item(item, folder, &mut import);
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_export(name: &str, export: &str) -> Import {
+        let path = std::env::temp_dir().join(format!(
+            "defira-bitwarden-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, export).unwrap();
+        let import = read(&path);
+        fs::remove_file(&path).unwrap();
+        import.unwrap()
+    }
+
+    #[test]
+    fn reads_login_with_totp_and_attachment() {
+        let import = read_export(
+            "login",
+            r#"{
+                "encrypted": false,
+                "folders": [{ "id": "f1", "name": "Work/Servers" }],
+                "items": [{
+                    "type": 1,
+                    "name": "Mail",
+                    "folderId": "f1",
+                    "notes": "first\nsecond",
+                    "login": {
+                        "username": "alice",
+                        "password": "hunter2",
+                        "uris": [{ "uri": "https://mail.example.com" }],
+                        "totp": "JBSW Y3DP"
+                    },
+                    "fields": [
+                        { "name": "PIN", "value": "1234", "type": 1 },
+                        { "name": "Linked", "value": null, "type": 3 }
+                    ],
+                    "attachments": [{ "fileName": "codes.txt" }]
+                }]
+            }"#,
+        );
+
+        assert_eq!(import.entries.len(), 1);
+        let entry = &import.entries[0];
+        assert_eq!(entry.folder, ["Work", "Servers"]);
+        assert_eq!(entry.name, "Mail");
+        assert_eq!(
+            entry.secret.to_text(),
+            "hunter2\nusername: alice\nurl: https://mail.example.com\nPIN: 1234\n\
+             notes: first\n  second\notpauth://totp/Mail?secret=JBSWY3DP\n"
+        );
+        assert_eq!(import.skipped, ["Mail: 1 attachments"]);
+    }
+
+    #[test]
+    fn keeps_steam_codes_as_field() {
+        let import = read_export(
+            "steam",
+            r#"{ "items": [{
+                "type": 1,
+                "name": "Steam",
+                "login": { "password": "pw", "totp": "steam://ABC" }
+            }] }"#,
+        );
+        let secret = &import.entries[0].secret;
+        assert!(secret.otp.is_none());
+        assert_eq!(secret.to_text(), "pw\ntotp: steam://ABC\n");
+        assert!(import.skipped.is_empty());
+    }
+
+    #[test]
+    fn places_items_in_their_collection() {
+        let import = read_export(
+            "collection",
+            r#"{
+                "collections": [{ "id": "c1", "name": "Shared" }],
+                "items": [{ "type": 2, "name": "Note", "notes": "text", "collectionIds": ["c1"] }]
+            }"#,
+        );
+        assert_eq!(import.entries[0].folder, ["Shared"]);
+        assert_eq!(import.entries[0].secret.to_text(), "\nnotes: text\n");
+    }
+}
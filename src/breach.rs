@@ -0,0 +1,166 @@
+//! Offline lookup of passwords in a Have I Been Pwned password file.
+//!
+//! Two formats are supported, both sorted by hash so that a password can be
+//! found with a binary search without loading the file into memory:
+//!
+//! * The text file from the HIBP downloader, one `SHA1:COUNT` line per hash
+//!   in upper or lower case hex.
+//! * A binary index of raw 20 byte SHA-1 hashes without separators.
+
+use log::debug;
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const HASH_LEN: usize = 20;
+const HEX_HASH_LEN: usize = 2 * HASH_LEN;
+
+#[derive(Debug)]
+pub enum BreachError {
+    IoError(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for BreachError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreachError::IoError(e) => write!(f, "I/O error: {}", e),
+            BreachError::Invalid(reason) => write!(f, "Not a password hash file: {}", reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for BreachError {
+    fn from(e: std::io::Error) -> Self {
+        BreachError::IoError(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Text,
+    Binary,
+}
+
+/// An open password hash file.
+pub struct Index {
+    reader: BufReader<fs::File>,
+    len: u64,
+    format: Format,
+}
+
+impl Index {
+    /// Open a hash file, detecting its format from the first line.
+    pub fn open(path: &Path) -> Result<Self, BreachError> {
+        let file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut first = Vec::new();
+        reader
+            .by_ref()
+            .take(HEX_HASH_LEN as u64 + 1)
+            .read_to_end(&mut first)?;
+        let is_text = first.len() > HEX_HASH_LEN
+            && first[..HEX_HASH_LEN].iter().all(u8::is_ascii_hexdigit)
+            && matches!(first[HEX_HASH_LEN], b':' | b'\n' | b'\r');
+        let format = if is_text {
+            Format::Text
+        } else if len > 0 && len % HASH_LEN as u64 == 0 {
+            Format::Binary
+        } else {
+            return Err(BreachError::Invalid(
+                "expected SHA-1 hashes as text or a binary index".to_string(),
+            ));
+        };
+
+        debug!(
+            "Opened {:?} password hash file '{}' of {} bytes",
+            format,
+            path.display(),
+            len
+        );
+        Ok(Self {
+            reader,
+            len,
+            format,
+        })
+    }
+
+    /// Whether `password` occurs in the file.
+    pub fn contains(&mut self, password: &str) -> Result<bool, BreachError> {
+        let hash: [u8; HASH_LEN] = Sha1::digest(password.as_bytes()).into();
+        match self.format {
+            Format::Text => self.contains_text(&hash),
+            Format::Binary => self.contains_binary(&hash),
+        }
+    }
+
+    fn contains_binary(&mut self, hash: &[u8; HASH_LEN]) -> Result<bool, BreachError> {
+        let (mut low, mut high) = (0, self.len / HASH_LEN as u64);
+        let mut record = [0u8; HASH_LEN];
+        while low < high {
+            let mid = low + (high - low) / 2;
+            self.reader.seek(SeekFrom::Start(mid * HASH_LEN as u64))?;
+            self.reader.read_exact(&mut record)?;
+            match record.cmp(hash) {
+                std::cmp::Ordering::Equal => return Ok(true),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        Ok(false)
+    }
+
+    /// The first line starting at or after `position`, with its start offset
+    /// and the offset after its newline.
+    fn line_at(&mut self, position: u64) -> Result<Option<(u64, u64, String)>, BreachError> {
+        let start = if position > 0 {
+            // Skip the rest of the line `position` is in
+            self.reader.seek(SeekFrom::Start(position - 1))?;
+            let mut skipped = Vec::new();
+            position - 1 + self.reader.read_until(b'\n', &mut skipped)? as u64
+        } else {
+            self.reader.seek(SeekFrom::Start(0))?;
+            0
+        };
+
+        let mut line = String::new();
+        let read = self.reader.read_line(&mut line)? as u64;
+        if read == 0 {
+            return Ok(None);
+        }
+        Ok(Some((start, start + read, line)))
+    }
+
+    fn contains_text(&mut self, hash: &[u8; HASH_LEN]) -> Result<bool, BreachError> {
+        let target: String = hash.iter().map(|b| format!("{:02X}", b)).collect();
+
+        // Lines starting before `low` are smaller than the target, lines
+        // starting at or after `high` are greater
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let Some((start, end, line)) = self.line_at(mid)? else {
+                high = mid;
+                continue;
+            };
+            if start >= high {
+                high = mid;
+                continue;
+            }
+            let line_hash = line
+                .get(..HEX_HASH_LEN)
+                .unwrap_or(line.trim_end())
+                .to_ascii_uppercase();
+            match line_hash.cmp(&target) {
+                std::cmp::Ordering::Equal => return Ok(true),
+                std::cmp::Ordering::Less => low = end,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,124 @@
+use iced::widget;
+use iced::{Element, Length};
+
+/// State for a confirmation modal with a confirm, an optional alternate and a cancel button.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The dialog title.
+    pub title: String,
+    /// The question asked to the user.
+    pub message: String,
+    /// Label of the primary button (e.g. "Save").
+    pub confirm_label: String,
+    /// Label of the optional secondary button (e.g. "Discard").
+    pub alternate_label: Option<String>,
+}
+
+impl State {
+    /// Create a new confirmation popup with a confirm and a cancel button.
+    pub fn new(
+        title: impl Into<String>,
+        message: impl Into<String>,
+        confirm_label: impl Into<String>,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            message: message.into(),
+            confirm_label: confirm_label.into(),
+            alternate_label: None,
+        }
+    }
+
+    /// Add a secondary button between confirm and cancel.
+    pub fn with_alternate(mut self, label: impl Into<String>) -> Self {
+        self.alternate_label = Some(label.into());
+        self
+    }
+}
+
+/// Messages for confirmation popup interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// User clicked the primary button.
+    Confirm,
+    /// User clicked the secondary button.
+    Alternate,
+    /// User clicked Cancel or the backdrop.
+    Cancel,
+}
+
+fn dialog_button<'a, M>(label: &str, message: M) -> widget::Button<'a, M>
+where
+    M: Clone + 'a,
+{
+    widget::button(
+        widget::text(label.to_string())
+            .align_x(iced::alignment::Horizontal::Center)
+            .width(Length::Fill),
+    )
+    .on_press(message)
+    .padding(10)
+    .width(100)
+}
+
+/// Renders the confirmation popup modal.
+///
+/// # Arguments
+/// * `state` - The current confirmation popup state
+/// * `on_message` - Function to wrap confirmation popup messages into the parent message type
+///
+/// # Type Parameters
+/// * `M` - The parent message type (e.g., FileAction)
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let title = widget::text(&state.title)
+        .size(20)
+        .width(Length::Fill)
+        .align_x(iced::alignment::Horizontal::Center);
+
+    let message = widget::text(&state.message)
+        .width(Length::Fill)
+        .align_x(iced::alignment::Horizontal::Center);
+
+    let mut button_row = widget::row![
+        widget::horizontal_space(),
+        dialog_button(&state.confirm_label, on_message(Message::Confirm))
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+    if let Some(label) = &state.alternate_label {
+        button_row = button_row.push(dialog_button(label, on_message(Message::Alternate)));
+    }
+    let button_row = button_row
+        .push(dialog_button("Cancel", on_message(Message::Cancel)))
+        .push(widget::horizontal_space());
+
+    let content = widget::column![title, message, button_row]
+        .spacing(16)
+        .padding(24)
+        .width(400);
+
+    let modal = widget::container(content)
+        .style(|theme: &iced::Theme| widget::container::Style {
+            background: Some(iced::Background::Color(iced::Color::WHITE)),
+            text_color: Some(theme.palette().text),
+            border: iced::Border {
+                color: iced::Color::from_rgb(0.7, 0.7, 0.7),
+                width: 1.0,
+                radius: 8.0.into(),
+            },
+            shadow: iced::Shadow {
+                color: iced::Color::from_rgba(0.0, 0.0, 0.0, 0.3),
+                offset: iced::Vector::new(0.0, 4.0),
+                blur_radius: 16.0,
+            },
+        })
+        .width(Length::Shrink);
+
+    widget::container(modal)
+        .center_x(Length::Fill)
+        .center_y(Length::Fill)
+        .into()
+}
@@ -0,0 +1,225 @@
+use crate::crypto;
+use crate::editor;
+use crate::git;
+use crate::merge;
+use iced::widget;
+use iced::widget::text_editor;
+use iced::{Element, Font, Length};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// State of the three-way merge view for one conflicting file.
+pub struct State {
+    /// The conflicting file.
+    pub path: PathBuf,
+    base: Zeroizing<String>,
+    ours: Zeroizing<String>,
+    theirs: Zeroizing<String>,
+    /// The resolution being edited, prefilled with an automatic merge.
+    pub merged: text_editor::Content,
+    /// Number of conflicting files left after this one.
+    remaining: usize,
+    /// Whether the passwords of a secret are shown, see [`editor::mask`].
+    revealed: bool,
+    /// Whether the merge is being aborted in the background.
+    aborting: bool,
+}
+
+/// Base, ours and theirs of a conflicting file, decrypted if it is a secret.
+#[derive(Debug, Clone)]
+pub struct Sides {
+    base: Zeroizing<String>,
+    ours: Zeroizing<String>,
+    theirs: Zeroizing<String>,
+}
+
+/// Messages for merge view interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Replace the resolution with our version.
+    UseOurs,
+    /// Replace the resolution with their version.
+    UseTheirs,
+    /// Show or hide the passwords of a secret.
+    ToggleReveal,
+    /// Edit the resolution.
+    EditorAction(text_editor::Action),
+    /// Write the resolution and mark the file as resolved.
+    Save,
+    /// Abort the merge.
+    Abort,
+}
+
+/// Read one side of the conflict, decrypting it if the file is a secret.
+fn read_side(root: &Path, path: &Path, stage: git::Stage) -> Result<Zeroizing<String>, String> {
+    let data = git::show_stage(root, stage, path).map_err(|e| e.to_string())?;
+    if data.is_empty() {
+        return Ok(Zeroizing::new(String::new()));
+    }
+    if editor::is_secret(path) {
+        crypto::backend(path)
+            .decrypt_text(&data)
+            .map(Zeroizing::new)
+            .map_err(|e| e.to_string())
+    } else {
+        Ok(Zeroizing::new(String::from_utf8_lossy(&data).to_string()))
+    }
+}
+
+/// Read base, ours and theirs of a conflicting file.
+///
+/// Runs in the background, as decryption may wait for a passphrase.
+pub fn read_sides(root: &Path, path: &Path) -> Result<Sides, String> {
+    Ok(Sides {
+        base: read_side(root, path, git::Stage::Base)?,
+        ours: read_side(root, path, git::Stage::Ours)?,
+        theirs: read_side(root, path, git::Stage::Theirs)?,
+    })
+}
+
+impl State {
+    /// Show the sides of a conflicting file and try to merge them.
+    pub fn new(path: PathBuf, sides: Sides, remaining: usize) -> Self {
+        let merged = merge::merge3(&sides.base, &sides.ours, &sides.theirs);
+        Self {
+            path,
+            base: sides.base,
+            ours: sides.ours,
+            theirs: sides.theirs,
+            merged: text_editor::Content::with_text(&merged.text),
+            remaining,
+            revealed: false,
+            aborting: false,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::UseOurs => self.merged = text_editor::Content::with_text(&self.ours),
+            Message::UseTheirs => self.merged = text_editor::Content::with_text(&self.theirs),
+            Message::ToggleReveal => self.revealed = !self.revealed,
+            Message::EditorAction(action) => self.merged.perform(action),
+            Message::Abort => self.aborting = true,
+            Message::Save => {}
+        }
+    }
+
+    /// Whether the passwords are masked, as in the editor.
+    fn masked(&self) -> bool {
+        editor::is_secret(&self.path) && !self.revealed
+    }
+
+    /// A side as shown, with the password masked unless revealed.
+    fn shown<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.masked() {
+            Cow::Owned(editor::mask(text))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+fn view_side<'a>(label: &'a str, text: Cow<'a, str>) -> Element<'a, Message> {
+    let content = widget::scrollable(
+        widget::text(text)
+            .font(Font::MONOSPACE)
+            .size(14)
+            .width(Length::Fill),
+    )
+    .height(Length::Fill);
+
+    widget::column![widget::text(label).size(14), content]
+        .spacing(4)
+        .width(Length::FillPortion(1))
+        .into()
+}
+
+/// Renders the merge view: base, ours and theirs side by side above the editable resolution.
+///
+/// # Arguments
+/// * `state` - The current merge view state
+/// * `on_message` - Function to wrap merge view messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let filename = state
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let title = if state.remaining > 0 {
+        format!(
+            "Resolve conflict in {} ({} more)",
+            filename, state.remaining
+        )
+    } else {
+        format!("Resolve conflict in {}", filename)
+    };
+
+    let abort_button = widget::button(
+        widget::text(if state.aborting {
+            "Aborting..."
+        } else {
+            "Abort merge"
+        })
+        .size(14),
+    )
+    .on_press_maybe((!state.aborting).then_some(Message::Abort));
+    let save_button = widget::button(widget::text("Mark resolved").size(14))
+        .on_press_maybe((!state.aborting).then_some(Message::Save));
+    let reveal_button = editor::is_secret(&state.path).then(|| {
+        widget::button(widget::text(if state.revealed { "Hide" } else { "Reveal" }).size(14))
+            .on_press(Message::ToggleReveal)
+    });
+    let header = widget::row![widget::text(title).size(16), widget::horizontal_space()]
+        .push_maybe(reveal_button)
+        .push(abort_button)
+        .push(save_button)
+        .spacing(8)
+        .align_y(iced::Alignment::Center);
+
+    let sides = widget::row![
+        view_side("Base", state.shown(&state.base)),
+        view_side("Ours", state.shown(&state.ours)),
+        view_side("Theirs", state.shown(&state.theirs)),
+    ]
+    .spacing(10)
+    .height(Length::FillPortion(1));
+
+    let pick_row = widget::row![
+        widget::text("Resolution").size(14),
+        widget::horizontal_space(),
+        widget::button(widget::text("Use ours").size(14)).on_press(Message::UseOurs),
+        widget::button(widget::text("Use theirs").size(14)).on_press(Message::UseTheirs),
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let merged: Element<'a, Message> = if state.masked() {
+        // The masked view is read-only; editing requires revealing the secret
+        widget::scrollable(
+            widget::text(editor::mask(&state.merged.text()))
+                .font(Font::MONOSPACE)
+                .width(Length::Fill),
+        )
+        .height(Length::Fill)
+        .into()
+    } else {
+        widget::text_editor(&state.merged)
+            .on_action(Message::EditorAction)
+            .font(Font::MONOSPACE)
+            .height(Length::Fill)
+            .into()
+    };
+    let merged = widget::container(merged).height(Length::FillPortion(1));
+
+    let content: Element<'a, Message> = widget::column![header, sides, pick_row, merged]
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
@@ -1,10 +1,29 @@
-use gpgme::{Context, Protocol};
+use crate::age;
+use gpgme::{
+    Context, CreateKeyFlags, EncryptFlags, ExportMode, Key, PassphraseRequest, PinentryMode,
+    Protocol, Validity,
+};
+use log::{debug, error};
+use std::collections::HashSet;
+use std::env;
 use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Fingerprint of the key secrets are signed with when they are encrypted.
+/// Secrets are not signed if it is not set.
+pub const SECRET_SIGNING_KEY_VAR: &str = "DEFIRA_SIGNING_KEY";
 
 #[derive(Debug)]
 pub enum CryptoError {
     NoData,
+    KeyNotFound(String),
     GpgError(gpgme::Error),
+    AgeError(String),
     Utf8Error(std::string::FromUtf8Error),
 }
 
@@ -12,7 +31,11 @@ impl fmt::Display for CryptoError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CryptoError::NoData => write!(f, "No data in encrypted file"),
+            CryptoError::KeyNotFound(recipient) => {
+                write!(f, "No usable encryption key found for '{}'", recipient)
+            }
             CryptoError::GpgError(e) => write!(f, "GPG error: {}", e),
+            CryptoError::AgeError(e) => write!(f, "age error: {}", e),
             CryptoError::Utf8Error(e) => write!(f, "Invalid UTF-8 in decrypted content: {}", e),
         }
     }
@@ -30,6 +53,113 @@ impl From<std::string::FromUtf8Error> for CryptoError {
     }
 }
 
+/// An encryption tool a store can use. Each backend has its own extension
+/// for secrets and its own recipients file, so the backend of a secret
+/// follows from its name.
+pub trait CryptoBackend: Sync {
+    /// Name shown to the user.
+    fn name(&self) -> &'static str;
+    /// Extension of encrypted secrets, without the dot.
+    fn extension(&self) -> &'static str;
+    /// File listing the recipients of a directory and the directories below it.
+    fn recipients_file(&self) -> &'static str;
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptoError>;
+    /// Encrypt data to recipients as listed in the recipients file.
+    fn encrypt(&self, plaintext: &[u8], recipients: &[String]) -> Result<Vec<u8>, CryptoError>;
+    /// Keys that secrets can be encrypted to.
+    fn encryption_keys(&self) -> Result<Vec<KeyInfo>, CryptoError>;
+    /// Recipients of the keys this user can decrypt with.
+    fn own_recipients(&self) -> Result<Vec<String>, CryptoError>;
+
+    /// Whether secrets can carry a signature, checked with [`decrypt_verified`].
+    fn supports_signatures(&self) -> bool {
+        false
+    }
+
+    /// Decrypt a secret that must be text.
+    fn decrypt_text(&self, encrypted_data: &[u8]) -> Result<String, CryptoError> {
+        let plaintext = self.decrypt(encrypted_data)?;
+        String::from_utf8(plaintext.to_vec()).map_err(CryptoError::from)
+    }
+}
+
+/// OpenPGP through gpgme and the gpg-agent, compatible with pass.
+pub struct Gpg;
+
+impl CryptoBackend for Gpg {
+    fn name(&self) -> &'static str {
+        "GPG"
+    }
+
+    fn extension(&self) -> &'static str {
+        "gpg"
+    }
+
+    fn recipients_file(&self) -> &'static str {
+        crate::recipients::GPG_ID_FILE
+    }
+
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+        decrypt_bytes(encrypted_data, None)
+    }
+
+    fn decrypt_text(&self, encrypted_data: &[u8]) -> Result<String, CryptoError> {
+        decrypt(encrypted_data)
+    }
+
+    fn encrypt(&self, plaintext: &[u8], recipients: &[String]) -> Result<Vec<u8>, CryptoError> {
+        encrypt(plaintext, recipients)
+    }
+
+    fn encryption_keys(&self) -> Result<Vec<KeyInfo>, CryptoError> {
+        encryption_keys()
+    }
+
+    fn supports_signatures(&self) -> bool {
+        true
+    }
+
+    fn own_recipients(&self) -> Result<Vec<String>, CryptoError> {
+        let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+        let fingerprints = ctx
+            .secret_keys()?
+            .filter_map(Result::ok)
+            .filter(|key| key.can_encrypt() && !key.is_revoked() && !key.is_expired())
+            .filter_map(|key| key.fingerprint().ok().map(str::to_string))
+            .collect();
+        Ok(fingerprints)
+    }
+}
+
+pub static GPG: Gpg = Gpg;
+
+/// Every backend, GPG first as the default.
+pub static BACKENDS: [&dyn CryptoBackend; 2] = [&GPG, &age::AGE];
+
+/// The backend of the secret at `path`, or `None` if it is not a secret.
+pub fn backend_of(path: &Path) -> Option<&'static dyn CryptoBackend> {
+    let extension = path.extension()?;
+    BACKENDS
+        .iter()
+        .copied()
+        .find(|backend| extension == backend.extension())
+}
+
+/// The backend of the secret at `path`, GPG if it is not a secret.
+pub fn backend(path: &Path) -> &'static dyn CryptoBackend {
+    backend_of(path).unwrap_or(&GPG)
+}
+
+/// The backend new secrets of the store at `root` are encrypted with: age
+/// if the root has an age recipients file, GPG otherwise.
+pub fn store_backend(root: &Path) -> &'static dyn CryptoBackend {
+    if root.join(age::RECIPIENTS_FILE).is_file() {
+        &age::AGE
+    } else {
+        &GPG
+    }
+}
+
 /// Decrypt GPG-encrypted data using the gpg-agent's cached passphrase.
 ///
 /// This function attempts decryption without prompting for a password,
@@ -48,3 +178,367 @@ pub fn decrypt(encrypted_data: &[u8]) -> Result<String, CryptoError> {
     String::from_utf8(plaintext).map_err(CryptoError::from)
 }
 
+/// The signer of an encrypted secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signer {
+    /// Fingerprint of the primary key, or of the signing key if it is not
+    /// in the keyring.
+    pub fingerprint: String,
+    /// Primary user ID, empty if the key is not in the keyring.
+    pub user_id: String,
+    /// Whether the signature is good. Signatures by keys that are not in the
+    /// keyring cannot be checked and are not valid.
+    pub valid: bool,
+    /// Whether the keyring fully trusts the key.
+    pub trusted: bool,
+}
+
+impl fmt::Display for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let short = &self.fingerprint[self.fingerprint.len().saturating_sub(16)..];
+        if self.user_id.is_empty() {
+            write!(f, "{}", short)
+        } else {
+            write!(f, "{} ({})", self.user_id, short)
+        }
+    }
+}
+
+/// Decrypt binary data with the gpg-agent like [`decrypt_bytes`] and check
+/// its signature. Returns the first signer, or `None` if it is not signed.
+pub fn decrypt_verified(
+    encrypted_data: &[u8],
+) -> Result<(Zeroizing<Vec<u8>>, Option<Signer>), CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+
+    let mut plaintext = Zeroizing::new(Vec::new());
+    let (_, verification) = ctx.decrypt_and_verify(encrypted_data, &mut *plaintext)?;
+    if plaintext.is_empty() {
+        return Err(CryptoError::NoData);
+    }
+
+    let signer = verification.signatures().next().map(|signature| {
+        let fingerprint = signature.fingerprint().unwrap_or_default().to_string();
+        let key = ctx.get_key(fingerprint.as_str()).ok();
+        Signer {
+            fingerprint: key
+                .as_ref()
+                .and_then(|key| key.fingerprint().ok())
+                .map(str::to_string)
+                .unwrap_or(fingerprint),
+            user_id: key
+                .as_ref()
+                .and_then(|key| key.user_ids().next())
+                .and_then(|uid| uid.id().ok().map(str::to_string))
+                .unwrap_or_default(),
+            valid: signature.status().is_ok(),
+            trusted: matches!(signature.validity(), Validity::Full | Validity::Ultimate),
+        }
+    });
+    Ok((plaintext, signer))
+}
+
+/// Encrypt data to the given recipients.
+///
+/// Recipients are the entries of a `.gpg-id` file: fingerprints, key IDs or
+/// email addresses. Every recipient must match at least one valid key that
+/// can encrypt, otherwise nothing is encrypted. The data is also signed if
+/// [`SECRET_SIGNING_KEY_VAR`] is set.
+pub fn encrypt(plaintext: &[u8], recipients: &[String]) -> Result<Vec<u8>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+
+    let mut keys: Vec<Key> = Vec::new();
+    for recipient in recipients {
+        let found: Vec<Key> = ctx
+            .find_keys([recipient.as_str()])?
+            .filter_map(Result::ok)
+            .filter(|key| {
+                key.can_encrypt()
+                    && !key.is_revoked()
+                    && !key.is_expired()
+                    && !key.is_disabled()
+                    && !key.is_invalid()
+            })
+            .collect();
+
+        if found.is_empty() {
+            return Err(CryptoError::KeyNotFound(recipient.clone()));
+        }
+        keys.extend(found);
+    }
+
+    // The .gpg-id file is the source of trust for the store, like with pass.
+    let mut ciphertext = Vec::new();
+    match env::var(SECRET_SIGNING_KEY_VAR)
+        .ok()
+        .filter(|key| !key.is_empty())
+    {
+        Some(signer) => {
+            let key = ctx
+                .get_secret_key(signer.as_str())
+                .map_err(|_| CryptoError::KeyNotFound(signer.clone()))?;
+            ctx.add_signer(&key)?;
+            ctx.sign_and_encrypt_with_flags(
+                &keys,
+                plaintext,
+                &mut ciphertext,
+                EncryptFlags::ALWAYS_TRUST,
+            )?;
+        }
+        None => {
+            ctx.encrypt_with_flags(
+                &keys,
+                plaintext,
+                &mut ciphertext,
+                EncryptFlags::ALWAYS_TRUST,
+            )?;
+        }
+    }
+
+    Ok(ciphertext)
+}
+
+/// A public key that data can be encrypted to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    pub fingerprint: String,
+    /// Primary user ID, e.g. `Alice <alice@example.com>`.
+    pub user_id: String,
+}
+
+impl fmt::Display for KeyInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let short = &self.fingerprint[self.fingerprint.len().saturating_sub(16)..];
+        write!(f, "{} ({})", self.user_id, short)
+    }
+}
+
+/// List the valid public keys in the keyring that can encrypt.
+pub fn encryption_keys() -> Result<Vec<KeyInfo>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    let keys = ctx
+        .keys()?
+        .filter_map(Result::ok)
+        .filter(|key| {
+            key.can_encrypt() && !key.is_revoked() && !key.is_expired() && !key.is_invalid()
+        })
+        .filter_map(|key| {
+            let fingerprint = key.fingerprint().ok()?.to_string();
+            let user_id = key
+                .user_ids()
+                .next()
+                .and_then(|uid| uid.id().ok().map(str::to_string))
+                .unwrap_or_default();
+            Some(KeyInfo {
+                fingerprint,
+                user_id,
+            })
+        })
+        .collect();
+    Ok(keys)
+}
+
+/// Key IDs of a key and its subkeys.
+fn subkey_ids(key: &Key) -> Vec<u64> {
+    key.subkeys()
+        .filter_map(|subkey| subkey.id().ok())
+        .filter_map(|id| u64::from_str_radix(id, 16).ok())
+        .collect()
+}
+
+/// Key IDs of the secret keys and subkeys in the keyring, i.e. the keys
+/// this user can decrypt with.
+pub fn secret_key_ids() -> Result<HashSet<u64>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    let ids = ctx
+        .secret_keys()?
+        .filter_map(Result::ok)
+        .flat_map(|key| subkey_ids(&key))
+        .collect();
+    Ok(ids)
+}
+
+/// Key IDs of the keys and subkeys matching a `.gpg-id` entry.
+pub fn recipient_key_ids(recipient: &str) -> Result<HashSet<u64>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    let ids: HashSet<u64> = ctx
+        .find_keys([recipient])?
+        .filter_map(Result::ok)
+        .flat_map(|key| subkey_ids(&key))
+        .collect();
+    if ids.is_empty() {
+        return Err(CryptoError::KeyNotFound(recipient.to_string()));
+    }
+    Ok(ids)
+}
+
+/// Primary key fingerprints of the valid signatures in the detached
+/// `signature` of `data`.
+pub fn verify_detached(signature: &[u8], data: &[u8]) -> Result<Vec<String>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    let result = ctx.verify_detached(signature, data)?;
+    let signers: Vec<String> = result
+        .signatures()
+        .filter(|signature| signature.status().is_ok())
+        .filter_map(|signature| signature.fingerprint().ok().map(str::to_string))
+        .collect();
+    // Signatures made with a subkey name the subkey
+    let fingerprints = signers
+        .into_iter()
+        .filter_map(|fingerprint| {
+            let key = ctx.get_key(fingerprint.as_str()).ok()?;
+            key.fingerprint().ok().map(str::to_string)
+        })
+        .collect();
+    Ok(fingerprints)
+}
+
+/// Create a detached signature of `data` with the first of `signers` that
+/// has a secret key in the keyring.
+pub fn sign_detached(data: &[u8], signers: &[String]) -> Result<Vec<u8>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    let key = signers
+        .iter()
+        .find_map(|signer| {
+            let key = ctx.get_secret_key(signer.as_str()).ok()?;
+            key.can_sign().then_some(key)
+        })
+        .ok_or_else(|| CryptoError::KeyNotFound(signers.join(", ")))?;
+    ctx.add_signer(&key)?;
+
+    let mut signature = Vec::new();
+    ctx.sign_detached(data, &mut signature)?;
+    Ok(signature)
+}
+
+/// Answer passphrase requests with `passphrase` instead of asking through pinentry.
+fn passphrase_provider(
+    passphrase: Zeroizing<String>,
+) -> impl FnMut(PassphraseRequest<'_>, &mut dyn Write) -> gpgme::Result<()> {
+    move |request: PassphraseRequest<'_>, out: &mut dyn Write| {
+        // Do not loop on a wrong passphrase
+        if request.prev_attempt_failed {
+            return Err(gpgme::Error::BAD_PASSPHRASE);
+        }
+        out.write_all(passphrase.as_bytes())?;
+        out.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Encrypt data with a passphrase instead of public keys.
+pub fn encrypt_symmetric(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    ctx.set_pinentry_mode(PinentryMode::Loopback)?;
+
+    let mut ciphertext = Vec::new();
+    let provider = passphrase_provider(Zeroizing::new(passphrase.to_string()));
+    ctx.with_passphrase_provider(provider, |ctx| {
+        ctx.encrypt_symmetric(plaintext, &mut ciphertext)
+    })?;
+    Ok(ciphertext)
+}
+
+/// Decrypt binary data, either with the gpg-agent like [`decrypt`] or, for
+/// data encrypted with [`encrypt_symmetric`], with the given passphrase.
+pub fn decrypt_bytes(
+    encrypted_data: &[u8],
+    passphrase: Option<&str>,
+) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+
+    let mut plaintext = Zeroizing::new(Vec::new());
+    match passphrase {
+        Some(passphrase) => {
+            ctx.set_pinentry_mode(PinentryMode::Loopback)?;
+            let provider = passphrase_provider(Zeroizing::new(passphrase.to_string()));
+            ctx.with_passphrase_provider(provider, |ctx| {
+                ctx.decrypt(encrypted_data, &mut *plaintext)
+            })?;
+        }
+        None => {
+            ctx.decrypt(encrypted_data, &mut *plaintext)?;
+        }
+    }
+
+    if plaintext.is_empty() {
+        return Err(CryptoError::NoData);
+    }
+    Ok(plaintext)
+}
+
+/// Generate a key pair protected by `passphrase`: an ed25519 primary key for
+/// signing and a cv25519 subkey for encryption, both expiring after
+/// `expires` or never.
+///
+/// Returns the fingerprint of the new key.
+pub fn generate_key(
+    user_id: &str,
+    expires: Option<Duration>,
+    passphrase: &str,
+) -> Result<String, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    ctx.set_pinentry_mode(PinentryMode::Loopback)?;
+    let (expires, flags) = match expires {
+        Some(expires) => (expires, CreateKeyFlags::empty()),
+        None => (Duration::ZERO, CreateKeyFlags::NOEXPIRE),
+    };
+
+    let provider = passphrase_provider(Zeroizing::new(passphrase.to_string()));
+    ctx.with_passphrase_provider(provider, |ctx| {
+        let result = ctx.create_key_with_flags(
+            user_id,
+            "ed25519",
+            expires,
+            flags | CreateKeyFlags::SIGN | CreateKeyFlags::CERT,
+        )?;
+        let fingerprint = result
+            .fingerprint()
+            .map_err(|_| CryptoError::KeyNotFound(user_id.to_string()))?
+            .to_string();
+        let key = ctx.get_secret_key(&fingerprint)?;
+        ctx.create_subkey_with_flags(&key, "cv25519", expires, flags | CreateKeyFlags::ENCR)?;
+        debug!("Generated key {} for '{}'", fingerprint, user_id);
+        Ok(fingerprint)
+    })
+}
+
+/// The ASCII armored public key with the given fingerprint.
+pub fn export_public_key(fingerprint: &str) -> Result<Vec<u8>, CryptoError> {
+    let mut ctx = Context::from_protocol(Protocol::OpenPgp)?;
+    ctx.set_armor(true);
+    let mut data = Vec::new();
+    ctx.export(Some(fingerprint), ExportMode::empty(), &mut data)?;
+    if data.is_empty() {
+        return Err(CryptoError::KeyNotFound(fingerprint.to_string()));
+    }
+    Ok(data)
+}
+
+/// The revocation certificate gpg creates along with every key, from
+/// `openpgp-revocs.d` in its home directory.
+pub fn revocation_certificate(fingerprint: &str) -> std::io::Result<Vec<u8>> {
+    let output = Command::new("gpgconf")
+        .args(["--list-dirs", "homedir"])
+        .output()?;
+    let home = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let path = Path::new(&home)
+        .join("openpgp-revocs.d")
+        .join(format!("{}.rev", fingerprint));
+    fs::read(path)
+}
+
+/// Ask gpg-agent to forget all cached passphrases.
+pub fn forget_passphrases() {
+    match Command::new("gpg-connect-agent")
+        .args(["reloadagent", "/bye"])
+        .output()
+    {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => error!(
+            "gpg-connect-agent failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => error!("Failed to run gpg-connect-agent: {}", e),
+    }
+}
@@ -0,0 +1,312 @@
+use crate::crypto::{self, Signer};
+use iced::widget::text_editor::{self, Action, Edit, Motion};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Character used to hide the password line of a masked secret.
+const MASK: &str = "\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}";
+/// Maximum number of undo steps kept per tab.
+const HISTORY_LIMIT: usize = 100;
+
+/// The kind of an edit, used to coalesce consecutive edits into one undo step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    Word,
+    Separator,
+    Deletion,
+    Other,
+}
+
+impl EditKind {
+    fn of(edit: &Edit) -> Self {
+        match edit {
+            Edit::Insert(c) if c.is_alphanumeric() => EditKind::Word,
+            Edit::Insert(_) => EditKind::Separator,
+            Edit::Backspace | Edit::Delete => EditKind::Deletion,
+            Edit::Paste(_) | Edit::Enter => EditKind::Other,
+        }
+    }
+
+    /// Whether an edit of kind `next` following `self` starts a new undo step.
+    ///
+    /// Typing a word and the separator after it form one step.
+    fn starts_step(self, next: EditKind) -> bool {
+        !matches!(
+            (self, next),
+            (EditKind::Word, EditKind::Word)
+                | (EditKind::Word, EditKind::Separator)
+                | (EditKind::Separator, EditKind::Separator)
+                | (EditKind::Deletion, EditKind::Deletion)
+        )
+    }
+}
+
+/// The editor content at some point in time. The text is wiped from memory
+/// when the snapshot is dropped.
+struct Snapshot {
+    text: Zeroizing<String>,
+    cursor: (usize, usize),
+}
+
+impl Snapshot {
+    fn of(content: &text_editor::Content) -> Self {
+        Self {
+            text: Zeroizing::new(content.text()),
+            cursor: content.cursor_position(),
+        }
+    }
+
+    fn restore(&self) -> text_editor::Content {
+        let mut content = text_editor::Content::with_text(&self.text);
+        let (line, column) = self.cursor;
+        for _ in 0..line {
+            content.perform(Action::Move(Motion::Down));
+        }
+        content.perform(Action::Move(Motion::Home));
+        for _ in 0..column {
+            content.perform(Action::Move(Motion::Right));
+        }
+        content
+    }
+}
+
+/// Undo and redo stacks of a tab.
+#[derive(Default)]
+struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Kind of the last edit, `None` after a cursor movement or undo/redo.
+    last_edit: Option<EditKind>,
+}
+
+/// Magic numbers of common binary formats, checked in order.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xff\xd8\xff", "JPEG image"),
+    (b"GIF8", "GIF image"),
+    (b"%PDF-", "PDF document"),
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"\x1f\x8b", "gzip archive"),
+    (b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (b"\x7fELF", "ELF executable"),
+    (b"SQLite format 3\0", "SQLite database"),
+    (b"\x03\xd9\xa2\x9a\x67\xfb\x4b\xb5", "KeePass database"),
+    (b"\x30\x82", "DER encoded certificate or key"),
+];
+
+/// Size and detected type of a binary secret. The content itself is not kept.
+#[derive(Debug, Clone)]
+pub struct BinaryInfo {
+    pub size: usize,
+    pub kind: &'static str,
+}
+
+impl BinaryInfo {
+    pub fn of(data: &[u8]) -> Self {
+        let kind = SIGNATURES
+            .iter()
+            .find(|(magic, _)| data.starts_with(magic))
+            .map(|(_, kind)| *kind)
+            .unwrap_or("Unknown binary data");
+        Self {
+            size: data.len(),
+            kind,
+        }
+    }
+
+    /// The size in bytes and, for larger files, in KiB or MiB.
+    pub fn size_text(&self) -> String {
+        const KIB: f64 = 1024.0;
+        let size = self.size as f64;
+        if size < KIB {
+            format!("{} bytes", self.size)
+        } else if size < KIB * KIB {
+            format!("{:.1} KiB ({} bytes)", size / KIB, self.size)
+        } else {
+            format!("{:.1} MiB ({} bytes)", size / (KIB * KIB), self.size)
+        }
+    }
+}
+
+/// Decrypted content of a file.
+#[derive(Clone)]
+pub enum FileData {
+    Text(Zeroizing<String>),
+    Binary(BinaryInfo),
+}
+
+// Keep decrypted text out of debug logs
+impl fmt::Debug for FileData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileData::Text(text) => write!(f, "Text({} bytes)", text.len()),
+            FileData::Binary(info) => write!(f, "Binary({:?})", info),
+        }
+    }
+}
+
+impl FileData {
+    /// Text if `data` is valid UTF-8, binary otherwise.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        match std::str::from_utf8(data) {
+            Ok(text) => FileData::Text(Zeroizing::new(text.to_string())),
+            Err(_) => FileData::Binary(BinaryInfo::of(data)),
+        }
+    }
+}
+
+/// Signature of a secret, checked when it was decrypted.
+#[derive(Debug, Clone)]
+pub enum Signature {
+    Unsigned,
+    /// Good signature by a recipient of the secret.
+    Team(Signer),
+    /// Good signature by a key that is not a recipient of the secret.
+    Outsider(Signer),
+    /// Bad signature, or by a key that is not in the keyring.
+    Invalid(Signer),
+}
+
+/// A file read for the editor.
+#[derive(Debug, Clone)]
+pub struct LoadedFile {
+    pub data: FileData,
+    /// `None` for files that are not secrets.
+    pub signature: Option<Signature>,
+}
+
+/// A file opened in its own tab of the editor panel.
+pub struct Tab {
+    pub path: PathBuf,
+    pub content: text_editor::Content,
+    /// Set for secrets that are not text. Their content is not shown or edited.
+    pub binary: Option<BinaryInfo>,
+    /// Whether the content differs from the file on disk.
+    pub dirty: bool,
+    /// Whether the password line is shown in clear text. Secrets start masked.
+    pub revealed: bool,
+    /// Incremented on every change, to tell whether a save is still current.
+    pub revision: u64,
+    /// Signature found when the secret was opened, cleared once it is saved.
+    pub signature: Option<Signature>,
+    history: History,
+}
+
+impl Tab {
+    pub fn new(path: PathBuf, text: &str) -> Self {
+        let revealed = !is_secret(&path);
+        Self {
+            path,
+            content: text_editor::Content::with_text(text),
+            binary: None,
+            dirty: false,
+            revealed,
+            revision: 0,
+            signature: None,
+            history: History::default(),
+        }
+    }
+
+    /// A tab showing the metadata of a binary secret.
+    pub fn binary(path: PathBuf, info: BinaryInfo) -> Self {
+        Self {
+            binary: Some(info),
+            ..Self::new(path, "")
+        }
+    }
+
+    /// Open `data` as text or, if it is not text, as a binary secret.
+    pub fn from_data(path: PathBuf, data: FileData) -> Self {
+        match data {
+            FileData::Text(text) => Self::new(path, &text),
+            FileData::Binary(info) => Self::binary(path, info),
+        }
+    }
+
+    /// Open a file read by the file explorer, with its signature.
+    pub fn from_loaded(path: PathBuf, loaded: LoadedFile) -> Self {
+        Self {
+            signature: loaded.signature,
+            ..Self::from_data(path, loaded.data)
+        }
+    }
+
+    /// Apply an editor action, recording edits in the undo history.
+    pub fn perform(&mut self, action: Action) {
+        match &action {
+            Action::Edit(edit) => {
+                let kind = EditKind::of(edit);
+                let starts_step = self
+                    .history
+                    .last_edit
+                    .is_none_or(|last| last.starts_step(kind));
+                if starts_step {
+                    self.history.undo.push(Snapshot::of(&self.content));
+                    if self.history.undo.len() > HISTORY_LIMIT {
+                        self.history.undo.remove(0);
+                    }
+                }
+                self.history.redo.clear();
+                self.history.last_edit = Some(kind);
+                self.dirty = true;
+                self.revision += 1;
+            }
+            Action::Scroll { .. } => {}
+            _ => self.history.last_edit = None,
+        }
+        self.content.perform(action);
+    }
+
+    /// Revert the last undo step. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(snapshot) = self.history.undo.pop() else {
+            return false;
+        };
+        self.history.redo.push(Snapshot::of(&self.content));
+        self.content = snapshot.restore();
+        self.history.last_edit = None;
+        self.dirty = true;
+        self.revision += 1;
+        true
+    }
+
+    /// Re-apply the last undone step. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(snapshot) = self.history.redo.pop() else {
+            return false;
+        };
+        self.history.undo.push(Snapshot::of(&self.content));
+        self.content = snapshot.restore();
+        self.history.last_edit = None;
+        self.dirty = true;
+        self.revision += 1;
+        true
+    }
+
+    /// The name shown in the tab bar.
+    pub fn title(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+
+    /// The content with the first line (the password, by pass convention) masked.
+    pub fn masked_text(&self) -> String {
+        mask(&self.content.text())
+    }
+}
+
+/// `text` with its first line, the password by pass convention, masked.
+pub fn mask(text: &str) -> String {
+    match text.split_once('\n') {
+        Some((_, rest)) => format!("{}\n{}", MASK, rest),
+        None => MASK.to_string(),
+    }
+}
+
+/// Whether `path` is an encrypted secret of any backend.
+pub fn is_secret(path: &Path) -> bool {
+    crypto::backend_of(path).is_some()
+}
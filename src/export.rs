@@ -0,0 +1,560 @@
+use crate::crypto::{self, CryptoError};
+use crate::job::{self, CancelFlag, Job};
+use crate::secret::Secret;
+use crate::tree;
+use iced::futures::{SinkExt, Stream};
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::debug;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+#[derive(Debug)]
+pub enum ExportError {
+    IoError(std::io::Error),
+    Crypto(PathBuf, CryptoError),
+    Json(serde_json::Error),
+    Cancelled,
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::IoError(e) => write!(f, "I/O error: {}", e),
+            ExportError::Crypto(path, e) => write!(f, "{}: {}", path.display(), e),
+            ExportError::Json(e) => write!(f, "Could not write JSON: {}", e),
+            ExportError::Cancelled => write!(f, "The export was cancelled"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+/// Optional parts of the secrets to include in an export.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub include_otp: bool,
+    pub include_notes: bool,
+}
+
+/// A decrypted secret with its path relative to the exported directory.
+struct Row {
+    path: String,
+    secret: Secret,
+}
+
+/// Decrypt and parse every secret below `dir`, reporting progress to `output`.
+async fn read_rows(
+    dir: &Path,
+    options: Options,
+    cancel: &CancelFlag,
+    output: &mut iced::futures::channel::mpsc::Sender<Message>,
+) -> Result<Vec<Row>, ExportError> {
+    let files = tree::secret_files(dir)?;
+    let total = files.len();
+    let mut rows = Vec::new();
+    for (index, file) in files.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            return Err(ExportError::Cancelled);
+        }
+        let _ = output.send(Message::Progress(index, total)).await;
+        let plaintext = crypto::backend(&file)
+            .decrypt_text(&fs::read(&file)?)
+            .map(Zeroizing::new)
+            .map_err(|e| ExportError::Crypto(file.clone(), e))?;
+        let mut secret = Secret::parse(&plaintext);
+        if !options.include_otp {
+            secret.otp = None;
+        }
+        if !options.include_notes {
+            secret.fields.retain(|(key, _)| key != "notes");
+        }
+
+        let path = file
+            .strip_prefix(dir)
+            .unwrap_or(&file)
+            .with_extension("")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        rows.push(Row { path, secret });
+    }
+    Ok(rows)
+}
+
+/// Columns holding the parts of a secret that are not fields.
+const RESERVED_COLUMNS: [&str; 3] = ["path", "password", "otp"];
+/// Prefix of field columns that would collide with a reserved one.
+const FIELD_PREFIX: &str = "field:";
+
+/// Column of the field `key`. Fields named like a reserved column, or like
+/// a prefixed one, are prefixed so that every field gets its own column.
+fn field_column(key: &str) -> String {
+    if RESERVED_COLUMNS.contains(&key) || key.starts_with(FIELD_PREFIX) {
+        format!("{}{}", FIELD_PREFIX, key)
+    } else {
+        key.to_string()
+    }
+}
+
+/// Quote a CSV value if needed, following RFC 4180.
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One row per secret with a column per field key. Keys occurring several
+/// times in a secret are joined by newlines.
+fn to_csv(rows: &[Row]) -> Zeroizing<String> {
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows {
+        for (key, _) in &row.secret.fields {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+    let has_otp = rows.iter().any(|row| row.secret.otp.is_some());
+
+    let mut csv = Zeroizing::new(String::new());
+    let mut header = vec!["path".to_string(), "password".to_string()];
+    header.extend(columns.iter().map(|key| field_column(key)));
+    if has_otp {
+        header.push("otp".to_string());
+    }
+    csv.push_str(
+        &header
+            .iter()
+            .map(|h| csv_value(h))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    csv.push('\n');
+
+    for row in rows {
+        let mut values = vec![csv_value(&row.path), csv_value(&row.secret.password)];
+        for column in &columns {
+            let value = row
+                .secret
+                .fields
+                .iter()
+                .filter(|(key, _)| key == column)
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            values.push(csv_value(&value));
+        }
+        if has_otp {
+            values.push(csv_value(row.secret.otp.as_deref().unwrap_or_default()));
+        }
+        csv.push_str(&values.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// An array with one object per secret, its fields in a separate `fields`
+/// object so that they cannot collide with the path, password or OTP seed.
+fn to_json(rows: &[Row]) -> Result<Zeroizing<String>, ExportError> {
+    let secrets: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            let mut object = Map::new();
+            object.insert("path".to_string(), Value::from(row.path.as_str()));
+            object.insert(
+                "password".to_string(),
+                Value::from(row.secret.password.as_str()),
+            );
+            let mut fields = Map::new();
+            for (key, value) in &row.secret.fields {
+                match fields.get_mut(key) {
+                    Some(Value::String(existing)) => {
+                        existing.push('\n');
+                        existing.push_str(value);
+                    }
+                    _ => {
+                        fields.insert(key.clone(), Value::from(value.as_str()));
+                    }
+                }
+            }
+            object.insert("fields".to_string(), Value::Object(fields));
+            if let Some(otp) = &row.secret.otp {
+                object.insert("otp".to_string(), Value::from(otp.as_str()));
+            }
+            Value::Object(object)
+        })
+        .collect();
+    Ok(Zeroizing::new(serde_json::to_string_pretty(&secrets)?))
+}
+
+/// Create or truncate `path` so that only the owner can read it, before any
+/// plaintext is written.
+pub fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)
+}
+
+/// Count the secrets below `dir`.
+fn count(dir: &Path) -> usize {
+    tree::secret_files(dir)
+        .map(|files| files.len())
+        .unwrap_or_default()
+}
+
+/// Write every secret below `dir` as plaintext to `destination`. Nothing is
+/// written if the export is cancelled while decrypting.
+///
+/// Returns the number of exported secrets.
+async fn export(
+    dir: &Path,
+    destination: &Path,
+    format: Format,
+    options: Options,
+    cancel: &CancelFlag,
+    output: &mut iced::futures::channel::mpsc::Sender<Message>,
+) -> Result<usize, ExportError> {
+    let rows = read_rows(dir, options, cancel, output).await?;
+    let data = match format {
+        Format::Csv => to_csv(&rows),
+        Format::Json => to_json(&rows)?,
+    };
+
+    debug!(
+        "Writing {} secrets as plaintext to '{}'",
+        rows.len(),
+        destination.display()
+    );
+    let mut file = create_private(destination)?;
+    file.write_all(data.as_bytes())?;
+    Ok(rows.len())
+}
+
+/// Run an export, yielding progress and then the result.
+fn run(
+    dir: PathBuf,
+    destination: PathBuf,
+    format: Format,
+    options: Options,
+    cancel: CancelFlag,
+) -> impl Stream<Item = Message> {
+    iced::stream::channel(16, move |mut output| async move {
+        let result = export(&dir, &destination, format, options, &cancel, &mut output)
+            .await
+            .map_err(|e| e.to_string());
+        let _ = output.send(Message::Finished(destination, result)).await;
+    })
+}
+
+/// State of the plaintext export panel.
+pub struct State {
+    /// Directory whose secrets are exported.
+    pub dir: PathBuf,
+    pub format: Format,
+    pub options: Options,
+    /// Whether the secrets are being counted before asking for confirmation.
+    counting: bool,
+    /// Cancels the running export when dropped.
+    running: Option<Job>,
+    progress: (usize, usize),
+}
+
+/// Messages for export panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    FormatSelected(Format),
+    IncludeOtp(bool),
+    IncludeNotes(bool),
+    /// Ask for confirmation and a destination, then export.
+    Export,
+    /// The secrets were counted for the export to this destination.
+    Counted(PathBuf, usize),
+    Cancel,
+    Progress(usize, usize),
+    /// The destination and the number of exported secrets.
+    Finished(PathBuf, Result<usize, String>),
+    Close,
+}
+
+impl State {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            format: Format::Csv,
+            options: Options {
+                include_otp: false,
+                include_notes: true,
+            },
+            counting: false,
+            running: None,
+            progress: (0, 0),
+        }
+    }
+
+    /// Count the secrets to export to `destination` in the background.
+    pub fn count(&mut self, destination: PathBuf) -> Task<Message> {
+        self.counting = true;
+        let dir = self.dir.clone();
+        Task::perform(
+            async move {
+                let count = count(&dir);
+                (destination, count)
+            },
+            |(destination, count)| Message::Counted(destination, count),
+        )
+    }
+
+    /// Start writing the export to `destination`.
+    pub fn start(&mut self, destination: PathBuf) -> Task<Message> {
+        debug!("Exporting '{}'", self.dir.display());
+        let (dir, format, options) = (self.dir.clone(), self.format, self.options);
+        let (task, job) = job::spawn(|cancel| run(dir, destination, format, options, cancel));
+        self.running = Some(job);
+        self.progress = (0, 0);
+        task
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::FormatSelected(format) => self.format = format,
+            Message::IncludeOtp(include) => self.options.include_otp = include,
+            Message::IncludeNotes(include) => self.options.include_notes = include,
+            Message::Cancel => {
+                debug!("Export cancelled");
+                self.running = None;
+            }
+            Message::Progress(done, total) => self.progress = (done, total),
+            Message::Counted(_, _) => self.counting = false,
+            Message::Finished(_, _) => self.running = None,
+            Message::Export | Message::Close => {}
+        }
+    }
+
+    /// Ask where to write the export.
+    pub fn pick_destination(&self) -> Option<PathBuf> {
+        let name = self
+            .dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "secrets".to_string());
+        rfd::FileDialog::new()
+            .set_file_name(format!("{}.{}", name, self.format.extension()))
+            .add_filter(self.format.extension(), &[self.format.extension()])
+            .save_file()
+    }
+}
+
+/// Renders the export panel with a plaintext warning and the export options.
+///
+/// # Arguments
+/// * `state` - The current export panel state
+/// * `on_message` - Function to wrap export messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let header = widget::row![
+        widget::text(format!("Export {}", state.dir.display())).size(16),
+        widget::horizontal_space(),
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let warning = widget::text(
+        "This writes plaintext. Every password in this directory will be readable \
+         by anyone with access to the exported file. Delete it as soon as the \
+         migration is done.",
+    )
+    .size(14)
+    .color(iced::Color::from_rgb(0.7, 0.1, 0.1));
+
+    let format_row = widget::row![
+        widget::text("Format").size(14),
+        widget::radio(
+            "CSV",
+            Format::Csv,
+            Some(state.format),
+            Message::FormatSelected
+        ),
+        widget::radio(
+            "JSON",
+            Format::Json,
+            Some(state.format),
+            Message::FormatSelected
+        ),
+    ]
+    .spacing(16)
+    .align_y(iced::Alignment::Center);
+
+    let otp = widget::checkbox("Include OTP seeds", state.options.include_otp)
+        .on_toggle(Message::IncludeOtp);
+    let notes = widget::checkbox("Include notes", state.options.include_notes)
+        .on_toggle(Message::IncludeNotes);
+    let action = if state.running.is_some() {
+        widget::button(widget::text("Cancel").size(14)).on_press(Message::Cancel)
+    } else {
+        widget::button(
+            widget::text(if state.counting {
+                "Counting secrets..."
+            } else {
+                "Export plaintext..."
+            })
+            .size(14),
+        )
+        .on_press_maybe((!state.counting).then_some(Message::Export))
+    };
+
+    let content: Element<'a, Message> = widget::column![
+        header,
+        warning,
+        format_row,
+        otp,
+        notes,
+        widget::row![widget::horizontal_space(), action]
+    ]
+    .push_maybe(state.running.as_ref().map(|_| {
+        let (done, total) = state.progress;
+        widget::column![
+            widget::text(format!("Decrypting secret {} of {}...", done + 1, total)).size(14),
+            widget::progress_bar(0.0..=total.max(1) as f32, done as f32),
+        ]
+        .spacing(8)
+    }))
+    .spacing(10)
+    .padding(10)
+    .height(Length::Fill)
+    .width(Length::Fill)
+    .into();
+    content.map(on_message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(path: &str, password: &str, fields: &[(&str, &str)], otp: Option<&str>) -> Row {
+        let mut secret = Secret::new(password);
+        secret.fields = fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        secret.otp = otp.map(str::to_string);
+        Row {
+            path: path.to_string(),
+            secret,
+        }
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(csv_value("plain"), "plain");
+        assert_eq!(csv_value(""), "");
+        assert_eq!(csv_value("a,b"), "\"a,b\"");
+        assert_eq!(csv_value("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_value("one\ntwo"), "\"one\ntwo\"");
+        assert_eq!(csv_value("one\r\ntwo"), "\"one\r\ntwo\"");
+    }
+
+    #[test]
+    fn prefixes_colliding_field_columns() {
+        assert_eq!(field_column("user"), "user");
+        assert_eq!(field_column("path"), "field:path");
+        assert_eq!(field_column("password"), "field:password");
+        assert_eq!(field_column("otp"), "field:otp");
+        // Otherwise a field named "field:path" would share a column with "path"
+        assert_eq!(field_column("field:path"), "field:field:path");
+        assert_eq!(field_column("Password"), "Password");
+    }
+
+    #[test]
+    fn writes_a_column_per_field_key() {
+        let rows = [
+            row("web/mail", "hunter2", &[("user", "alice")], None),
+            row("web/bank", "p,w", &[("pin", "1234"), ("user", "bob")], None),
+        ];
+        assert_eq!(
+            to_csv(&rows).as_str(),
+            "path,password,user,pin\n\
+             web/mail,hunter2,alice,\n\
+             web/bank,\"p,w\",bob,1234\n"
+        );
+    }
+
+    #[test]
+    fn keeps_colliding_fields_apart() {
+        let rows = [row(
+            "web/mail",
+            "hunter2",
+            &[("password", "old"), ("field:password", "older")],
+            Some("otpauth://totp/mail?secret=ABC"),
+        )];
+        assert_eq!(
+            to_csv(&rows).as_str(),
+            "path,password,field:password,field:field:password,otp\n\
+             web/mail,hunter2,old,older,otpauth://totp/mail?secret=ABC\n"
+        );
+    }
+
+    #[test]
+    fn joins_repeated_keys_and_quotes_newlines() {
+        let rows = [row(
+            "ssh",
+            "pw",
+            &[
+                ("notes", "first line\nsecond \"line\""),
+                ("url", "a.example"),
+                ("url", "b.example"),
+            ],
+            None,
+        )];
+        assert_eq!(
+            to_csv(&rows).as_str(),
+            "path,password,notes,url\n\
+             ssh,pw,\"first line\nsecond \"\"line\"\"\",\"a.example\nb.example\"\n"
+        );
+    }
+}
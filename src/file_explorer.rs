@@ -1,14 +1,43 @@
+use crate::access;
 use crate::assets;
+use crate::audit;
+use crate::backup;
+use crate::confirm_popup;
+use crate::conflict;
 use crate::context_menu;
 use crate::crypto;
+use crate::editor;
 use crate::error_popup;
+use crate::export;
+use crate::fsck;
+use crate::git;
+use crate::history;
+use crate::import;
+use crate::job::{self, Job};
+use crate::packets;
+use crate::plaintext;
+use crate::recipients;
+use crate::search;
+use crate::summary_popup;
+use crate::tree;
 use iced::widget;
-use iced::widget::text_editor;
-use iced::{Element, Length};
-use log::{debug, error, trace};
-use std::collections::HashSet;
+use iced::widget::{scrollable, text_editor};
+use iced::{keyboard, window, Element, Length, Subscription, Task};
+use log::{debug, error, trace, warn};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use zeroize::Zeroizing;
+
+/// Height of a single row in the file explorer. Rows have a fixed height so
+/// that only the rows inside the viewport need to be built.
+const ROW_HEIGHT: f32 = 30.0;
+/// Extra rows built above and below the viewport to avoid flicker while scrolling.
+const OVERSCAN_ROWS: usize = 10;
+/// Viewport height assumed until the scrollable reports its real size.
+const DEFAULT_VIEWPORT_HEIGHT: f32 = 1200.0;
 
 #[derive(Debug, Clone)]
 pub enum FileAction {
@@ -17,10 +46,113 @@ pub enum FileAction {
     CloseContextMenu,
     EditItem(PathBuf),
     DeleteItem(PathBuf),
+    EncryptItem(PathBuf),
+    /// A plaintext file was encrypted in the background, into the given secret.
+    ItemEncrypted(PathBuf, Result<PathBuf, String>),
     CursorMoved(iced::Point),
-    CloseEditor,
+    Scrolled(scrollable::Viewport),
+    SelectTab(usize),
+    CloseTab(usize),
+    NextTab,
+    PreviousTab,
+    ToggleReveal,
+    SaveEditor,
+    /// A file opened or reloaded in the background was read.
+    Loaded(PathBuf, Result<editor::LoadedFile, String>),
+    CancelLoading,
+    /// A tab was saved in the background at the given revision.
+    Saved(PathBuf, u64, Result<(), String>),
+    /// The children of a directory were read in the background.
+    DirectoryLoaded(PathBuf, Vec<tree::Node>),
+    /// The directories above a secret to reveal were read in the background.
+    AncestorsLoaded(PathBuf, Vec<(PathBuf, Vec<tree::Node>)>),
+    /// The loaded directories of the tree were read again in the background.
+    TreeReloaded(Vec<tree::Node>),
+    /// The store was scanned for unencrypted files in the background.
+    PlaintextScanned(Vec<PathBuf>),
+    /// The key IDs of the user's secret keys were listed in the background.
+    SecretKeysListed(Result<HashSet<u64>, String>),
+    /// Whether visible secrets are locked was checked in the background, with
+    /// the modification time of each file when it was checked.
+    LockedChecked(Vec<(PathBuf, (SystemTime, bool))>),
     EditorAction(text_editor::Action),
+    Undo,
+    Redo,
+    ShowHistory(PathBuf),
+    /// The commits touching a secret were listed in the background.
+    HistoryLoaded(PathBuf, Result<Vec<git::Commit>, String>),
+    History(history::Message),
+    /// An old version of a secret was restored in the background.
+    VersionRestored(PathBuf, Result<Zeroizing<String>, String>),
+    Sync,
+    /// Remote changes were pulled in the background, with the files left
+    /// conflicting.
+    Synced(Result<(), String>, Result<Vec<PathBuf>, String>),
+    /// The sides of a conflicting file were read in the background.
+    ConflictLoaded(PathBuf, Result<conflict::Sides, String>),
+    /// The resolution of a conflicting file was written in the background.
+    ConflictResolved(PathBuf, Result<(), String>),
+    /// The merge was committed once every conflict was resolved.
+    MergeCommitted(Result<(), String>),
+    /// The merge was aborted in the background.
+    MergeAborted(Result<(), String>),
+    Conflict(conflict::Message),
+    ShowImport(PathBuf),
+    Import(import::Message),
+    ShowBackup,
+    Backup(backup::Message),
+    ShowExport(PathBuf),
+    ShowRecipients(PathBuf),
+    Recipients(access::Message),
+    Export(export::Message),
+    SaveDecryptedCopy,
+    /// A decrypted copy of a secret was written in the background.
+    DecryptedCopySaved(PathBuf, PathBuf, Result<(), String>),
+    ReplaceWithFile,
+    /// A secret was replaced with the content of a file in the background.
+    Replaced(PathBuf, Result<editor::FileData, String>),
+    AddFile(PathBuf),
+    /// A file was encrypted into a new secret in the background.
+    FileAdded(PathBuf, Result<(), String>),
+    ShowAudit,
+    Audit(audit::Message),
+    ShowVerify,
+    Verify(fsck::Message),
+    ShowSearch,
+    Search(search::Message),
+    /// Lock every store. Handled by the parent, which asks each store in
+    /// turn with `LockStore`.
+    Lock,
+    LockStore,
+    /// The store was locked. Handled by the parent.
+    Locked,
+    CloseRequested(window::Id),
+    /// The store agreed to close the window. Handled by the parent.
+    ReadyToClose(window::Id),
     ErrorPopup(error_popup::Message),
+    ConfirmPopup(confirm_popup::Message),
+    SummaryPopup(summary_popup::Message),
+}
+
+/// An action held back until the user confirms it, e.g. because it would
+/// discard unsaved changes.
+#[derive(Debug, Clone)]
+enum PendingAction {
+    CloseTab(PathBuf),
+    Lock,
+    Quit(window::Id),
+    /// The plaintext warning was accepted, ask for a destination next.
+    ConfirmExport,
+    /// Write the plaintext export to this file.
+    Export(PathBuf),
+}
+
+/// A file being read and decrypted in the background, opened in a new tab
+/// once it is loaded.
+struct Loading {
+    path: PathBuf,
+    /// Cancels the load when dropped.
+    _job: Job,
 }
 
 #[derive(Debug, Clone)]
@@ -30,139 +162,1792 @@ pub struct ContextMenuState {
 }
 
 pub struct State {
+    tree: tree::Tree,
+    /// Flattened rows of the tree, rebuilt whenever the tree or `expanded` changes.
+    rows: Vec<tree::VisibleRow>,
+    scroll_offset: f32,
+    viewport_height: f32,
     expanded: HashSet<PathBuf>,
     selected: HashSet<PathBuf>,
     context_menu: Option<ContextMenuState>,
     cursor_position: iced::Point,
-    opened_file: Option<PathBuf>,
-    editor_content: Option<text_editor::Content>,
+    tabs: Vec<editor::Tab>,
+    active_tab: Option<usize>,
+    loading: Option<Loading>,
+    /// Whether a pull is running in the background.
+    syncing: bool,
+    /// History viewer shown in place of the editor.
+    history: Option<history::State>,
+    /// Merge view for the first of `conflicts`, shown in place of the editor.
+    merge: Option<conflict::State>,
+    /// Files left with merge conflicts after a pull.
+    conflicts: Vec<PathBuf>,
+    /// Import panel shown in place of the editor.
+    import: Option<import::State>,
+    /// Backup panel shown in place of the editor.
+    backup: Option<backup::State>,
+    /// Plaintext export panel shown in place of the editor.
+    export: Option<export::State>,
+    /// Recipients editor of a directory shown in place of the editor.
+    recipients: Option<access::State>,
+    /// Password audit, kept while browsing its findings.
+    audit: Option<audit::State>,
+    /// Whether the audit panel is shown in place of the editor.
+    audit_visible: bool,
+    /// Store consistency check, kept while browsing its findings.
+    verify: Option<fsck::State>,
+    /// Whether the store check panel is shown in place of the editor.
+    verify_visible: bool,
+    /// Content search, kept while browsing its results.
+    search: Option<search::State>,
+    /// Whether the search panel is shown in place of the editor.
+    search_visible: bool,
+    /// Name and root of the stores searched, this store only unless set
+    /// with [`State::set_search_stores`].
+    search_stores: Vec<(String, PathBuf)>,
+    /// Secrets whose password was found in a breach by the last audit.
+    breached: HashSet<PathBuf>,
+    /// Key IDs of the user's secret keys, `None` if they could not be listed.
+    secret_key_ids: Option<HashSet<u64>>,
+    /// Whether the visible secrets are encrypted to none of `secret_key_ids`,
+    /// with the modification time of the file when this was checked.
+    locked: HashMap<PathBuf, (SystemTime, bool)>,
     error_popup: Option<error_popup::State>,
+    confirm_popup: Option<confirm_popup::State>,
+    summary_popup: Option<summary_popup::State>,
+    pending_action: Option<PendingAction>,
+    /// Tabs being saved in the background, with whether they need to be
+    /// saved again once that save is done.
+    saving: HashMap<PathBuf, bool>,
+    /// Secrets whose decrypted copy is being written in the background.
+    saving_copies: HashSet<PathBuf>,
+    /// Action waiting for the saves of the tabs it would discard.
+    after_save: Option<PendingAction>,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        Self {
+impl State {
+    /// Open the store at `root`, reading it in the background.
+    pub fn new(root: PathBuf) -> (Self, Task<FileAction>) {
+        let search_stores = vec![(String::new(), root.clone())];
+        let state = Self {
+            tree: tree::Tree::new(root),
+            rows: Vec::new(),
+            scroll_offset: 0.0,
+            viewport_height: DEFAULT_VIEWPORT_HEIGHT,
             expanded: HashSet::new(),
             selected: HashSet::new(),
             context_menu: None,
             cursor_position: iced::Point::ORIGIN,
-            opened_file: None,
-            editor_content: None,
+            tabs: Vec::new(),
+            active_tab: None,
+            loading: None,
+            syncing: false,
+            history: None,
+            merge: None,
+            conflicts: Vec::new(),
+            import: None,
+            backup: None,
+            export: None,
+            recipients: None,
+            audit: None,
+            audit_visible: false,
+            verify: None,
+            verify_visible: false,
+            search: None,
+            search_visible: false,
+            search_stores,
+            breached: HashSet::new(),
+            secret_key_ids: None,
+            locked: HashMap::new(),
             error_popup: None,
+            confirm_popup: None,
+            summary_popup: None,
+            pending_action: None,
+            saving: HashMap::new(),
+            saving_copies: HashSet::new(),
+            after_save: None,
+        };
+        let secret_key_ids = Task::perform(
+            async { crypto::secret_key_ids().map_err(|e| e.to_string()) },
+            FileAction::SecretKeysListed,
+        );
+        let task = Task::batch([reload_tree(&state), scan_plaintext(&state), secret_key_ids]);
+        (state, task)
+    }
+
+    /// Whether any tab has changes that are not saved.
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.tabs.iter().any(|tab| tab.dirty)
+    }
+
+    /// Search these stores instead of this one only. Results in other
+    /// stores are opened by the parent.
+    pub fn set_search_stores(&mut self, stores: Vec<(String, PathBuf)>) {
+        self.search_stores = stores;
+        self.search = None;
+        self.search_visible = false;
+    }
+}
+
+/// Re-read the loaded directories of the tree in the background.
+fn reload_tree(state: &State) -> Task<FileAction> {
+    let root = state.tree.root().to_path_buf();
+    let nodes = state.tree.nodes().to_vec();
+    Task::perform(
+        async move { tree::reload(&root, &nodes) },
+        FileAction::TreeReloaded,
+    )
+}
+
+/// Read the children of a directory in the background.
+fn load_directory(path: PathBuf) -> Task<FileAction> {
+    Task::perform(
+        async move {
+            let children = tree::read_children(&path);
+            (path, children)
+        },
+        |(path, children)| FileAction::DirectoryLoaded(path, children),
+    )
+}
+
+/// Scan the store for unencrypted files in the background.
+fn scan_plaintext(state: &State) -> Task<FileAction> {
+    let root = state.tree.root().to_path_buf();
+    Task::perform(
+        async move { plaintext::scan(&root) },
+        FileAction::PlaintextScanned,
+    )
+}
+
+/// Warn about unencrypted files in the store that are not on the allowlist.
+fn warn_plaintext(state: &mut State, files: Vec<PathBuf>) {
+    let root = state.tree.root();
+    if files.is_empty() {
+        return;
+    }
+    let details = files
+        .iter()
+        .map(|f| f.strip_prefix(root).unwrap_or(f).display().to_string())
+        .collect();
+    state.summary_popup = Some(summary_popup::State::new(
+        "Unencrypted Files",
+        format!(
+            "{} files in the store are not encrypted. Encrypt them from their \
+             context menu or list them in {} if they are meant to be plaintext:",
+            files.len(),
+            plaintext::ALLOWLIST_FILE
+        ),
+        details,
+    ));
+}
+
+/// Replace a plaintext file with an encrypted secret in the background.
+fn encrypt_item(state: &mut State, path: &Path) -> Task<FileAction> {
+    if state.tabs.iter().any(|tab| tab.path == path && tab.dirty) {
+        state.error_popup = Some(error_popup::State::new(
+            "Encryption Error",
+            "Save or discard your changes to this file before encrypting it.",
+        ));
+        return Task::none();
+    }
+
+    let (root, path) = (state.tree.root().to_path_buf(), path.to_path_buf());
+    Task::perform(
+        async move {
+            let result = plaintext::encrypt_in_place(&root, &path).map_err(|e| e.to_string());
+            (path, result)
+        },
+        |(path, result)| FileAction::ItemEncrypted(path, result),
+    )
+}
+
+/// Select the secret a plaintext file was encrypted into.
+fn item_encrypted(
+    state: &mut State,
+    path: PathBuf,
+    result: Result<PathBuf, String>,
+) -> Task<FileAction> {
+    match result {
+        Ok(target) => {
+            close_tab(state, &path);
+            state.selected.remove(&path);
+            state.selected.insert(target);
+            reload_tree(state)
+        }
+        Err(e) => {
+            error!("Failed to encrypt '{}': {}", path.display(), e);
+            state.error_popup = Some(error_popup::State::new(
+                "Encryption Error",
+                format!("Could not encrypt the file: {}", e),
+            ));
+            Task::none()
+        }
+    }
+}
+
+fn file_list_id() -> scrollable::Id {
+    scrollable::Id::new("file-list")
+}
+
+fn rebuild_rows(state: &mut State) -> Task<FileAction> {
+    state.rows = state.tree.visible_rows(&state.expanded);
+    update_locked(state)
+}
+
+/// Whether none of the user's secret keys is a recipient of the secret at
+/// `path`. Secrets with hidden or unknown recipients are not locked.
+fn is_locked(secret_key_ids: &HashSet<u64>, path: &Path) -> bool {
+    packets::file_recipients(path).is_some_and(|ids| {
+        !ids.is_empty()
+            && !ids.contains(&packets::WILDCARD_KEY_ID)
+            && !ids.iter().any(|id| secret_key_ids.contains(id))
+    })
+}
+
+/// Check the recipients of visible secrets that are new or changed on disk
+/// in the background.
+fn update_locked(state: &State) -> Task<FileAction> {
+    let Some(secret_key_ids) = state.secret_key_ids.clone() else {
+        return Task::none();
+    };
+    let visible: Vec<(PathBuf, Option<SystemTime>)> = state
+        .rows
+        .iter()
+        .filter(|row| !row.is_dir && editor::is_secret(&row.path))
+        .map(|row| {
+            let checked = state.locked.get(&row.path).map(|(checked, _)| *checked);
+            (row.path.clone(), checked)
+        })
+        .collect();
+    if visible.is_empty() {
+        return Task::none();
+    }
+
+    Task::perform(
+        async move {
+            visible
+                .into_iter()
+                .filter_map(|(path, checked)| {
+                    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                    if checked == Some(modified) {
+                        return None;
+                    }
+                    let locked = is_locked(&secret_key_ids, &path);
+                    Some((path, (modified, locked)))
+                })
+                .collect()
+        },
+        FileAction::LockedChecked,
+    )
+}
+
+fn active_tab_mut(state: &mut State) -> Option<&mut editor::Tab> {
+    state.active_tab.and_then(|i| state.tabs.get_mut(i))
+}
+
+/// Whether `signer` is one of the recipients the secret at `path` should
+/// be encrypted to.
+fn is_team_member(root: &Path, path: &Path, signer: &crypto::Signer) -> bool {
+    let fingerprint = &signer.fingerprint;
+    let Ok(key_id) = u64::from_str_radix(&fingerprint[fingerprint.len().saturating_sub(16)..], 16)
+    else {
+        return false;
+    };
+    recipients::resolve(path, root).is_ok_and(|recipients| {
+        recipients.iter().any(|recipient| {
+            crypto::recipient_key_ids(recipient).is_ok_and(|ids| ids.contains(&key_id))
+        })
+    })
+}
+
+/// Classify the signer of the secret at `path`.
+fn check_signature(root: &Path, path: &Path, signer: Option<crypto::Signer>) -> editor::Signature {
+    let signature = match signer {
+        None => editor::Signature::Unsigned,
+        Some(signer) if !signer.valid => editor::Signature::Invalid(signer),
+        Some(signer) if is_team_member(root, path, &signer) => editor::Signature::Team(signer),
+        Some(signer) => editor::Signature::Outsider(signer),
+    };
+    if !matches!(signature, editor::Signature::Team(_)) {
+        warn!("Signature of '{}': {:?}", path.display(), signature);
+    }
+    signature
+}
+
+/// Read a file for the editor, decrypting it and checking its signature if
+/// it is a secret. Secrets that are not valid UTF-8 are opened as binary
+/// secrets.
+///
+/// Runs in the background, as decryption may wait for a passphrase.
+fn load_file(root: &Path, path: &Path) -> Result<editor::LoadedFile, String> {
+    // Check if this is an encrypted file
+    if editor::is_secret(path) {
+        debug!("File is encrypted, attempting decryption");
+
+        let encrypted_data = fs::read(path).map_err(|e| {
+            error!("Failed to read encrypted file '{}': {}", path.display(), e);
+            format!("Could not read file: {}", e)
+        })?;
+        let backend = crypto::backend(path);
+        let decrypted = if backend.supports_signatures() {
+            crypto::decrypt_verified(&encrypted_data)
+                .map(|(plaintext, signer)| (plaintext, Some(check_signature(root, path, signer))))
+        } else {
+            backend
+                .decrypt(&encrypted_data)
+                .map(|plaintext| (plaintext, None))
+        };
+        let (plaintext, signature) = decrypted.map_err(|e| {
+            error!("Failed to decrypt file '{}': {}", path.display(), e);
+            format!("Failed to decrypt file: {}", e)
+        })?;
+        debug!("Successfully decrypted file '{}'", path.display());
+        return Ok(editor::LoadedFile {
+            data: editor::FileData::from_bytes(&plaintext),
+            signature,
+        });
+    }
+
+    // Otherwise, this is a regular file
+    fs::read_to_string(path)
+        .map(|content| editor::LoadedFile {
+            data: editor::FileData::Text(Zeroizing::new(content)),
+            signature: None,
+        })
+        .map_err(|e| {
+            error!("Failed to read file '{}': {}", path.display(), e);
+            format!("Failed to read file: {}", e)
+        })
+}
+
+/// Load `path` in the background, delivering the result as `FileAction::Loaded`.
+fn load_task(root: PathBuf, path: PathBuf) -> Task<FileAction> {
+    Task::perform(
+        async move {
+            let result = load_file(&root, &path);
+            (path, result)
+        },
+        |(path, result)| FileAction::Loaded(path, result),
+    )
+}
+
+/// Focus the tab of `path`, opening it in a new tab once it is loaded if it
+/// is not open yet. Selecting another file cancels a load in progress.
+fn open_file_in_editor(state: &mut State, path: &Path) -> Task<FileAction> {
+    if let Some(index) = state.tabs.iter().position(|tab| tab.path == path) {
+        debug!("File '{}' is already open in tab {}", path.display(), index);
+        state.active_tab = Some(index);
+        state.loading = None;
+        return Task::none();
+    }
+    if state.loading.as_ref().is_some_and(|l| l.path == path) {
+        return Task::none();
+    }
+    if state.locked.get(path).is_some_and(|(_, locked)| *locked) {
+        let recipients = packets::file_recipients(path)
+            .unwrap_or_default()
+            .iter()
+            .map(|id| format!("{:016X}", id))
+            .collect();
+        state.summary_popup = Some(summary_popup::State::new(
+            "Locked Secret",
+            "This secret is not encrypted to any of your keys, so it cannot be \
+             decrypted here. It is encrypted to:",
+            recipients,
+        ));
+        return Task::none();
+    }
+
+    debug!("Opening file in editor: {}", path.display());
+    let (root, target) = (state.tree.root().to_path_buf(), path.to_path_buf());
+    let (task, job) = job::spawn(|_| {
+        iced::futures::stream::once(async move {
+            let result = load_file(&root, &target);
+            FileAction::Loaded(target, result)
+        })
+    });
+    state.loading = Some(Loading {
+        path: path.to_path_buf(),
+        _job: job,
+    });
+    task
+}
+
+/// Show a file loaded in the background: in a new tab if it was being
+/// opened, or in place of its tab if that has no unsaved changes.
+fn file_loaded(state: &mut State, path: PathBuf, result: Result<editor::LoadedFile, String>) {
+    let opening = state.loading.as_ref().is_some_and(|l| l.path == path);
+    if opening {
+        state.loading = None;
+    }
+    let loaded = match result {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            state.error_popup = Some(error_popup::State::new("Open Error", e));
+            return;
+        }
+    };
+
+    match state.tabs.iter().position(|tab| tab.path == path) {
+        Some(index) if !state.tabs[index].dirty => {
+            let revealed = state.tabs[index].revealed;
+            let mut tab = editor::Tab::from_loaded(path, loaded);
+            tab.revealed = revealed;
+            state.tabs[index] = tab;
+        }
+        Some(_) => debug!("Not reloading '{}' with unsaved changes", path.display()),
+        None if opening => {
+            state.tabs.push(editor::Tab::from_loaded(path, loaded));
+            state.active_tab = Some(state.tabs.len() - 1);
+        }
+        None => {}
+    }
+}
+
+fn close_tab(state: &mut State, path: &PathBuf) {
+    let Some(index) = state.tabs.iter().position(|tab| &tab.path == path) else {
+        return;
+    };
+    debug!("Closing tab for '{}'", path.display());
+    state.tabs.remove(index);
+
+    state.active_tab = match state.active_tab {
+        _ if state.tabs.is_empty() => None,
+        Some(active) if active > index => Some(active - 1),
+        Some(active) => Some(active.min(state.tabs.len() - 1)),
+        None => None,
+    };
+}
+
+fn close_all_tabs(state: &mut State) {
+    debug!("Closing all {} tabs", state.tabs.len());
+    state.tabs.clear();
+    state.active_tab = None;
+}
+
+/// Write `text` to `path`, encrypting it to the recipients of the nearest
+/// recipients file if it is a secret.
+fn write_file(root: &Path, path: &Path, text: &str) -> Result<(), String> {
+    let data = if editor::is_secret(path) {
+        let recipients = recipients::resolve(path, root).map_err(|e| {
+            error!(
+                "Failed to resolve recipients for '{}': {}",
+                path.display(),
+                e
+            );
+            format!("Could not determine recipients: {}", e)
+        })?;
+        crypto::backend(path)
+            .encrypt(text.as_bytes(), &recipients)
+            .map_err(|e| {
+                error!("Failed to encrypt file '{}': {}", path.display(), e);
+                format!("Failed to encrypt file: {}", e)
+            })?
+    } else {
+        text.as_bytes().to_vec()
+    };
+
+    fs::write(path, data).map_err(|e| {
+        error!("Failed to write file '{}': {}", path.display(), e);
+        format!("Could not write file: {}", e)
+    })?;
+    debug!("Saved '{}'", path.display());
+    Ok(())
+}
+
+/// Save a tab in the background. A tab that is already being saved is saved
+/// again once that save is done, so that an older write never finishes last.
+fn save_tab(state: &mut State, index: usize) -> Task<FileAction> {
+    let Some(tab) = state.tabs.get(index) else {
+        return Task::none();
+    };
+    // Binary secrets are only replaced as a whole, see `replace_with_file`
+    if tab.binary.is_some() {
+        return Task::none();
+    }
+    if let Some(again) = state.saving.get_mut(&tab.path) {
+        debug!(
+            "'{}' is being saved, saving it again after",
+            tab.path.display()
+        );
+        *again = true;
+        return Task::none();
+    }
+    state.saving.insert(tab.path.clone(), false);
+
+    let root = state.tree.root().to_path_buf();
+    let path = tab.path.clone();
+    let revision = tab.revision;
+    let text = Zeroizing::new(tab.content.text());
+    Task::perform(
+        async move {
+            let result = write_file(&root, &path, &text);
+            (path, result)
+        },
+        move |(path, result)| FileAction::Saved(path, revision, result),
+    )
+}
+
+/// Finish a background save. Starts the next save of the tab if it was saved
+/// again meanwhile, and runs the action waiting for the saves once all are done.
+fn saved(
+    state: &mut State,
+    path: PathBuf,
+    revision: u64,
+    result: Result<(), String>,
+) -> Task<FileAction> {
+    let again = state.saving.remove(&path).unwrap_or(false);
+    let index = state.tabs.iter().position(|tab| tab.path == path);
+    match result {
+        Ok(()) => {
+            if let Some(tab) = index.map(|index| &mut state.tabs[index]) {
+                // Edits made while saving still need to be saved
+                if tab.revision == revision {
+                    tab.dirty = false;
+                }
+                // The file is no longer the one whose signature was checked
+                tab.signature = None;
+            }
+        }
+        Err(e) => {
+            state.error_popup = Some(error_popup::State::new("Save Error", e));
+            // Changes that could not be saved must not be discarded
+            if let Some(pending) = state.after_save.take() {
+                debug!("Cancelled {:?}", pending);
+            }
+        }
+    }
+
+    if let Some(index) = index.filter(|index| again && state.tabs[*index].dirty) {
+        return save_tab(state, index);
+    }
+    if state.saving.is_empty() {
+        if let Some(pending) = state.after_save.take() {
+            return guard_unsaved(state, pending);
+        }
+    }
+    Task::none()
+}
+
+/// Indices of the tabs whose changes would be lost by `pending`.
+fn unsaved_tabs(state: &State, pending: &PendingAction) -> Vec<usize> {
+    state
+        .tabs
+        .iter()
+        .enumerate()
+        .filter(|(_, tab)| tab.dirty)
+        .filter(|(_, tab)| match pending {
+            PendingAction::CloseTab(path) => &tab.path == path,
+            PendingAction::Lock | PendingAction::Quit(_) => true,
+            PendingAction::ConfirmExport | PendingAction::Export(_) => false,
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Run `pending` right away, or ask the user first if it would discard unsaved changes.
+fn guard_unsaved(state: &mut State, pending: PendingAction) -> Task<FileAction> {
+    let unsaved = unsaved_tabs(state, &pending);
+    if unsaved.is_empty() {
+        return perform_pending(state, pending);
+    }
+
+    let message = match unsaved.as_slice() {
+        [index] => format!(
+            "Do you want to save the changes to '{}'?",
+            state.tabs[*index].title()
+        ),
+        _ => format!(
+            "Do you want to save the changes to {} files?",
+            unsaved.len()
+        ),
+    };
+    debug!(
+        "Unsaved changes in {} tabs, asking before {:?}",
+        unsaved.len(),
+        pending
+    );
+    state.confirm_popup = Some(
+        confirm_popup::State::new("Unsaved Changes", message, "Save").with_alternate("Discard"),
+    );
+    state.pending_action = Some(pending);
+    Task::none()
+}
+
+fn perform_pending(state: &mut State, pending: PendingAction) -> Task<FileAction> {
+    match pending {
+        PendingAction::CloseTab(path) => {
+            close_tab(state, &path);
+        }
+        PendingAction::Lock => {
+            debug!("Locking: closing all tabs");
+            close_all_tabs(state);
+            state.loading = None;
+            state.history = None;
+            state.merge = None;
+            state.import = None;
+            state.backup = None;
+            state.export = None;
+            state.recipients = None;
+            state.audit = None;
+            state.verify = None;
+            state.breached.clear();
+            state.search = None;
+            state.selected.clear();
+            return Task::done(FileAction::Locked);
+        }
+        PendingAction::Quit(id) => {
+            debug!("Ready to close the window");
+            close_all_tabs(state);
+            state.history = None;
+            return Task::done(FileAction::ReadyToClose(id));
+        }
+        PendingAction::ConfirmExport => return pick_export_destination(state),
+        PendingAction::Export(destination) => {
+            if let Some(export_state) = &mut state.export {
+                return export_state.start(destination).map(FileAction::Export);
+            }
+        }
+    }
+    Task::none()
+}
+
+/// Ask where to write a plaintext export and count the secrets in the
+/// background for the second confirmation.
+fn pick_export_destination(state: &mut State) -> Task<FileAction> {
+    let Some(export_state) = &mut state.export else {
+        return Task::none();
+    };
+    let Some(destination) = export_state.pick_destination() else {
+        return Task::none();
+    };
+    if destination.starts_with(state.tree.root()) {
+        state.error_popup = Some(error_popup::State::new(
+            "Export Error",
+            "Plaintext exports must be written outside of the store.",
+        ));
+        return Task::none();
+    }
+    export_state.count(destination).map(FileAction::Export)
+}
+
+/// Second confirmation of a plaintext export, once the destination is known.
+fn confirm_export_destination(state: &mut State, destination: PathBuf, count: usize) {
+    state.confirm_popup = Some(confirm_popup::State::new(
+        "Write Plaintext?",
+        format!(
+            "{} secrets will be written unencrypted to '{}'. Are you sure?",
+            count,
+            destination.display()
+        ),
+        "Write plaintext",
+    ));
+    state.pending_action = Some(PendingAction::Export(destination));
+}
+
+/// Show the outcome of a plaintext export.
+fn exported(state: &mut State, destination: &Path, result: Result<usize, String>) {
+    match result {
+        Ok(count) => {
+            debug!("Exported {} secrets", count);
+            state.export = None;
+            state.summary_popup = Some(summary_popup::State::new(
+                "Export Complete",
+                format!(
+                    "Exported {} secrets to '{}'. Delete the file once you are done.",
+                    count,
+                    destination.display()
+                ),
+                Vec::new(),
+            ));
+        }
+        Err(e) => {
+            error!("Failed to export secrets: {}", e);
+            state.error_popup = Some(error_popup::State::new(
+                "Export Error",
+                format!("Could not export the secrets: {}", e),
+            ));
+        }
+    }
+}
+
+/// Expand the ancestors of `path`, select it and scroll it into view.
+///
+/// Directories that have not been read yet are read in the background first.
+fn reveal_in_tree(state: &mut State, path: &Path) -> Task<FileAction> {
+    state.selected.clear();
+    state.selected.insert(path.to_path_buf());
+    let unloaded: Vec<PathBuf> = ancestors_in_tree(state, path)
+        .into_iter()
+        .filter(|dir| !state.tree.is_loaded(dir))
+        .collect();
+    if unloaded.is_empty() {
+        return show_in_tree(state, path);
+    }
+
+    let path = path.to_path_buf();
+    Task::perform(
+        async move {
+            let directories = unloaded
+                .into_iter()
+                .map(|dir| {
+                    let children = tree::read_children(&dir);
+                    (dir, children)
+                })
+                .collect();
+            (path, directories)
+        },
+        |(path, directories)| FileAction::AncestorsLoaded(path, directories),
+    )
+}
+
+/// The directories between the root and `path`, outermost first.
+fn ancestors_in_tree(state: &State, path: &Path) -> Vec<PathBuf> {
+    let root = state.tree.root();
+    let mut ancestors: Vec<PathBuf> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root) && *dir != root)
+        .map(Path::to_path_buf)
+        .collect();
+    ancestors.reverse();
+    ancestors
+}
+
+/// Expand the loaded ancestors of `path` and scroll it into view.
+fn show_in_tree(state: &mut State, path: &Path) -> Task<FileAction> {
+    for dir in ancestors_in_tree(state, path) {
+        state.expanded.insert(dir);
+    }
+    let locked = rebuild_rows(state);
+
+    let Some(index) = state.rows.iter().position(|row| row.path == path) else {
+        return locked;
+    };
+    let offset = index as f32 * ROW_HEIGHT - state.viewport_height / 2.0;
+    let scroll = scrollable::scroll_to(
+        file_list_id(),
+        scrollable::AbsoluteOffset {
+            x: 0.0,
+            y: offset.max(0.0),
+        },
+    );
+    Task::batch([locked, scroll])
+}
+
+/// List the commits touching `path` in the background.
+fn show_history(state: &State, path: &Path) -> Task<FileAction> {
+    let (root, path) = (state.tree.root().to_path_buf(), path.to_path_buf());
+    Task::perform(
+        async move {
+            let result = if git::is_repository(&root) {
+                git::log(&root, &path).map_err(|e| format!("Could not read history: {}", e))
+            } else {
+                Err("The store is not a git repository.".to_string())
+            };
+            (path, result)
+        },
+        |(path, result)| FileAction::HistoryLoaded(path, result),
+    )
+}
+
+/// Re-encrypt a historical version to the current recipients and commit it
+/// as the new content of the secret.
+fn write_version(
+    root: &Path,
+    path: &Path,
+    commit: &str,
+    plaintext: Zeroizing<String>,
+) -> Result<Zeroizing<String>, String> {
+    let recipients = recipients::resolve(path, root).map_err(|e| e.to_string())?;
+    let ciphertext = crypto::backend(path)
+        .encrypt(plaintext.as_bytes(), &recipients)
+        .map_err(|e| e.to_string())?;
+    fs::write(path, ciphertext).map_err(|e| e.to_string())?;
+
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let message = format!("Restore {} to {}", relative.display(), commit);
+    git::commit_file(root, path, &message).map_err(|e| e.to_string())?;
+    debug!("Restored '{}' to {}", path.display(), commit);
+    Ok(plaintext)
+}
+
+/// Restore the version selected in the history viewer in the background.
+fn restore_version(state: &mut State) -> Task<FileAction> {
+    let Some(history_state) = &state.history else {
+        return Task::none();
+    };
+    let Some((commit, plaintext)) = history_state.selected_version() else {
+        return Task::none();
+    };
+    let path = history_state.path.clone();
+    let commit = commit.short_id.clone();
+
+    if state.tabs.iter().any(|tab| tab.path == path && tab.dirty) {
+        state.error_popup = Some(error_popup::State::new(
+            "Restore Error",
+            "Save or discard your changes to this secret before restoring an old version.",
+        ));
+        return Task::none();
+    }
+
+    let root = state.tree.root().to_path_buf();
+    Task::perform(
+        async move {
+            let result = write_version(&root, &path, &commit, plaintext);
+            (path, result)
+        },
+        |(path, result)| FileAction::VersionRestored(path, result),
+    )
+}
+
+/// Encrypt the file at `source` to the recipients of `target` and write it there.
+fn encrypt_file_into(
+    root: &Path,
+    source: &Path,
+    target: &Path,
+) -> Result<editor::FileData, String> {
+    let data = Zeroizing::new(fs::read(source).map_err(|e| e.to_string())?);
+    let recipients = recipients::resolve(target, root).map_err(|e| e.to_string())?;
+    let ciphertext = crypto::backend(target)
+        .encrypt(&data, &recipients)
+        .map_err(|e| e.to_string())?;
+    fs::write(target, ciphertext).map_err(|e| e.to_string())?;
+    Ok(editor::FileData::from_bytes(&data))
+}
+
+/// Decrypt the secret of the active tab to a file outside of the store.
+fn save_decrypted_copy(state: &mut State) -> Task<FileAction> {
+    let Some(tab) = state.active_tab.and_then(|i| state.tabs.get(i)) else {
+        return Task::none();
+    };
+    let path = tab.path.clone();
+    let name = path
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let Some(destination) = rfd::FileDialog::new().set_file_name(name).save_file() else {
+        return Task::none();
+    };
+    if destination.starts_with(state.tree.root()) {
+        state.error_popup = Some(error_popup::State::new(
+            "Save Error",
+            "Decrypted copies must be written outside of the store.",
+        ));
+        return Task::none();
+    }
+
+    // Binary tabs do not keep their content, so the secret is decrypted again
+    state.saving_copies.insert(path.clone());
+    Task::perform(
+        async move {
+            let result = fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| {
+                    crypto::backend(&path)
+                        .decrypt(&data)
+                        .map_err(|e| e.to_string())
+                })
+                .and_then(|plaintext| {
+                    export::create_private(&destination)
+                        .and_then(|mut file| file.write_all(&plaintext))
+                        .map_err(|e| e.to_string())
+                });
+            (path, destination, result)
+        },
+        |(path, destination, result)| FileAction::DecryptedCopySaved(path, destination, result),
+    )
+}
+
+/// Report the outcome of writing a decrypted copy.
+fn decrypted_copy_saved(
+    state: &mut State,
+    path: &Path,
+    destination: &Path,
+    result: Result<(), String>,
+) {
+    state.saving_copies.remove(path);
+    match result {
+        Ok(()) => debug!(
+            "Saved decrypted copy of '{}' to '{}'",
+            path.display(),
+            destination.display()
+        ),
+        Err(e) => {
+            error!(
+                "Failed to save decrypted copy of '{}': {}",
+                path.display(),
+                e
+            );
+            state.error_popup = Some(error_popup::State::new(
+                "Save Error",
+                format!("Could not save the decrypted copy: {}", e),
+            ));
+        }
+    }
+}
+
+/// Replace the secret of the active tab with the encrypted content of a file,
+/// in the background.
+fn replace_with_file(state: &State) -> Task<FileAction> {
+    let Some(tab) = state.active_tab.and_then(|i| state.tabs.get(i)) else {
+        return Task::none();
+    };
+    let (root, path) = (state.tree.root().to_path_buf(), tab.path.clone());
+    let Some(source) = rfd::FileDialog::new().pick_file() else {
+        return Task::none();
+    };
+
+    Task::perform(
+        async move {
+            let result = encrypt_file_into(&root, &source, &path);
+            if result.is_ok() {
+                debug!("Replaced '{}' with '{}'", path.display(), source.display());
+            }
+            (path, result)
+        },
+        |(path, result)| FileAction::Replaced(path, result),
+    )
+}
+
+/// Encrypt a file of any type into `dir` as a new secret, in the background.
+fn add_file(state: &mut State, dir: &Path) -> Task<FileAction> {
+    let Some(source) = rfd::FileDialog::new().pick_file() else {
+        return Task::none();
+    };
+    let name = source.file_name().unwrap_or_default().to_string_lossy();
+    let extension = crypto::store_backend(state.tree.root()).extension();
+    let target = dir.join(format!("{}.{}", name, extension));
+    if target.exists() {
+        state.error_popup = Some(error_popup::State::new(
+            "Encryption Error",
+            format!("'{}' already exists.", target.display()),
+        ));
+        return Task::none();
+    }
+
+    let root = state.tree.root().to_path_buf();
+    Task::perform(
+        async move {
+            let result = encrypt_file_into(&root, &source, &target).map(|_| ());
+            match &result {
+                Ok(()) => debug!("Added '{}' as '{}'", source.display(), target.display()),
+                Err(e) => error!("Failed to add '{}': {}", source.display(), e),
+            }
+            (target, result)
+        },
+        |(target, result)| FileAction::FileAdded(target, result),
+    )
+}
+
+/// Show a secret added with [`add_file`] in its directory and open it.
+fn file_added(state: &mut State, target: PathBuf, result: Result<(), String>) -> Task<FileAction> {
+    if let Err(e) = result {
+        state.error_popup = Some(error_popup::State::new(
+            "Encryption Error",
+            format!("Could not encrypt the file: {}", e),
+        ));
+        return Task::none();
+    }
+    let mut tasks = vec![reload_tree(state)];
+    if let Some(dir) = target.parent().filter(|dir| *dir != state.tree.root()) {
+        tasks.push(load_directory(dir.to_path_buf()));
+    }
+    tasks.push(open_file_in_editor(state, &target));
+    Task::batch(tasks)
+}
+
+/// Re-read open tabs without unsaved changes, e.g. after a pull changed them on disk.
+fn reload_clean_tabs(state: &State) -> Task<FileAction> {
+    Task::batch(
+        state
+            .tabs
+            .iter()
+            .filter(|tab| !tab.dirty && tab.path.is_file())
+            .map(|tab| load_task(state.tree.root().to_path_buf(), tab.path.clone())),
+    )
+}
+
+/// Pull remote changes in the background.
+fn sync(state: &mut State) -> Task<FileAction> {
+    if state.syncing {
+        return Task::none();
+    }
+
+    let root = state.tree.root().to_path_buf();
+    debug!("Pulling changes into '{}'", root.display());
+    state.syncing = true;
+    Task::perform(
+        async move {
+            if !git::is_repository(&root) {
+                let e = "The store is not a git repository.".to_string();
+                return (Err(e), Ok(Vec::new()));
+            }
+            let pulled = git::pull(&root).map_err(|e| format!("Could not pull changes: {}", e));
+            let conflicts = git::conflicted_files(&root).map_err(|e| e.to_string());
+            (pulled, conflicts)
+        },
+        |(pulled, conflicts)| FileAction::Synced(pulled, conflicts),
+    )
+}
+
+/// Show the changes of a pull and start resolving conflicts if there are any.
+fn synced(
+    state: &mut State,
+    pulled: Result<(), String>,
+    conflicts: Result<Vec<PathBuf>, String>,
+) -> Task<FileAction> {
+    state.syncing = false;
+    let reload = reload_tree(state);
+
+    match conflicts {
+        Ok(files) if !files.is_empty() => {
+            debug!("Pull left {} conflicting files", files.len());
+            state.conflicts = files;
+            return Task::batch([reload, open_next_conflict(state)]);
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to list conflicting files: {}", e),
+    }
+
+    match pulled {
+        Ok(()) => {
+            return Task::batch([reload, scan_plaintext(state), reload_clean_tabs(state)]);
+        }
+        Err(e) => {
+            error!("Failed to pull: {}", e);
+            state.error_popup = Some(error_popup::State::new("Sync Error", e));
+        }
+    }
+    reload
+}
+
+/// Read the next conflicting file in the background to show it in the merge
+/// view, or commit the merge once every conflict is resolved.
+fn open_next_conflict(state: &mut State) -> Task<FileAction> {
+    let root = state.tree.root().to_path_buf();
+    let Some(path) = state.conflicts.first().cloned() else {
+        state.merge = None;
+        debug!("All conflicts resolved, committing merge");
+        return Task::perform(
+            async move { git::commit_merge(&root).map_err(|e| e.to_string()) },
+            FileAction::MergeCommitted,
+        );
+    };
+
+    Task::perform(
+        async move {
+            let result = conflict::read_sides(&root, &path);
+            (path, result)
+        },
+        |(path, result)| FileAction::ConflictLoaded(path, result),
+    )
+}
+
+/// Show a conflicting file read by [`open_next_conflict`] in the merge view.
+fn conflict_loaded(state: &mut State, path: PathBuf, result: Result<conflict::Sides, String>) {
+    if state.conflicts.first() != Some(&path) {
+        return;
+    }
+    match result {
+        Ok(sides) => {
+            let remaining = state.conflicts.len() - 1;
+            state.merge = Some(conflict::State::new(path, sides, remaining));
+        }
+        Err(e) => {
+            error!("Failed to load conflict in '{}': {}", path.display(), e);
+            state.merge = None;
+            state.error_popup = Some(error_popup::State::new(
+                "Merge Error",
+                format!("Could not load the conflicting versions: {}", e),
+            ));
+        }
+    }
+}
+
+fn write_resolution(root: &Path, path: &Path, text: &str) -> Result<(), String> {
+    let data = if editor::is_secret(path) {
+        let recipients = recipients::resolve(path, root).map_err(|e| e.to_string())?;
+        crypto::backend(path)
+            .encrypt(text.as_bytes(), &recipients)
+            .map_err(|e| e.to_string())?
+    } else {
+        text.as_bytes().to_vec()
+    };
+    fs::write(path, data).map_err(|e| e.to_string())?;
+    git::stage_file(root, path).map_err(|e| e.to_string())
+}
+
+/// Write the resolution of the current conflict in the background, encrypting
+/// it if the file is a secret, and mark it as resolved.
+fn resolve_conflict(state: &State) -> Task<FileAction> {
+    let Some(merge_state) = &state.merge else {
+        return Task::none();
+    };
+    let root = state.tree.root().to_path_buf();
+    let path = merge_state.path.clone();
+    let text = Zeroizing::new(merge_state.merged.text());
+
+    Task::perform(
+        async move {
+            let result = write_resolution(&root, &path, &text);
+            (path, result)
+        },
+        |(path, result)| FileAction::ConflictResolved(path, result),
+    )
+}
+
+/// Continue with the next conflict once a resolution is written.
+fn conflict_resolved(
+    state: &mut State,
+    path: PathBuf,
+    result: Result<(), String>,
+) -> Task<FileAction> {
+    // The resolution may have been saved twice
+    if !state.conflicts.contains(&path) {
+        return Task::none();
+    }
+    match result {
+        Ok(()) => {
+            debug!("Resolved conflict in '{}'", path.display());
+            state.conflicts.retain(|p| p != &path);
+            open_next_conflict(state)
+        }
+        Err(e) => {
+            error!("Failed to resolve conflict in '{}': {}", path.display(), e);
+            state.error_popup = Some(error_popup::State::new(
+                "Merge Error",
+                format!("Could not save the resolution: {}", e),
+            ));
+            Task::none()
+        }
+    }
+}
+
+pub fn update(state: &mut State, action: FileAction) -> Task<FileAction> {
+    match action {
+        FileAction::Select(path) => {
+            if !state.selected.contains(&path) {
+                debug!("Path '{}' is selected", path.display());
+                state.selected.clear();
+                state.selected.insert(path.clone());
+            }
+
+            if state.tree.is_dir(&path) {
+                if state.expanded.contains(&path) {
+                    debug!("Directory '{}' is collapsed", path.display());
+                    state.expanded.remove(&path);
+                } else if !state.tree.is_loaded(&path) {
+                    debug!("Reading directory '{}'", path.display());
+                    return load_directory(path);
+                } else {
+                    debug!("Directory '{}' is expanded", path.display());
+                    state.expanded.insert(path);
+                }
+                return rebuild_rows(state);
+            } else {
+                return open_file_in_editor(state, &path);
+            }
+        }
+        FileAction::ContextMenu(path) => {
+            debug!(
+                "Context menu opened for {} '{}' at position ({}, {})",
+                if state.tree.is_dir(&path) {
+                    "directory"
+                } else {
+                    "secret"
+                },
+                path.display(),
+                state.cursor_position.x,
+                state.cursor_position.y
+            );
+            state.context_menu = Some(ContextMenuState {
+                target_path: path,
+                position: state.cursor_position,
+            });
+        }
+        FileAction::CloseContextMenu => {
+            debug!("Context menu closed");
+            state.context_menu = None;
+        }
+        FileAction::EditItem(path) => {
+            debug!("Edit secret: {}", path.display());
+            state.context_menu = None;
+            return open_file_in_editor(state, &path);
+        }
+        FileAction::SelectTab(index) => {
+            if index < state.tabs.len() {
+                state.active_tab = Some(index);
+            }
+        }
+        FileAction::CloseTab(index) => {
+            if let Some(tab) = state.tabs.get(index) {
+                let path = tab.path.clone();
+                return guard_unsaved(state, PendingAction::CloseTab(path));
+            }
+        }
+        FileAction::NextTab => {
+            if let Some(active) = state.active_tab {
+                state.active_tab = Some((active + 1) % state.tabs.len());
+            }
+        }
+        FileAction::PreviousTab => {
+            if let Some(active) = state.active_tab {
+                state.active_tab = Some((active + state.tabs.len() - 1) % state.tabs.len());
+            }
+        }
+        FileAction::ToggleReveal => {
+            if let Some(tab) = active_tab_mut(state) {
+                tab.revealed = !tab.revealed;
+                debug!(
+                    "Secret '{}' is {}",
+                    tab.path.display(),
+                    if tab.revealed { "revealed" } else { "masked" }
+                );
+            }
+        }
+        FileAction::SaveEditor => {
+            if let Some(index) = state.active_tab {
+                return save_tab(state, index);
+            }
+        }
+        FileAction::Loaded(path, result) => file_loaded(state, path, result),
+        FileAction::CancelLoading => {
+            debug!("Cancelled opening a file");
+            state.loading = None;
+        }
+        FileAction::Saved(path, revision, result) => return saved(state, path, revision, result),
+        FileAction::AncestorsLoaded(path, directories) => {
+            // Outermost first, so that each directory is in the tree when
+            // its children are set
+            for (dir, children) in directories {
+                state.tree.set_children(&dir, children);
+            }
+            return show_in_tree(state, &path);
+        }
+        FileAction::DirectoryLoaded(path, children) => {
+            debug!("Directory '{}' is expanded", path.display());
+            state.tree.set_children(&path, children);
+            state.expanded.insert(path);
+            return rebuild_rows(state);
+        }
+        FileAction::TreeReloaded(nodes) => {
+            state.tree.set_nodes(nodes);
+            return rebuild_rows(state);
+        }
+        FileAction::LockedChecked(checked) => state.locked.extend(checked),
+        FileAction::PlaintextScanned(files) => warn_plaintext(state, files),
+        FileAction::SecretKeysListed(result) => match result {
+            Ok(ids) => {
+                state.secret_key_ids = Some(ids);
+                return update_locked(state);
+            }
+            Err(e) => error!("Failed to list secret keys: {}", e),
+        },
+        FileAction::EditorAction(action) => {
+            if let Some(tab) = active_tab_mut(state) {
+                tab.perform(action);
+            }
+        }
+        FileAction::Undo => {
+            if let Some(tab) = active_tab_mut(state) {
+                if !tab.undo() {
+                    trace!("Nothing to undo in '{}'", tab.path.display());
+                }
+            }
+        }
+        FileAction::Redo => {
+            if let Some(tab) = active_tab_mut(state) {
+                if !tab.redo() {
+                    trace!("Nothing to redo in '{}'", tab.path.display());
+                }
+            }
+        }
+        FileAction::ShowHistory(path) => {
+            debug!("Show history of '{}'", path.display());
+            state.context_menu = None;
+            return show_history(state, &path);
+        }
+        FileAction::HistoryLoaded(path, result) => match result {
+            Ok(commits) => state.history = Some(history::State::new(path, commits)),
+            Err(e) => {
+                error!("Failed to read history of '{}': {}", path.display(), e);
+                state.error_popup = Some(error_popup::State::new("History Error", e));
+            }
+        },
+        FileAction::History(msg) => match msg {
+            history::Message::Select(index) => {
+                if let Some(history_state) = &mut state.history {
+                    return history_state
+                        .select(state.tree.root(), index)
+                        .map(FileAction::History);
+                }
+            }
+            history::Message::Decrypted(index, result) => {
+                let Some(history_state) = &mut state.history else {
+                    return Task::none();
+                };
+                if let Err(e) = history_state.decrypted(index, result) {
+                    error!("Failed to load version: {}", e);
+                    state.error_popup = Some(error_popup::State::new(
+                        "History Error",
+                        format!("Could not load this version: {}", e),
+                    ));
+                }
+            }
+            history::Message::Restore => return restore_version(state),
+            history::Message::Close => {
+                debug!("History closed");
+                state.history = None;
+            }
+        },
+        FileAction::VersionRestored(path, result) => match result {
+            Ok(plaintext) => {
+                if let Some(tab) = state.tabs.iter_mut().find(|tab| tab.path == path) {
+                    *tab = editor::Tab::new(path.clone(), &plaintext);
+                }
+                return show_history(state, &path);
+            }
+            Err(e) => {
+                error!("Failed to restore '{}': {}", path.display(), e);
+                state.error_popup = Some(error_popup::State::new(
+                    "Restore Error",
+                    format!("Could not restore this version: {}", e),
+                ));
+            }
+        },
+        FileAction::Sync => return sync(state),
+        FileAction::Synced(pulled, conflicts) => return synced(state, pulled, conflicts),
+        FileAction::ConflictLoaded(path, result) => conflict_loaded(state, path, result),
+        FileAction::ConflictResolved(path, result) => {
+            return conflict_resolved(state, path, result);
+        }
+        FileAction::MergeCommitted(result) => {
+            if let Err(e) = result {
+                error!("Failed to commit merge: {}", e);
+                state.error_popup = Some(error_popup::State::new(
+                    "Merge Error",
+                    format!("Could not commit the merge: {}", e),
+                ));
+            }
+            return Task::batch([reload_tree(state), reload_clean_tabs(state)]);
+        }
+        FileAction::MergeAborted(result) => {
+            if let Err(e) = result {
+                error!("Failed to abort merge: {}", e);
+                state.error_popup = Some(error_popup::State::new(
+                    "Merge Error",
+                    format!("Could not abort the merge: {}", e),
+                ));
+            }
+            state.merge = None;
+            state.conflicts.clear();
+            return reload_tree(state);
         }
-    }
-}
-
-fn open_file_in_editor(state: &mut State, path: &PathBuf) {
-    debug!("Opening file in editor: {}", path.display());
-
-    // Check if this is an encrypted file
-    if path.extension().is_some_and(|ext| ext == "gpg") {
-        debug!("File is encrypted, attempting decryption");
+        FileAction::Conflict(msg) => match msg {
+            conflict::Message::Save => return resolve_conflict(state),
+            conflict::Message::Abort => {
+                let Some(merge_state) = &mut state.merge else {
+                    return Task::none();
+                };
+                debug!("Aborting merge");
+                merge_state.update(conflict::Message::Abort);
+                let root = state.tree.root().to_path_buf();
+                return Task::perform(
+                    async move { git::abort_merge(&root).map_err(|e| e.to_string()) },
+                    FileAction::MergeAborted,
+                );
+            }
+            msg => {
+                if let Some(merge_state) = &mut state.merge {
+                    merge_state.update(msg);
+                }
+            }
+        },
+        FileAction::ShowImport(path) => {
+            debug!("Import into '{}'", path.display());
+            state.context_menu = None;
+            let extension = crypto::store_backend(state.tree.root()).extension();
+            state.import = Some(import::State::new(path, extension));
+        }
+        FileAction::Import(msg) => match msg {
+            import::Message::Open => {
+                if let Some(import_state) = &mut state.import {
+                    return import_state.open().map(FileAction::Import);
+                }
+            }
+            import::Message::Opened(file, result) => {
+                if let Some(import_state) = &mut state.import {
+                    if let Err(e) = import_state.opened(&file, result) {
+                        error!("Failed to read import file: {}", e);
+                        state.error_popup = Some(error_popup::State::new(
+                            "Import Error",
+                            format!("Could not read the file: {}", e),
+                        ));
+                    }
+                }
+            }
+            import::Message::Import => {
+                let root = state.tree.root().to_path_buf();
+                if let Some(import_state) = &mut state.import {
+                    return import_state.import(&root).map(FileAction::Import);
+                }
+            }
+            import::Message::Imported(summary) => {
+                state.import = None;
 
-        // Read the encrypted file
-        match fs::read(path) {
-            Ok(encrypted_data) => {
-                match crypto::decrypt(&encrypted_data) {
-                    Ok(plaintext) => {
-                        debug!("Successfully decrypted file '{}'", path.display());
-                        state.opened_file = Some(path.clone());
-                        state.editor_content = Some(text_editor::Content::with_text(&plaintext));
+                debug!(
+                    "Imported {} secrets, skipped {} items",
+                    summary.imported,
+                    summary.skipped.len()
+                );
+                if let Some(e) = &summary.error {
+                    error!("Failed to import secrets: {}", e);
+                    state.error_popup = Some(error_popup::State::new(
+                        "Import Error",
+                        format!("Could not import all secrets: {}", e),
+                    ));
+                }
+                let mut message = format!("Imported {} secrets.", summary.imported);
+                if !summary.skipped.is_empty() {
+                    message.push_str(&format!(" Skipped {} items:", summary.skipped.len()));
+                }
+                state.summary_popup = Some(summary_popup::State::new(
+                    "Import Complete",
+                    message,
+                    summary.skipped,
+                ));
+                return reload_tree(state);
+            }
+            import::Message::Close => {
+                debug!("Import closed");
+                state.import = None;
+            }
+            msg => {
+                if let Some(import_state) = &mut state.import {
+                    import_state.update(msg);
+                }
+            }
+        },
+        FileAction::ShowBackup => {
+            debug!("Show backup");
+            let (backup_state, task) = backup::State::new();
+            state.backup = Some(backup_state);
+            return task.map(FileAction::Backup);
+        }
+        FileAction::Backup(msg) => match msg {
+            backup::Message::Export => {
+                let root = state.tree.root().to_path_buf();
+                if let Some(backup_state) = &mut state.backup {
+                    return backup_state.export(&root).map(FileAction::Backup);
+                }
+            }
+            backup::Message::Exported(result) => {
+                if let Some(backup_state) = &mut state.backup {
+                    backup_state.update(backup::Message::Exported(result.clone()));
+                }
+                match result {
+                    Ok((destination, count)) => {
+                        debug!("Backed up {} secrets", count);
+                        state.backup = None;
+                        state.summary_popup = Some(summary_popup::State::new(
+                            "Backup Complete",
+                            format!(
+                                "Backed up {} secrets to '{}'.",
+                                count,
+                                destination.display()
+                            ),
+                            Vec::new(),
+                        ));
                     }
                     Err(e) => {
-                        error!("Failed to decrypt file '{}': {}", path.display(), e);
+                        error!("Failed to back up the store: {}", e);
                         state.error_popup = Some(error_popup::State::new(
-                            "Decryption Error",
-                            format!("Failed to decrypt file: {}", e),
+                            "Backup Error",
+                            format!("Could not back up the store: {}", e),
                         ));
                     }
                 }
             }
-            Err(e) => {
-                error!("Failed to read encrypted file '{}': {}", path.display(), e);
-                state.error_popup = Some(error_popup::State::new(
-                    "File Read Error",
-                    format!("Could not read file: {}", e),
-                ));
+            backup::Message::Restore => {
+                let root = state.tree.root().to_path_buf();
+                if let Some(backup_state) = &mut state.backup {
+                    return backup_state.restore(&root).map(FileAction::Backup);
+                }
             }
+            backup::Message::Restored(result) => {
+                if let Some(backup_state) = &mut state.backup {
+                    backup_state.update(backup::Message::Restored(result.clone()));
+                }
+                let reload = reload_tree(state);
+                match result {
+                    Ok(count) => {
+                        debug!("Restored {} secrets", count);
+                        state.backup = None;
+                        state.summary_popup = Some(summary_popup::State::new(
+                            "Restore Complete",
+                            format!("Restored {} secrets.", count),
+                            Vec::new(),
+                        ));
+                        return Task::batch([reload, reload_clean_tabs(state)]);
+                    }
+                    Err(e) => {
+                        error!("Failed to restore backup: {}", e);
+                        state.error_popup = Some(error_popup::State::new(
+                            "Restore Error",
+                            format!("Could not restore the backup: {}", e),
+                        ));
+                    }
+                }
+                return reload;
+            }
+            backup::Message::Close => {
+                debug!("Backup closed");
+                state.backup = None;
+            }
+            msg => {
+                if let Some(backup_state) = &mut state.backup {
+                    backup_state.update(msg);
+                }
+            }
+        },
+        FileAction::ShowExport(path) => {
+            debug!("Export '{}'", path.display());
+            state.context_menu = None;
+            state.export = Some(export::State::new(path));
         }
-        return;
-    }
-
-    // Otherwise, this is a regular file
-    match fs::read_to_string(path) {
-        Ok(content) => {
-            state.opened_file = Some(path.clone());
-            state.editor_content = Some(text_editor::Content::with_text(&content));
-        }
-        Err(e) => {
-            error!("Failed to read file '{}': {}", path.display(), e);
-            state.opened_file = None;
-            state.editor_content = None;
-            state.error_popup = Some(error_popup::State::new(
-                "Error",
-                format!("Failed to read file: {}", e),
-            ));
+        FileAction::Export(msg) => match msg {
+            export::Message::Export => {
+                state.confirm_popup = Some(confirm_popup::State::new(
+                    "Plaintext Export",
+                    "The export is not encrypted. Anyone who can read the file can read \
+                     every exported secret. Continue?",
+                    "Continue",
+                ));
+                state.pending_action = Some(PendingAction::ConfirmExport);
+            }
+            export::Message::Counted(destination, count) => {
+                if let Some(export_state) = &mut state.export {
+                    export_state.update(export::Message::Counted(destination.clone(), count));
+                    confirm_export_destination(state, destination, count);
+                }
+            }
+            export::Message::Finished(destination, result) => {
+                if let Some(export_state) = &mut state.export {
+                    export_state.update(export::Message::Finished(
+                        destination.clone(),
+                        result.clone(),
+                    ));
+                    exported(state, &destination, result);
+                }
+            }
+            export::Message::Close => {
+                debug!("Export closed");
+                state.export = None;
+            }
+            msg => {
+                if let Some(export_state) = &mut state.export {
+                    export_state.update(msg);
+                }
+            }
+        },
+        FileAction::ShowRecipients(dir) => {
+            debug!("Recipients of '{}'", dir.display());
+            state.context_menu = None;
+            let root = state.tree.root().to_path_buf();
+            let (recipients_state, task) = access::State::new(root, dir);
+            state.recipients = Some(recipients_state);
+            return task.map(FileAction::Recipients);
         }
-    }
-}
-
-pub fn update(state: &mut State, action: FileAction) {
-    match action {
-        FileAction::Select(path) => {
-            if path.is_dir() {
-                if state.expanded.contains(&path) {
-                    debug!("Directory '{}' is collapsed", path.display());
-                    state.expanded.remove(&path);
-                } else {
-                    debug!("Directory '{}' is expanded", path.display());
-                    state.expanded.insert(path.clone());
+        FileAction::Recipients(msg) => match msg {
+            access::Message::Save => {
+                if let Some(recipients_state) = &mut state.recipients {
+                    return recipients_state.save().map(FileAction::Recipients);
                 }
-            } else {
-                debug!("Opened '{}' in editor", path.display());
-                open_file_in_editor(state, &path);
             }
-
-            if !state.selected.contains(&path) {
-                debug!("Path '{}' is selected", path.display());
-                state.selected.clear();
-                state.selected.insert(path);
+            access::Message::Saved(Ok(summary)) => {
+                debug!("Re-encrypted {} secrets", summary.reencrypted);
+                state.recipients = None;
+                let locked = update_locked(state);
+                let mut message = format!("Re-encrypted {} secrets.", summary.reencrypted);
+                if !summary.failed.is_empty() {
+                    message.push_str(&format!(
+                        " Could not re-encrypt {} secrets:",
+                        summary.failed.len()
+                    ));
+                }
+                state.summary_popup = Some(summary_popup::State::new(
+                    "Recipients Updated",
+                    message,
+                    summary.failed,
+                ));
+                return locked;
+            }
+            access::Message::Close => {
+                debug!("Recipients closed");
+                state.recipients = None;
             }
+            msg => {
+                if let Some(recipients_state) = &mut state.recipients {
+                    recipients_state.update(msg);
+                }
+            }
+        },
+        FileAction::EncryptItem(path) => {
+            debug!("Encrypt '{}'", path.display());
+            state.context_menu = None;
+            return encrypt_item(state, &path);
         }
-        FileAction::ContextMenu(path) => {
-            debug!(
-                "Context menu opened for {} '{}' at position ({}, {})",
-                if path.is_dir() { "directory" } else { "secret" },
-                path.display(),
-                state.cursor_position.x,
-                state.cursor_position.y
-            );
-            state.context_menu = Some(ContextMenuState {
-                target_path: path,
-                position: state.cursor_position,
-            });
+        FileAction::ItemEncrypted(path, result) => return item_encrypted(state, path, result),
+        FileAction::SaveDecryptedCopy => return save_decrypted_copy(state),
+        FileAction::DecryptedCopySaved(path, destination, result) => {
+            decrypted_copy_saved(state, &path, &destination, result)
         }
-        FileAction::CloseContextMenu => {
-            debug!("Context menu closed");
+        FileAction::ReplaceWithFile => return replace_with_file(state),
+        FileAction::Replaced(path, result) => match result {
+            Ok(data) => {
+                if let Some(tab) = state.tabs.iter_mut().find(|tab| tab.path == path) {
+                    *tab = editor::Tab::from_data(path, data);
+                }
+            }
+            Err(e) => {
+                error!("Failed to replace '{}': {}", path.display(), e);
+                state.error_popup = Some(error_popup::State::new(
+                    "Encryption Error",
+                    format!("Could not replace the secret: {}", e),
+                ));
+            }
+        },
+        FileAction::AddFile(dir) => {
             state.context_menu = None;
+            return add_file(state, &dir);
         }
-        FileAction::EditItem(path) => {
-            debug!("Edit secret: {}", path.display());
-            open_file_in_editor(state, &path);
-            state.context_menu = None;
+        FileAction::FileAdded(target, result) => return file_added(state, target, result),
+        FileAction::ShowAudit => {
+            debug!("Show audit");
+            let root = state.tree.root().to_path_buf();
+            state.audit.get_or_insert_with(|| audit::State::new(root));
+            state.audit_visible = true;
+            state.search_visible = false;
+            state.verify_visible = false;
+        }
+        FileAction::Audit(msg) => match msg {
+            audit::Message::Start => {
+                if let Some(audit_state) = &mut state.audit {
+                    return audit_state.start().map(FileAction::Audit);
+                }
+            }
+            audit::Message::Open(path) => {
+                debug!("Show audit finding '{}'", path.display());
+                state.audit_visible = false;
+                state.history = None;
+                let task = reveal_in_tree(state, &path);
+                return Task::batch([task, open_file_in_editor(state, &path)]);
+            }
+            audit::Message::Finished(report) => {
+                if report.breach_checked {
+                    state.breached = report.breached().map(Path::to_path_buf).collect();
+                }
+                if let Some(audit_state) = &mut state.audit {
+                    audit_state.update(audit::Message::Finished(report));
+                }
+            }
+            audit::Message::Close => {
+                debug!("Audit closed");
+                state.audit = None;
+                state.audit_visible = false;
+            }
+            msg => {
+                if let Some(audit_state) = &mut state.audit {
+                    audit_state.update(msg);
+                }
+            }
+        },
+        FileAction::ShowVerify => {
+            debug!("Show store check");
+            let root = state.tree.root().to_path_buf();
+            state.verify.get_or_insert_with(|| fsck::State::new(root));
+            state.verify_visible = true;
+            state.audit_visible = false;
+            state.search_visible = false;
         }
-        FileAction::CloseEditor => {
-            debug!("Closing editor");
-            state.opened_file = None;
-            state.editor_content = None;
+        FileAction::Verify(msg) => match msg {
+            fsck::Message::Start => {
+                if let Some(verify_state) = &mut state.verify {
+                    return verify_state.start().map(FileAction::Verify);
+                }
+            }
+            fsck::Message::Fix(path) => {
+                if let Some(verify_state) = &mut state.verify {
+                    return verify_state.fix(path).map(FileAction::Verify);
+                }
+            }
+            fsck::Message::Open(path) => {
+                debug!("Show store check finding '{}'", path.display());
+                state.verify_visible = false;
+                state.history = None;
+                let task = reveal_in_tree(state, &path);
+                return Task::batch([task, open_file_in_editor(state, &path)]);
+            }
+            fsck::Message::Close => {
+                debug!("Store check closed");
+                state.verify = None;
+                state.verify_visible = false;
+            }
+            msg => {
+                let fixed = matches!(msg, fsck::Message::Fixed(_, Ok(())));
+                if let Some(verify_state) = &mut state.verify {
+                    verify_state.update(msg);
+                }
+                // The recipients of a re-encrypted secret changed
+                if fixed {
+                    return update_locked(state);
+                }
+            }
+        },
+        FileAction::ShowSearch => {
+            debug!("Show search");
+            let stores = state.search_stores.clone();
+            state
+                .search
+                .get_or_insert_with(|| search::State::new(stores));
+            state.search_visible = true;
+            state.audit_visible = false;
+            state.verify_visible = false;
         }
-        FileAction::EditorAction(action) => {
-            if let Some(ref mut content) = state.editor_content {
-                content.perform(action);
+        FileAction::Search(msg) => match msg {
+            search::Message::Start => {
+                if let Some(search_state) = &mut state.search {
+                    return search_state.start().map(FileAction::Search);
+                }
+            }
+            search::Message::Open(path) => {
+                debug!("Show search result '{}'", path.display());
+                state.search_visible = false;
+                state.history = None;
+                let task = reveal_in_tree(state, &path);
+                return Task::batch([task, open_file_in_editor(state, &path)]);
+            }
+            search::Message::Close => {
+                debug!("Search closed");
+                state.search = None;
+                state.search_visible = false;
             }
+            msg => {
+                if let Some(search_state) = &mut state.search {
+                    search_state.update(msg);
+                }
+            }
+        },
+        FileAction::LockStore => {
+            return guard_unsaved(state, PendingAction::Lock);
+        }
+        FileAction::CloseRequested(id) => {
+            return guard_unsaved(state, PendingAction::Quit(id));
         }
+        FileAction::Lock | FileAction::Locked | FileAction::ReadyToClose(_) => {}
         FileAction::DeleteItem(path) => {
-            if path.is_dir() {
+            if state.tree.is_dir(&path) {
                 debug!("Deleting directory: {}", path.display());
             } else {
                 debug!("Deleting secret: {}", path.display());
@@ -174,17 +1959,71 @@ pub fn update(state: &mut State, action: FileAction) {
             state.expanded.remove(&path);
             // Close context menu
             state.context_menu = None;
+            return rebuild_rows(state);
         }
         FileAction::CursorMoved(position) => {
             state.cursor_position = position;
         }
+        FileAction::Scrolled(viewport) => {
+            state.scroll_offset = viewport.absolute_offset().y;
+            state.viewport_height = viewport.bounds().height;
+        }
         FileAction::ErrorPopup(msg) => match msg {
             error_popup::Message::Dismiss => {
                 debug!("Error popup dismissed");
                 state.error_popup = None;
             }
         },
+        FileAction::SummaryPopup(msg) => match msg {
+            summary_popup::Message::Dismiss => {
+                debug!("Summary popup dismissed");
+                state.summary_popup = None;
+            }
+        },
+        FileAction::ConfirmPopup(msg) => {
+            state.confirm_popup = None;
+            let Some(pending) = state.pending_action.take() else {
+                return Task::none();
+            };
+            match msg {
+                confirm_popup::Message::Confirm => {
+                    let unsaved = unsaved_tabs(state, &pending);
+                    if unsaved.is_empty() {
+                        return perform_pending(state, pending);
+                    }
+                    // Runs once the tabs are saved, see `saved`
+                    state.after_save = Some(pending);
+                    let mut saves = Vec::new();
+                    for index in unsaved {
+                        saves.push(save_tab(state, index));
+                    }
+                    return Task::batch(saves);
+                }
+                confirm_popup::Message::Alternate => {
+                    debug!("Discarding unsaved changes");
+                    return perform_pending(state, pending);
+                }
+                confirm_popup::Message::Cancel => {
+                    debug!("Cancelled {:?}", pending);
+                }
+            }
+        }
     }
+    Task::none()
+}
+
+/// Keyboard shortcuts of the file explorer.
+pub fn subscription(_state: &State) -> Subscription<FileAction> {
+    keyboard::on_key_press(|key, modifiers| match key.as_ref() {
+        keyboard::Key::Named(keyboard::key::Named::Tab) if modifiers.control() => {
+            if modifiers.shift() {
+                Some(FileAction::PreviousTab)
+            } else {
+                Some(FileAction::NextTab)
+            }
+        }
+        _ => None,
+    })
 }
 
 fn create_row<'a>(
@@ -193,12 +2032,14 @@ fn create_row<'a>(
     indent_level: u16,
     is_directory: bool,
     is_selected: bool,
+    is_breached: bool,
+    is_locked: bool,
 ) -> Element<'a, FileAction> {
     let indent = widget::Space::with_width(indent_width * indent_level);
 
     let asset = if is_directory {
         assets::FOLDER_LOGO
-    } else if path.extension().is_some_and(|ext| ext == "gpg") {
+    } else if editor::is_secret(&path) {
         assets::SECRET_LOGO
     } else {
         assets::FILE_LOGO
@@ -208,8 +2049,26 @@ fn create_row<'a>(
     let filename = path.file_name().unwrap_or_default();
     let icon = widget::svg(asset).width(20);
     let space = widget::Space::with_width(10);
+    let badge = is_breached.then(|| {
+        widget::svg(widget::svg::Handle::from_memory(assets::WARNING_LOGO))
+            .width(14)
+            .style(|_theme, _status| widget::svg::Style {
+                color: Some(iced::Color::from_rgb(0.85, 0.45, 0.0)),
+            })
+    });
+    let locked_badge = is_locked.then(|| {
+        widget::svg(widget::svg::Handle::from_memory(assets::LOCKED_LOGO))
+            .width(14)
+            .style(|_theme, _status| widget::svg::Style {
+                color: Some(iced::Color::from_rgb(0.5, 0.5, 0.5)),
+            })
+    });
     let text = widget::text!["{}", filename.display()].width(Length::Fill);
-    let row = widget::row![indent, icon, space, text]
+    let row = widget::row![indent, icon]
+        .push_maybe(badge)
+        .push_maybe(locked_badge)
+        .push(space)
+        .push(text)
         .align_y(iced::Alignment::Center)
         .width(Length::Fill);
 
@@ -233,6 +2092,7 @@ fn create_row<'a>(
                 _ => base,
             }
         })
+        .height(ROW_HEIGHT)
         .width(Length::Fill);
 
     widget::mouse_area(button)
@@ -240,107 +2100,250 @@ fn create_row<'a>(
         .into()
 }
 
-fn render_directory_contents(
-    path: &std::path::Path,
-    state: &State,
-    indent_level: u16,
-    indent_width: u16,
-    buttons: &mut Vec<Element<FileAction>>,
-) {
-    let entries = match fs::read_dir(path) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("Failed to read directory '{}': {}", path.display(), e);
-            return;
-        }
-    };
+/// Builds only the rows that intersect the viewport, padding the rest of the
+/// list with empty space so the scrollbar reflects the full tree.
+fn view_file_list(state: &State, indent_width: u16) -> Element<'_, FileAction> {
+    let total = state.rows.len();
+    let first = ((state.scroll_offset / ROW_HEIGHT) as usize)
+        .saturating_sub(OVERSCAN_ROWS)
+        .min(total);
+    let visible = (state.viewport_height / ROW_HEIGHT).ceil() as usize + 2 * OVERSCAN_ROWS;
+    let last = (first + visible).min(total);
 
-    for entry in entries {
-        let entry_path = match entry {
-            Ok(v) => v.path(),
-            Err(e) => {
-                error!(
-                    "Failed to get entry from directory '{}': {}",
-                    path.display(),
-                    e
-                );
-                continue;
+    let mut buttons: Vec<Element<FileAction>> = Vec::with_capacity(last - first + 2);
+    buttons.push(widget::Space::with_height(first as f32 * ROW_HEIGHT).into());
+    for row in &state.rows[first..last] {
+        buttons.push(create_row(
+            row.path.clone(),
+            indent_width,
+            row.depth,
+            row.is_dir,
+            state.selected.contains(&row.path),
+            state.breached.contains(&row.path),
+            state
+                .locked
+                .get(&row.path)
+                .is_some_and(|(_, locked)| *locked),
+        ));
+    }
+    buttons.push(widget::Space::with_height((total - last) as f32 * ROW_HEIGHT).into());
+
+    widget::Column::from_vec(buttons).width(Length::Fill).into()
+}
+
+/// Editor key bindings, adding undo (Ctrl+Z) and redo (Ctrl+Shift+Z or Ctrl+Y)
+/// to the defaults.
+fn editor_key_binding(
+    key_press: text_editor::KeyPress,
+) -> Option<text_editor::Binding<FileAction>> {
+    if key_press.status == text_editor::Status::Focused && key_press.modifiers.command() {
+        match key_press.key.as_ref() {
+            keyboard::Key::Character(c) if c.eq_ignore_ascii_case("z") => {
+                return Some(text_editor::Binding::Custom(
+                    if key_press.modifiers.shift() {
+                        FileAction::Redo
+                    } else {
+                        FileAction::Undo
+                    },
+                ));
             }
+            keyboard::Key::Character("y") => {
+                return Some(text_editor::Binding::Custom(FileAction::Redo));
+            }
+            _ => {}
+        }
+    }
+    text_editor::Binding::from_key_press(key_press)
+}
+
+fn view_tab_bar(state: &State) -> Element<'_, FileAction> {
+    let mut tab_bar = widget::row![].spacing(2);
+
+    for (index, tab) in state.tabs.iter().enumerate() {
+        let is_active = state.active_tab == Some(index);
+        let title = if tab.dirty {
+            format!("{} \u{25CF}", tab.title())
+        } else {
+            tab.title()
         };
 
-        if let Some(filename) = entry_path.file_name() {
-            // Skip hidden files (starting with .)
-            if filename.to_string_lossy().starts_with('.') {
-                continue;
-            }
+        let label = widget::button(widget::text(title).size(14))
+            .on_press(FileAction::SelectTab(index))
+            .style(widget::button::text);
+        let close_button = widget::button(widget::text("x").size(12))
+            .on_press(FileAction::CloseTab(index))
+            .style(widget::button::text);
+        let content = widget::row![label, close_button].align_y(iced::Alignment::Center);
 
-            trace!(
-                "Creating row for {} {} at indent level {}",
-                if entry_path.is_dir() {
-                    "directory"
+        let tab_container =
+            widget::container(content).style(move |theme: &iced::Theme| widget::container::Style {
+                background: if is_active {
+                    Some(iced::Background::Color(iced::Color::from_rgba(
+                        0.3, 0.5, 0.8, 0.3,
+                    )))
                 } else {
-                    "file"
+                    None
                 },
-                filename.display(),
-                indent_level
-            );
+                text_color: Some(theme.palette().text),
+                border: iced::Border {
+                    color: iced::Color::from_rgb(0.7, 0.7, 0.7),
+                    width: 1.0,
+                    radius: 4.0.into(),
+                },
+                ..Default::default()
+            });
 
-            let is_expanded = state.expanded.contains(&entry_path);
-            let is_selected = state.selected.contains(&entry_path);
-            let row = create_row(
-                entry_path.clone(),
-                indent_width,
-                indent_level,
-                entry_path.is_dir(),
-                is_selected,
-            );
-            buttons.push(row);
+        // Middle click closes the tab, like in a browser
+        tab_bar = tab_bar
+            .push(widget::mouse_area(tab_container).on_middle_press(FileAction::CloseTab(index)));
+    }
 
-            if entry_path.is_dir() {
-                // If directory is expanded, recursively render its contents
-                if is_expanded {
-                    render_directory_contents(
-                        &entry_path,
-                        state,
-                        indent_level + 1,
-                        indent_width,
-                        buttons,
-                    );
-                }
-            }
+    widget::scrollable(tab_bar)
+        .direction(scrollable::Direction::Horizontal(
+            scrollable::Scrollbar::new().width(4).scroller_width(4),
+        ))
+        .into()
+}
+
+/// Who signed the secret of a tab, with a warning for unsigned secrets and
+/// signers that are not recipients.
+fn view_signature(signature: &editor::Signature) -> Element<'_, FileAction> {
+    const GOOD: iced::Color = iced::Color::from_rgb(0.1, 0.55, 0.2);
+    const WARNING: iced::Color = iced::Color::from_rgb(0.85, 0.45, 0.0);
+    const BAD: iced::Color = iced::Color::from_rgb(0.7, 0.1, 0.1);
+
+    let (label, color) = match signature {
+        editor::Signature::Unsigned => ("Unsigned".to_string(), WARNING),
+        editor::Signature::Team(signer) if signer.trusted => {
+            (format!("Signed by {}", signer), GOOD)
         }
-    }
+        editor::Signature::Team(signer) => (format!("Signed by {}, key not trusted", signer), GOOD),
+        editor::Signature::Outsider(signer) => {
+            (format!("Signed by {}, not a recipient", signer), WARNING)
+        }
+        editor::Signature::Invalid(signer) => (format!("Signature by {} not valid", signer), BAD),
+    };
+    let icon = (color != GOOD).then(|| {
+        widget::svg(widget::svg::Handle::from_memory(assets::WARNING_LOGO))
+            .width(14)
+            .style(move |_theme, _status| widget::svg::Style { color: Some(color) })
+    });
+    widget::row![]
+        .push_maybe(icon)
+        .push(widget::text(label).size(12).color(color))
+        .spacing(4)
+        .align_y(iced::Alignment::Center)
+        .into()
+}
+
+/// Metadata of a binary secret in place of the editor.
+fn view_binary(info: &editor::BinaryInfo, saving: bool) -> Element<'_, FileAction> {
+    let save_label = if saving {
+        "Saving decrypted copy..."
+    } else {
+        "Save decrypted copy as..."
+    };
+    let save_button = widget::button(widget::text(save_label).size(14))
+        .on_press_maybe((!saving).then_some(FileAction::SaveDecryptedCopy));
+    let replace_button = widget::button(widget::text("Replace with file...").size(14))
+        .on_press(FileAction::ReplaceWithFile);
+    widget::column![
+        widget::text("This secret is a binary file and cannot be edited here.").size(14),
+        widget::text(format!("Type: {}", info.kind)).size(14),
+        widget::text(format!("Size: {}", info.size_text())).size(14),
+        widget::row![save_button, replace_button].spacing(8),
+    ]
+    .spacing(10)
+    .into()
 }
 
 fn view_editor_panel(state: &State) -> Element<'_, FileAction> {
     const CONTENT_PADDING: u16 = 10;
 
-    if let Some(file) = &state.opened_file {
-        let filename = file
+    if let Some(merge_state) = &state.merge {
+        return conflict::view(merge_state, FileAction::Conflict);
+    }
+    if let Some(import_state) = &state.import {
+        return import::view(import_state, FileAction::Import);
+    }
+    if let Some(export_state) = &state.export {
+        return export::view(export_state, FileAction::Export);
+    }
+    if let Some(backup_state) = &state.backup {
+        return backup::view(backup_state, FileAction::Backup);
+    }
+    if let Some(recipients_state) = &state.recipients {
+        return access::view(recipients_state, FileAction::Recipients);
+    }
+    if let Some(search_state) = state.search.as_ref().filter(|_| state.search_visible) {
+        return search::view(search_state, FileAction::Search);
+    }
+    if let Some(audit_state) = state.audit.as_ref().filter(|_| state.audit_visible) {
+        return audit::view(audit_state, FileAction::Audit);
+    }
+    if let Some(verify_state) = state.verify.as_ref().filter(|_| state.verify_visible) {
+        return fsck::view(verify_state, FileAction::Verify);
+    }
+    if let Some(history_state) = &state.history {
+        return history::view(history_state, FileAction::History);
+    }
+    if let Some(loading) = &state.loading {
+        let name = loading
+            .path
             .file_name()
-            .map(|n| n.to_string_lossy().to_string())
-            .unwrap_or_else(|| "Unknown".to_string());
+            .unwrap_or_default()
+            .to_string_lossy();
+        let cancel_button =
+            widget::button(widget::text("Cancel").size(14)).on_press(FileAction::CancelLoading);
+        let status = widget::column![
+            widget::text(format!("Decrypting {}...", name)).size(14),
+            cancel_button
+        ]
+        .spacing(10)
+        .align_x(iced::Alignment::Center);
+        return widget::container(status)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill)
+            .into();
+    }
 
-        // Header with filename and close button
-        let title = widget::text(filename).size(16);
-        let close_button =
-            widget::button(widget::text("X").size(14)).on_press(FileAction::CloseEditor);
+    if let Some(tab) = state.active_tab.and_then(|i| state.tabs.get(i)) {
+        // Header with open tabs, reveal, save and close buttons
+        let reveal_button = (editor::is_secret(&tab.path) && tab.binary.is_none()).then(|| {
+            widget::button(widget::text(if tab.revealed { "Hide" } else { "Reveal" }).size(14))
+                .on_press(FileAction::ToggleReveal)
+        });
+        let save_button = widget::button(widget::text("Save").size(14))
+            .on_press_maybe(tab.dirty.then_some(FileAction::SaveEditor));
 
-        let header = widget::row![title, widget::horizontal_space(), close_button]
+        let header = widget::row![view_tab_bar(state), widget::horizontal_space()]
+            .push_maybe(tab.signature.as_ref().map(view_signature))
+            .push_maybe(reveal_button)
+            .push(save_button)
+            .spacing(8)
             .align_y(iced::Alignment::Center);
         let header = widget::container(header).width(Length::Fill);
 
-        if let Some(content) = &state.editor_content {
-            let editor = widget::text_editor(&content)
+        let body: Element<'_, FileAction> = if let Some(info) = &tab.binary {
+            view_binary(info, state.saving_copies.contains(&tab.path))
+        } else if tab.revealed {
+            widget::text_editor(&tab.content)
                 .on_action(FileAction::EditorAction)
-                .height(Length::Fill);
-            let editor = widget::container(editor).padding(CONTENT_PADDING);
-
-            return widget::column![header, editor]
+                .key_binding(editor_key_binding)
                 .height(Length::Fill)
-                .width(Length::Fill)
-                .into();
-        }
+                .into()
+        } else {
+            // The masked view is read-only; editing requires revealing the secret
+            widget::scrollable(widget::text(tab.masked_text()).width(Length::Fill))
+                .height(Length::Fill)
+                .into()
+        };
+        let body = widget::container(body).padding(CONTENT_PADDING);
+
+        return widget::column![header, body]
+            .height(Length::Fill)
+            .width(Length::Fill)
+            .into();
     }
 
     let placeholder = widget::text("Select a file to view its contents...");
@@ -351,23 +2354,44 @@ fn view_editor_panel(state: &State) -> Element<'_, FileAction> {
 }
 
 pub fn view(state: &State) -> Element<'_, FileAction> {
-    const INDENT_LEVEL: u16 = 0;
     const INDENT_WIDTH: u16 = 24;
     const CONTENT_PADDING: u16 = 10;
     const EXPLORER_FILL_PORTION: u16 = 1;
     const EDITOR_FILL_PORTION: u16 = 2;
 
-    let home = std::env::var("HOME").unwrap_or(".".to_string());
-    let dir = std::path::PathBuf::from(format!("{}/ntech/mystiko", home));
-    let mut buttons: Vec<Element<FileAction>> = Vec::new();
-
-    render_directory_contents(&dir, state, INDENT_LEVEL, INDENT_WIDTH, &mut buttons);
-
-    let file_list = widget::Column::from_vec(buttons).width(Length::Fill);
-    let scrollable_list = widget::scrollable(file_list);
-    let file_explorer_panel = widget::container(scrollable_list)
-        .padding(CONTENT_PADDING)
-        .width(Length::FillPortion(EXPLORER_FILL_PORTION));
+    let file_list = view_file_list(state, INDENT_WIDTH);
+    let scrollable_list = widget::scrollable(file_list)
+        .id(file_list_id())
+        .on_scroll(FileAction::Scrolled);
+    let sync_button =
+        widget::button(widget::text(if state.syncing { "Syncing..." } else { "Sync" }).size(14))
+            .on_press_maybe((!state.syncing).then_some(FileAction::Sync));
+    let import_button = widget::button(widget::text("Import").size(14))
+        .on_press(FileAction::ShowImport(state.tree.root().to_path_buf()));
+    let backup_button =
+        widget::button(widget::text("Backup").size(14)).on_press(FileAction::ShowBackup);
+    let search_button =
+        widget::button(widget::text("Search").size(14)).on_press(FileAction::ShowSearch);
+    let audit_button =
+        widget::button(widget::text("Audit").size(14)).on_press(FileAction::ShowAudit);
+    let verify_button =
+        widget::button(widget::text("Verify").size(14)).on_press(FileAction::ShowVerify);
+    let lock_button = widget::button(widget::text("Lock").size(14)).on_press(FileAction::Lock);
+    let toolbar = widget::row![
+        widget::horizontal_space(),
+        search_button,
+        import_button,
+        backup_button,
+        audit_button,
+        verify_button,
+        sync_button,
+        lock_button
+    ]
+    .spacing(8);
+    let file_explorer_panel =
+        widget::container(widget::column![toolbar, scrollable_list].spacing(8))
+            .padding(CONTENT_PADDING)
+            .width(Length::FillPortion(EXPLORER_FILL_PORTION));
 
     // Editor panel on the right
     let editor_panel = widget::container(view_editor_panel(state))
@@ -385,6 +2409,30 @@ pub fn view(state: &State) -> Element<'_, FileAction> {
             error_popup::create_backdrop(FileAction::ErrorPopup(error_popup::Message::Dismiss));
         let modal = error_popup::view(error_state, FileAction::ErrorPopup);
 
+        widget::Stack::new()
+            .push(split_layout)
+            .push(backdrop)
+            .push(modal)
+            .height(Length::Fill)
+            .width(Length::Fill)
+            .into()
+    } else if let Some(confirm_state) = &state.confirm_popup {
+        let backdrop =
+            error_popup::create_backdrop(FileAction::ConfirmPopup(confirm_popup::Message::Cancel));
+        let modal = confirm_popup::view(confirm_state, FileAction::ConfirmPopup);
+
+        widget::Stack::new()
+            .push(split_layout)
+            .push(backdrop)
+            .push(modal)
+            .height(Length::Fill)
+            .width(Length::Fill)
+            .into()
+    } else if let Some(summary_state) = &state.summary_popup {
+        let backdrop =
+            error_popup::create_backdrop(FileAction::SummaryPopup(summary_popup::Message::Dismiss));
+        let modal = summary_popup::view(summary_state, FileAction::SummaryPopup);
+
         widget::Stack::new()
             .push(split_layout)
             .push(backdrop)
@@ -395,7 +2443,7 @@ pub fn view(state: &State) -> Element<'_, FileAction> {
     } else if let Some(menu_state) = &state.context_menu {
         // If context menu is open, render it on top
         // Build menu items for file explorer context
-        let menu_items = vec![
+        let mut menu_items = vec![
             context_menu::MenuItem::new(
                 "Edit",
                 FileAction::EditItem(menu_state.target_path.clone()),
@@ -407,6 +2455,47 @@ pub fn view(state: &State) -> Element<'_, FileAction> {
             )
             .with_icon(assets::DELETE_LOGO),
         ];
+        if editor::is_secret(&menu_state.target_path) {
+            menu_items.push(context_menu::MenuItem::new(
+                "History",
+                FileAction::ShowHistory(menu_state.target_path.clone()),
+            ));
+        }
+        if !state.tree.is_dir(&menu_state.target_path)
+            && !editor::is_secret(&menu_state.target_path)
+        {
+            menu_items.push(
+                context_menu::MenuItem::new(
+                    "Encrypt",
+                    FileAction::EncryptItem(menu_state.target_path.clone()),
+                )
+                .with_icon(assets::SECRET_LOGO),
+            );
+        }
+        if state.tree.is_dir(&menu_state.target_path) {
+            menu_items.push(context_menu::MenuItem::new(
+                "Import...",
+                FileAction::ShowImport(menu_state.target_path.clone()),
+            ));
+            menu_items.push(
+                context_menu::MenuItem::new(
+                    "Add file...",
+                    FileAction::AddFile(menu_state.target_path.clone()),
+                )
+                .with_icon(assets::NEW_FILE_LOGO),
+            );
+            menu_items.push(context_menu::MenuItem::new(
+                "Export as plaintext...",
+                FileAction::ShowExport(menu_state.target_path.clone()),
+            ));
+            menu_items.push(
+                context_menu::MenuItem::new(
+                    "Recipients...",
+                    FileAction::ShowRecipients(menu_state.target_path.clone()),
+                )
+                .with_icon(assets::ADD_USER_LOGO),
+            );
+        }
 
         // Create dismiss layer and menu using generic context_menu module
         let dismiss_layer = context_menu::create_dismiss_layer(FileAction::CloseContextMenu);
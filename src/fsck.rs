@@ -0,0 +1,480 @@
+//! Store consistency check: secrets encrypted to other recipients than their
+//! `.gpg-id` lists, unencrypted files and unused `.gpg-id` files. Recipients
+//! of age secrets cannot be read without decrypting, so they are not compared.
+
+use crate::crypto;
+use crate::job::{self, CancelFlag, Job};
+use crate::packets;
+use crate::plaintext;
+use crate::recipients::{self, GPG_ID_FILE};
+use crate::tree;
+use iced::futures::{SinkExt, Stream};
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What is wrong with a file in the store.
+#[derive(Debug, Clone)]
+pub enum Problem {
+    /// `.gpg-id` entries that cannot decrypt the secret.
+    MissingReaders(Vec<String>),
+    /// Key IDs that can decrypt the secret without being listed in `.gpg-id`.
+    ExtraReaders(Vec<u64>),
+    Unencrypted,
+    /// A `.gpg-id` that applies to no secret.
+    UnusedGpgId,
+    Unreadable(String),
+}
+
+impl Problem {
+    fn describe(&self) -> String {
+        match self {
+            Problem::MissingReaders(readers) => {
+                format!("Not readable by {}", readers.join(", "))
+            }
+            Problem::ExtraReaders(ids) => format!(
+                "Also readable by {}",
+                ids.iter()
+                    .map(|id| format!("{:016X}", id))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Problem::Unencrypted => "Not encrypted".to_string(),
+            Problem::UnusedGpgId => format!("No secret uses this {}", GPG_ID_FILE),
+            Problem::Unreadable(e) => format!("Could not check: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub path: PathBuf,
+    pub problem: Problem,
+    /// Whether re-encrypting the secret fixes the problem, which requires
+    /// being able to decrypt it.
+    pub fixable: bool,
+}
+
+/// Outcome of a check, with recipient drift, unencrypted files, unused
+/// `.gpg-id` files and unreadable files in that order.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checked: usize,
+    pub findings: Vec<Finding>,
+    pub error: Option<String>,
+}
+
+impl Report {
+    fn count(&self, matches: fn(&Problem) -> bool) -> usize {
+        self.findings.iter().filter(|f| matches(&f.problem)).count()
+    }
+}
+
+/// Messages for store check panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    Start,
+    Cancel,
+    /// Number of secrets checked so far, out of the total.
+    Progress(usize, usize),
+    Finished(Report),
+    /// Re-encrypt a secret to the recipients of its `.gpg-id`.
+    Fix(PathBuf),
+    Fixed(PathBuf, Result<(), String>),
+    /// Show the file of a finding.
+    Open(PathBuf),
+    Close,
+}
+
+/// Entries of a `.gpg-id` file with the key IDs of each.
+type Readers = Vec<(String, HashSet<u64>)>;
+
+/// Every `.gpg-id` file below `dir`, skipping `.git`.
+fn gpg_id_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            if entry.file_name() != ".git" {
+                gpg_id_files(&path, files);
+            }
+        } else if entry.file_name() == GPG_ID_FILE {
+            files.push(path);
+        }
+    }
+}
+
+fn expected_readers(gpg_id: &Path) -> Result<Readers, String> {
+    recipients::read_gpg_id(gpg_id)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|recipient| {
+            let ids = crypto::recipient_key_ids(&recipient).map_err(|e| e.to_string())?;
+            Ok((recipient, ids))
+        })
+        .collect()
+}
+
+/// Compare the recipients of the secret at `path` with `expected`.
+fn check_secret(path: &Path, expected: &Readers, secret_key_ids: &HashSet<u64>) -> Vec<Finding> {
+    // Only the session key packets are read, so the size of the secret
+    // does not matter
+    let Some(actual) = packets::file_recipients(path) else {
+        return vec![Finding {
+            path: path.to_path_buf(),
+            problem: Problem::Unreadable(
+                "No recipients found, the file is ASCII armored or damaged".to_string(),
+            ),
+            fixable: false,
+        }];
+    };
+    // Hidden recipients cannot be compared
+    if actual.contains(&packets::WILDCARD_KEY_ID) {
+        return Vec::new();
+    }
+    let fixable = actual.iter().any(|id| secret_key_ids.contains(id));
+
+    let missing: Vec<String> = expected
+        .iter()
+        .filter(|(_, ids)| !actual.iter().any(|id| ids.contains(id)))
+        .map(|(recipient, _)| recipient.clone())
+        .collect();
+    let extra: Vec<u64> = actual
+        .iter()
+        .filter(|id| !expected.iter().any(|(_, ids)| ids.contains(id)))
+        .copied()
+        .collect();
+
+    let mut findings = Vec::new();
+    if !missing.is_empty() {
+        findings.push(Finding {
+            path: path.to_path_buf(),
+            problem: Problem::MissingReaders(missing),
+            fixable,
+        });
+    }
+    if !extra.is_empty() {
+        findings.push(Finding {
+            path: path.to_path_buf(),
+            problem: Problem::ExtraReaders(extra),
+            fixable,
+        });
+    }
+    findings
+}
+
+/// Check every file below `root`, reporting progress to `output`.
+async fn check(
+    root: &Path,
+    cancel: &CancelFlag,
+    output: &mut iced::futures::channel::mpsc::Sender<Message>,
+) -> Report {
+    let files = match tree::secret_files(root) {
+        Ok(files) => files,
+        Err(e) => {
+            return Report {
+                error: Some(format!("Could not list the secrets: {}", e)),
+                ..Report::default()
+            }
+        }
+    };
+    let secret_key_ids = crypto::secret_key_ids().unwrap_or_else(|e| {
+        warn!("Failed to list secret keys: {}", e);
+        HashSet::new()
+    });
+
+    let mut drift = Vec::new();
+    let mut unreadable = Vec::new();
+    // Expected readers of each `.gpg-id`, looked up once
+    let mut readers: HashMap<PathBuf, Result<Readers, String>> = HashMap::new();
+
+    for (index, path) in files.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let _ = output.send(Message::Progress(index, files.len())).await;
+
+        let recipients_file = crypto::backend(path).recipients_file();
+        let Some(gpg_id) = recipients::find_gpg_id(path, root) else {
+            unreadable.push(Finding {
+                path: path.clone(),
+                problem: Problem::Unreadable(format!("No {} file applies", recipients_file)),
+                fixable: false,
+            });
+            continue;
+        };
+        // Only OpenPGP messages tell their recipients without decrypting
+        if recipients_file != GPG_ID_FILE {
+            continue;
+        }
+        let expected = readers
+            .entry(gpg_id.clone())
+            .or_insert_with(|| expected_readers(&gpg_id));
+        match expected {
+            Ok(expected) => drift.extend(check_secret(path, expected, &secret_key_ids)),
+            Err(e) => unreadable.push(Finding {
+                path: path.clone(),
+                problem: Problem::Unreadable(e.clone()),
+                fixable: false,
+            }),
+        }
+    }
+
+    let unencrypted = plaintext::scan(root).into_iter().map(|path| Finding {
+        path,
+        problem: Problem::Unencrypted,
+        fixable: false,
+    });
+    let mut gpg_ids = Vec::new();
+    gpg_id_files(root, &mut gpg_ids);
+    gpg_ids.sort();
+    let unused = gpg_ids
+        .into_iter()
+        .filter(|gpg_id| !readers.contains_key(gpg_id))
+        .map(|path| Finding {
+            path,
+            problem: Problem::UnusedGpgId,
+            fixable: false,
+        });
+
+    let mut findings = drift;
+    findings.extend(unencrypted);
+    findings.extend(unused);
+    findings.append(&mut unreadable);
+    Report {
+        checked: files.len(),
+        findings,
+        error: None,
+    }
+}
+
+/// Run a check of the store at `root`, yielding progress and then the report.
+fn run(root: PathBuf, cancel: CancelFlag) -> impl Stream<Item = Message> {
+    iced::stream::channel(16, move |mut output| async move {
+        let report = check(&root, &cancel, &mut output).await;
+        debug!(
+            "Check of {} secrets found {} problems",
+            report.checked,
+            report.findings.len()
+        );
+        let _ = output.send(Message::Finished(report)).await;
+    })
+}
+
+/// Decrypt the secret at `path` and encrypt it again to the recipients of
+/// its recipients file.
+pub fn reencrypt(root: &Path, path: &Path) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let backend = crypto::backend(path);
+    let plaintext = backend.decrypt(&data).map_err(|e| e.to_string())?;
+    let recipients = recipients::resolve(path, root).map_err(|e| e.to_string())?;
+    let ciphertext = backend
+        .encrypt(&plaintext, &recipients)
+        .map_err(|e| e.to_string())?;
+    fs::write(path, ciphertext).map_err(|e| e.to_string())?;
+    debug!("Re-encrypted '{}'", path.display());
+    Ok(())
+}
+
+/// State of the store check panel.
+pub struct State {
+    root: PathBuf,
+    /// Cancels the running check when dropped.
+    running: Option<Job>,
+    progress: (usize, usize),
+    report: Option<Report>,
+    /// Secrets being re-encrypted.
+    fixing: HashSet<PathBuf>,
+    error: Option<String>,
+}
+
+impl State {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            running: None,
+            progress: (0, 0),
+            report: None,
+            fixing: HashSet::new(),
+            error: None,
+        }
+    }
+
+    /// Start a check, cancelling the one in progress if any.
+    pub fn start(&mut self) -> Task<Message> {
+        debug!("Checking '{}'", self.root.display());
+        let root = self.root.clone();
+        let (task, job) = job::spawn(|cancel| run(root, cancel));
+        self.running = Some(job);
+        self.progress = (0, 0);
+        self.report = None;
+        self.error = None;
+        task
+    }
+
+    /// Re-encrypt the secret at `path` in the background.
+    pub fn fix(&mut self, path: PathBuf) -> Task<Message> {
+        if !self.fixing.insert(path.clone()) {
+            return Task::none();
+        }
+        let root = self.root.clone();
+        Task::perform(
+            async move {
+                let result = reencrypt(&root, &path);
+                (path, result)
+            },
+            |(path, result)| Message::Fixed(path, result),
+        )
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::Cancel => {
+                debug!("Check cancelled");
+                self.running = None;
+            }
+            Message::Progress(done, total) => self.progress = (done, total),
+            Message::Finished(report) => {
+                self.running = None;
+                self.report = Some(report);
+            }
+            Message::Fixed(path, result) => {
+                self.fixing.remove(&path);
+                match result {
+                    Ok(()) => {
+                        if let Some(report) = &mut self.report {
+                            report.findings.retain(|f| f.path != path || !f.fixable);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Failed to re-encrypt '{}': {}", path.display(), e);
+                        self.error = Some(format!(
+                            "Could not re-encrypt '{}': {}",
+                            path.strip_prefix(&self.root).unwrap_or(&path).display(),
+                            e
+                        ));
+                    }
+                }
+            }
+            Message::Start | Message::Fix(_) | Message::Open(_) | Message::Close => {}
+        }
+    }
+}
+
+fn view_report<'a>(state: &'a State, report: &'a Report) -> Element<'a, Message> {
+    if let Some(e) = &report.error {
+        return widget::text(e).size(14).into();
+    }
+
+    let summary = widget::text(format!(
+        "Checked {} secrets: {} with other readers than their {}, {} unencrypted \
+         files, {} unused {} files, {} unreadable.",
+        report.checked,
+        report.count(|p| matches!(p, Problem::MissingReaders(_) | Problem::ExtraReaders(_))),
+        GPG_ID_FILE,
+        report.count(|p| matches!(p, Problem::Unencrypted)),
+        report.count(|p| matches!(p, Problem::UnusedGpgId)),
+        GPG_ID_FILE,
+        report.count(|p| matches!(p, Problem::Unreadable(_))),
+    ))
+    .size(14);
+
+    let mut findings = widget::column![].spacing(2);
+    for finding in &report.findings {
+        let name = finding
+            .path
+            .strip_prefix(&state.root)
+            .unwrap_or(&finding.path);
+        let label = widget::column![
+            widget::text(name.display().to_string()).size(14),
+            widget::text(finding.problem.describe()).size(12),
+        ];
+        let open_button = widget::button(label)
+            .on_press(Message::Open(finding.path.clone()))
+            .style(widget::button::text)
+            .width(Length::Fill);
+        let fix_button = finding.fixable.then(|| {
+            widget::button(widget::text("Re-encrypt").size(14)).on_press_maybe(
+                (!state.fixing.contains(&finding.path)).then(|| Message::Fix(finding.path.clone())),
+            )
+        });
+        findings = findings.push(
+            widget::row![open_button]
+                .push_maybe(fix_button)
+                .spacing(8)
+                .align_y(iced::Alignment::Center),
+        );
+    }
+
+    widget::column![summary, widget::scrollable(findings).height(Length::Fill)]
+        .spacing(10)
+        .into()
+}
+
+/// Renders the store check panel: its progress and the findings.
+///
+/// # Arguments
+/// * `state` - The current store check panel state
+/// * `on_message` - Function to wrap check messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let action = if state.running.is_some() {
+        widget::button(widget::text("Cancel").size(14)).on_press(Message::Cancel)
+    } else {
+        widget::button(widget::text("Start check").size(14)).on_press(Message::Start)
+    };
+    let header = widget::row![
+        widget::text("Verify store").size(16),
+        widget::horizontal_space(),
+        action,
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let error = state.error.as_ref().map(|e| {
+        widget::text(e)
+            .size(14)
+            .color(iced::Color::from_rgb(0.7, 0.1, 0.1))
+    });
+
+    let body: Element<'a, Message> = if state.running.is_some() {
+        let (done, total) = state.progress;
+        widget::column![
+            widget::text(format!("Checking secret {} of {}...", done + 1, total)).size(14),
+            widget::progress_bar(0.0..=total.max(1) as f32, done as f32),
+        ]
+        .spacing(8)
+        .into()
+    } else if let Some(report) = &state.report {
+        view_report(state, report)
+    } else {
+        widget::text(format!(
+            "The check compares the recipients of every secret with its {} \
+             without decrypting anything, and looks for unencrypted files and \
+             unused {} files. Secrets you can decrypt can then be re-encrypted \
+             to the right recipients.",
+            GPG_ID_FILE, GPG_ID_FILE
+        ))
+        .size(14)
+        .into()
+    };
+
+    let content: Element<'a, Message> = widget::column![header]
+        .push_maybe(error)
+        .push(body)
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
@@ -0,0 +1,295 @@
+use log::debug;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Separates commits in `git log` output.
+const RECORD_SEPARATOR: char = '\u{1e}';
+/// Separates fields of a commit in `git log` output.
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+#[derive(Debug)]
+pub enum GitError {
+    IoError(std::io::Error),
+    CommandFailed(String),
+    OutsideRepository(PathBuf),
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitError::IoError(e) => write!(f, "Could not run git: {}", e),
+            GitError::CommandFailed(stderr) => write!(f, "git failed: {}", stderr),
+            GitError::OutsideRepository(path) => {
+                write!(f, "'{}' is not inside the store", path.display())
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for GitError {
+    fn from(e: std::io::Error) -> Self {
+        GitError::IoError(e)
+    }
+}
+
+/// A commit touching a file.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub id: String,
+    pub short_id: String,
+    pub author: String,
+    pub date: String,
+    pub summary: String,
+    /// Path of the file in this commit, relative to the repository root.
+    /// Differs from the current path if the file was renamed since.
+    pub path: String,
+}
+
+/// Run git against a remote repository. Prompts for credentials, host keys
+/// or passphrases are turned off, so that a missing credential fails
+/// instead of hanging.
+fn run_remote(args: &[&str]) -> Result<Vec<u8>, GitError> {
+    debug!("Running git {}", args.join(" "));
+    let mut command = Command::new("git");
+    command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null());
+    if env::var_os("GIT_SSH_COMMAND").is_none() {
+        command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+    }
+    let output = command.output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(GitError::CommandFailed(stderr));
+    }
+    Ok(output.stdout)
+}
+
+/// Run git in `repo` and return its standard output.
+fn run(repo: &Path, args: &[&str]) -> Result<Vec<u8>, GitError> {
+    debug!("Running git {} in '{}'", args.join(" "), repo.display());
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(GitError::CommandFailed(stderr));
+    }
+    Ok(output.stdout)
+}
+
+/// Path of `path` relative to `repo`, with forward slashes as git expects.
+fn relative_path(repo: &Path, path: &Path) -> Result<String, GitError> {
+    let relative = path
+        .strip_prefix(repo)
+        .map_err(|_| GitError::OutsideRepository(path.to_path_buf()))?;
+    Ok(relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Whether `repo` is the working tree of a git repository.
+pub fn is_repository(repo: &Path) -> bool {
+    run(repo, &["rev-parse", "--is-inside-work-tree"]).is_ok()
+}
+
+/// List the commits touching `path`, newest first, following renames.
+pub fn log(repo: &Path, path: &Path) -> Result<Vec<Commit>, GitError> {
+    let relative = relative_path(repo, path)?;
+    let format = format!(
+        "--format={}%H{}%h{}%an{}%ad{}%s",
+        RECORD_SEPARATOR, FIELD_SEPARATOR, FIELD_SEPARATOR, FIELD_SEPARATOR, FIELD_SEPARATOR
+    );
+    let output = run(
+        repo,
+        &[
+            "log",
+            "--follow",
+            "--name-only",
+            "--date=short",
+            &format,
+            "--",
+            &relative,
+        ],
+    )?;
+    let output = String::from_utf8_lossy(&output);
+
+    let mut commits = Vec::new();
+    for record in output
+        .split(RECORD_SEPARATOR)
+        .filter(|r| !r.trim().is_empty())
+    {
+        let mut lines = record.lines();
+        let header = lines.next().unwrap_or_default();
+        let fields: Vec<&str> = header.split(FIELD_SEPARATOR).collect();
+        let [id, short_id, author, date, summary] = fields[..] else {
+            continue;
+        };
+        let path = lines
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or(&relative)
+            .to_string();
+
+        commits.push(Commit {
+            id: id.to_string(),
+            short_id: short_id.to_string(),
+            author: author.to_string(),
+            date: date.to_string(),
+            summary: summary.to_string(),
+            path,
+        });
+    }
+    Ok(commits)
+}
+
+/// Read the content of `path` (relative to the repository root) at commit `rev`.
+pub fn show(repo: &Path, rev: &str, path: &str) -> Result<Vec<u8>, GitError> {
+    run(repo, &["show", &format!("{}:{}", rev, path)])
+}
+
+/// Stage `path` and commit it with `message`.
+pub fn commit_file(repo: &Path, path: &Path, message: &str) -> Result<(), GitError> {
+    let relative = relative_path(repo, path)?;
+    run(repo, &["add", "--", &relative])?;
+    run(repo, &["commit", "-m", message, "--", &relative])?;
+    Ok(())
+}
+
+/// Create a repository in `repo` and commit everything in it with `message`.
+pub fn init(repo: &Path, message: &str) -> Result<(), GitError> {
+    run(repo, &["init"])?;
+    run(repo, &["add", "--all"])?;
+    run(repo, &["commit", "-m", message])?;
+    Ok(())
+}
+
+/// Check that the repository at `url` exists and can be read.
+pub fn check_access(url: &str) -> Result<(), GitError> {
+    run_remote(&["ls-remote", "--heads", "--", url])?;
+    Ok(())
+}
+
+/// Clone the repository at `url` into `dir`.
+pub fn clone(url: &str, dir: &Path) -> Result<(), GitError> {
+    let dir = dir.to_string_lossy();
+    run_remote(&["clone", "--", url, &dir])?;
+    Ok(())
+}
+
+/// URL of the `origin` remote of `repo`.
+pub fn remote_url(repo: &Path) -> Result<String, GitError> {
+    let output = run(repo, &["remote", "get-url", "origin"])?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+/// Set a configuration value of `repo`.
+pub fn set_config(repo: &Path, key: &str, value: &str) -> Result<(), GitError> {
+    run(repo, &["config", "--local", key, value])?;
+    Ok(())
+}
+
+/// Add `url` as the `origin` remote of `repo`.
+pub fn add_remote(repo: &Path, url: &str) -> Result<(), GitError> {
+    run(repo, &["remote", "add", "origin", url])?;
+    Ok(())
+}
+
+/// Pull from the upstream of the current branch, merging remote changes.
+pub fn pull(repo: &Path) -> Result<(), GitError> {
+    run(repo, &["pull", "--no-rebase", "--no-edit"])?;
+    Ok(())
+}
+
+/// Files with unresolved merge conflicts.
+pub fn conflicted_files(repo: &Path) -> Result<Vec<PathBuf>, GitError> {
+    let output = run(repo, &["diff", "--name-only", "--diff-filter=U", "-z"])?;
+    Ok(output
+        .split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| repo.join(String::from_utf8_lossy(name).as_ref()))
+        .collect())
+}
+
+/// Stage of a conflicted file in the index.
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Base = 1,
+    Ours = 2,
+    Theirs = 3,
+}
+
+/// Read one side of a conflicted file from the index.
+///
+/// Returns empty content if the side does not exist, e.g. the base of a file
+/// added on both sides.
+pub fn show_stage(repo: &Path, stage: Stage, path: &Path) -> Result<Vec<u8>, GitError> {
+    let relative = relative_path(repo, path)?;
+    // Lines look like "<mode> <object> <stage>\t<path>"
+    let output = run(repo, &["ls-files", "--unmerged", "--", &relative])?;
+    let output = String::from_utf8_lossy(&output);
+    let object = output.lines().find_map(|line| {
+        let (info, _) = line.split_once('\t')?;
+        let mut fields = info.split_whitespace();
+        let (_, object, line_stage) = (fields.next()?, fields.next()?, fields.next()?);
+        (line_stage == (stage as u8).to_string()).then(|| object.to_string())
+    });
+
+    match object {
+        Some(object) => run(repo, &["cat-file", "blob", &object]),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Mark `path` as resolved by staging it.
+pub fn stage_file(repo: &Path, path: &Path) -> Result<(), GitError> {
+    let relative = relative_path(repo, path)?;
+    run(repo, &["add", "--", &relative])?;
+    Ok(())
+}
+
+/// Conclude a merge whose conflicts have all been resolved.
+pub fn commit_merge(repo: &Path) -> Result<(), GitError> {
+    run(repo, &["commit", "--no-edit"])?;
+    Ok(())
+}
+
+/// Abort the merge in progress, restoring the state before the pull.
+pub fn abort_merge(repo: &Path) -> Result<(), GitError> {
+    run(repo, &["merge", "--abort"])?;
+    Ok(())
+}
+
+/// Time of the last commit touching each file of `repo`, in seconds since
+/// the Unix epoch, keyed by absolute path.
+pub fn last_changes(repo: &Path) -> Result<HashMap<PathBuf, u64>, GitError> {
+    let format = format!("--format={}%ct", RECORD_SEPARATOR);
+    let output = run(
+        repo,
+        &["-c", "core.quotePath=false", "log", "--name-only", &format],
+    )?;
+    let output = String::from_utf8_lossy(&output);
+
+    let mut changes = HashMap::new();
+    // Newest commits come first, so the first time a file appears is its last change
+    for record in output.split(RECORD_SEPARATOR) {
+        let mut lines = record.lines();
+        let Some(Ok(time)) = lines.next().map(|line| line.trim().parse::<u64>()) else {
+            continue;
+        };
+        for name in lines.map(str::trim).filter(|line| !line.is_empty()) {
+            changes.entry(repo.join(name)).or_insert(time);
+        }
+    }
+    Ok(changes)
+}
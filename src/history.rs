@@ -0,0 +1,293 @@
+use crate::crypto;
+use crate::git;
+use iced::widget;
+use iced::{Element, Font, Length, Task};
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Replaces password lines in the diff.
+const MASK: &str = "\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}\u{2022}";
+
+/// State of the history viewer for one secret.
+pub struct State {
+    /// The secret whose history is shown.
+    pub path: PathBuf,
+    /// Commits touching the secret, newest first.
+    pub commits: Vec<git::Commit>,
+    /// Index of the selected commit in `commits`.
+    pub selected: Option<usize>,
+    /// Decrypted versions by commit id. Wiped from memory when dropped.
+    versions: HashMap<String, Zeroizing<String>>,
+    /// Diff between the selected version and the one before it.
+    diff: Vec<DiffLine>,
+    /// Whether the versions of the selected commit are being decrypted.
+    decrypting: bool,
+}
+
+/// A line in the diff view, with passwords already masked.
+#[derive(Debug, Clone)]
+struct DiffLine {
+    tag: ChangeTag,
+    text: String,
+}
+
+/// Messages for history viewer interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// User selected the commit at the given index.
+    Select(usize),
+    /// The versions needed for the diff of the commit at the given index
+    /// were decrypted in the background, by commit id.
+    Decrypted(usize, Result<Vec<(String, Zeroizing<String>)>, String>),
+    /// User asked to restore the selected version.
+    Restore,
+    /// User closed the history viewer.
+    Close,
+}
+
+impl State {
+    /// Show `commits`, as listed by [`git::log`] for `path`.
+    pub fn new(path: PathBuf, commits: Vec<git::Commit>) -> Self {
+        Self {
+            path,
+            commits,
+            selected: None,
+            versions: HashMap::new(),
+            diff: Vec::new(),
+            decrypting: false,
+        }
+    }
+
+    /// Select a commit, decrypting it and its predecessor in the background
+    /// to compute the diff.
+    pub fn select(&mut self, root: &Path, index: usize) -> Task<Message> {
+        if index >= self.commits.len() {
+            return Task::none();
+        }
+        self.selected = Some(index);
+
+        let end = (index + 2).min(self.commits.len());
+        let missing: Vec<git::Commit> = self.commits[index..end]
+            .iter()
+            .filter(|commit| !self.versions.contains_key(&commit.id))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            self.decrypting = false;
+            self.show_diff(index);
+            return Task::none();
+        }
+
+        self.decrypting = true;
+        self.diff.clear();
+        let root = root.to_path_buf();
+        Task::perform(
+            async move {
+                missing
+                    .iter()
+                    .map(|commit| Ok((commit.id.clone(), decrypt_version(&root, commit)?)))
+                    .collect()
+            },
+            move |result| Message::Decrypted(index, result),
+        )
+    }
+
+    /// Keep versions decrypted in the background and show the diff if their
+    /// commit is still selected.
+    pub fn decrypted(
+        &mut self,
+        index: usize,
+        result: Result<Vec<(String, Zeroizing<String>)>, String>,
+    ) -> Result<(), String> {
+        if self.selected == Some(index) {
+            self.decrypting = false;
+        }
+        self.versions.extend(result?);
+        if self.selected == Some(index) {
+            self.show_diff(index);
+        }
+        Ok(())
+    }
+
+    /// The selected commit and its decrypted content, once it is decrypted.
+    pub fn selected_version(&self) -> Option<(&git::Commit, Zeroizing<String>)> {
+        let commit = &self.commits[self.selected?];
+        let text = self.versions.get(&commit.id)?;
+        Some((commit, text.clone()))
+    }
+
+    fn show_diff(&mut self, index: usize) {
+        let version = |index: usize| {
+            self.commits
+                .get(index)
+                .and_then(|commit| self.versions.get(&commit.id))
+                .map(|text| text.as_str())
+                .unwrap_or_default()
+        };
+        self.diff = diff_lines(version(index + 1), version(index));
+    }
+}
+
+/// Decrypt the version of the secret at `commit`.
+fn decrypt_version(root: &Path, commit: &git::Commit) -> Result<Zeroizing<String>, String> {
+    let encrypted = git::show(root, &commit.id, &commit.path).map_err(|e| e.to_string())?;
+    // An empty file has nothing to decrypt
+    if encrypted.is_empty() {
+        return Ok(Zeroizing::new(String::new()));
+    }
+    crypto::backend(Path::new(&commit.path))
+        .decrypt_text(&encrypted)
+        .map(Zeroizing::new)
+        .map_err(|e| e.to_string())
+}
+
+/// Whether a line holds a password: the first line by pass convention, or a
+/// `password:` field.
+fn is_password_line(line: &str, index: Option<usize>) -> bool {
+    if index == Some(0) {
+        return true;
+    }
+    line.split_once(':').is_some_and(|(key, _)| {
+        let key = key.trim().to_lowercase();
+        key == "password" || key == "pass"
+    })
+}
+
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| {
+            let index = match change.tag() {
+                ChangeTag::Delete => change.old_index(),
+                ChangeTag::Insert | ChangeTag::Equal => change.new_index(),
+            };
+            let line = change.value().trim_end_matches('\n');
+            let text = if is_password_line(line, index) {
+                match line.split_once(':') {
+                    Some((key, _)) if index != Some(0) => format!("{}: {}", key, MASK),
+                    _ => MASK.to_string(),
+                }
+            } else {
+                line.to_string()
+            };
+            DiffLine {
+                tag: change.tag(),
+                text,
+            }
+        })
+        .collect()
+}
+
+fn view_diff(state: &State) -> Element<'_, Message> {
+    if state.selected.is_none() {
+        return widget::container(widget::text("Select a commit to see its changes..."))
+            .center_x(Length::Fill)
+            .center_y(Length::Fill)
+            .into();
+    }
+    if state.decrypting {
+        return widget::container(widget::text("Decrypting..."))
+            .center_x(Length::Fill)
+            .center_y(Length::Fill)
+            .into();
+    }
+
+    let mut lines = widget::column![];
+    for line in &state.diff {
+        let (prefix, color) = match line.tag {
+            ChangeTag::Delete => ("-", Some(iced::Color::from_rgb(0.7, 0.1, 0.1))),
+            ChangeTag::Insert => ("+", Some(iced::Color::from_rgb(0.1, 0.5, 0.1))),
+            ChangeTag::Equal => (" ", None),
+        };
+        let text = widget::text(format!("{} {}", prefix, line.text))
+            .font(Font::MONOSPACE)
+            .size(14)
+            .color_maybe(color);
+        lines = lines.push(text);
+    }
+
+    widget::scrollable(lines.width(Length::Fill))
+        .height(Length::Fill)
+        .into()
+}
+
+/// Renders the history viewer: the commit list next to the diff of the selected commit.
+///
+/// # Arguments
+/// * `state` - The current history viewer state
+/// * `on_message` - Function to wrap history messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let filename = state
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let title = widget::text(format!("History of {}", filename)).size(16);
+    let restore_button = widget::button(widget::text("Restore this version").size(14))
+        .on_press_maybe(state.selected_version().map(|_| Message::Restore));
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let header = widget::row![
+        title,
+        widget::horizontal_space(),
+        restore_button,
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let mut commit_list = widget::column![].spacing(2);
+    for (index, commit) in state.commits.iter().enumerate() {
+        let is_selected = state.selected == Some(index);
+        let label = widget::column![
+            widget::text(&commit.summary).size(14),
+            widget::text(format!(
+                "{} \u{00B7} {} \u{00B7} {}",
+                commit.short_id, commit.author, commit.date
+            ))
+            .size(12),
+        ];
+        let button = widget::button(label)
+            .on_press(Message::Select(index))
+            .style(move |theme: &iced::Theme, status| {
+                let selected_bg =
+                    iced::Background::Color(iced::Color::from_rgba(0.3, 0.5, 0.8, 0.5));
+                let hover_bg = iced::Background::Color(iced::Color::from_rgba(0.3, 0.5, 0.8, 0.3));
+
+                let base = widget::button::Style {
+                    background: if is_selected { Some(selected_bg) } else { None },
+                    text_color: theme.palette().text,
+                    border: iced::Border::default(),
+                    shadow: iced::Shadow::default(),
+                };
+                match status {
+                    widget::button::Status::Hovered => widget::button::Style {
+                        background: Some(hover_bg),
+                        ..base
+                    },
+                    _ => base,
+                }
+            })
+            .width(Length::Fill);
+        commit_list = commit_list.push(button);
+    }
+    let commit_list = widget::scrollable(commit_list)
+        .height(Length::Fill)
+        .width(Length::FillPortion(1));
+
+    let diff = widget::container(view_diff(state)).width(Length::FillPortion(2));
+    let body = widget::row![commit_list, diff].spacing(10);
+
+    let content: Element<'a, Message> = widget::column![header, body]
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
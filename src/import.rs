@@ -0,0 +1,539 @@
+use crate::bitwarden;
+use crate::crypto;
+use crate::keepass;
+use crate::onepassword;
+use crate::recipients;
+use crate::secret::Secret;
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::debug;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+#[derive(Debug)]
+pub enum ImportError {
+    IoError(std::io::Error),
+    KeePass(keepass::KeePassError),
+    Json(serde_json::Error),
+    Zip(zip::result::ZipError),
+    Invalid(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::IoError(e) => write!(f, "Could not read file: {}", e),
+            ImportError::KeePass(e) => write!(f, "{}", e),
+            ImportError::Json(e) => write!(f, "Invalid JSON: {}", e),
+            ImportError::Zip(e) => write!(f, "Invalid archive: {}", e),
+            ImportError::Invalid(what) => write!(f, "Invalid export: {}", what),
+            ImportError::Unsupported(what) => write!(f, "Unsupported: {}", what),
+        }
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::IoError(e)
+    }
+}
+
+impl From<keepass::KeePassError> for ImportError {
+    fn from(e: keepass::KeePassError) -> Self {
+        ImportError::KeePass(e)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> Self {
+        ImportError::Json(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ImportError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ImportError::Zip(e)
+    }
+}
+
+/// Password managers secrets can be imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    KeePass,
+    Bitwarden,
+    OnePassword,
+    OnePasswordCsv,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [
+        Format::KeePass,
+        Format::Bitwarden,
+        Format::OnePassword,
+        Format::OnePasswordCsv,
+    ];
+
+    /// File extensions offered in the file picker.
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Format::KeePass => &["kdbx"],
+            Format::Bitwarden => &["json"],
+            Format::OnePassword => &["1pux"],
+            Format::OnePasswordCsv => &["csv"],
+        }
+    }
+
+    /// Whether the source is encrypted with a master password.
+    fn is_encrypted(&self) -> bool {
+        *self == Format::KeePass
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::KeePass => write!(f, "KeePass database (.kdbx)"),
+            Format::Bitwarden => write!(f, "Bitwarden export (.json)"),
+            Format::OnePassword => write!(f, "1Password export (.1pux)"),
+            Format::OnePasswordCsv => write!(f, "1Password export (.csv)"),
+        }
+    }
+}
+
+/// A secret read from another password manager.
+#[derive(Debug, Clone)]
+pub struct ImportedEntry {
+    /// Folders leading to the entry, outermost first.
+    pub folder: Vec<String>,
+    pub name: String,
+    pub secret: Secret,
+}
+
+/// Everything read from an export or database.
+#[derive(Debug, Clone, Default)]
+pub struct Import {
+    pub entries: Vec<ImportedEntry>,
+    /// Descriptions of items that could not be imported.
+    pub skipped: Vec<String>,
+}
+
+/// Turn a title into a usable file or directory name.
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c == '/' || c == '\\' || c.is_control() {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() {
+        "Untitled".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Choose a path below `target` for every entry.
+///
+/// Entries whose path is taken, either on disk or by an earlier entry, get a
+/// numbered name like `name (2).gpg`. `extension` is the extension of
+/// secrets of the store's backend.
+pub fn plan(import: &Import, target: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut taken = HashSet::new();
+    import
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut dir = target.to_path_buf();
+            for folder in &entry.folder {
+                dir.push(sanitize(folder));
+            }
+            let name = sanitize(&entry.name);
+
+            let mut path = dir.join(format!("{}.{}", name, extension));
+            let mut counter = 2;
+            while path.exists() || taken.contains(&path) {
+                path = dir.join(format!("{} ({}).{}", name, counter, extension));
+                counter += 1;
+            }
+            taken.insert(path.clone());
+            path
+        })
+        .collect()
+}
+
+fn write_entry(entry: &ImportedEntry, path: &Path, root: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let recipients = recipients::resolve(path, root).map_err(|e| e.to_string())?;
+    let text = Zeroizing::new(entry.secret.to_text());
+    let ciphertext = crypto::backend(path)
+        .encrypt(text.as_bytes(), &recipients)
+        .map_err(|e| e.to_string())?;
+    fs::write(path, ciphertext).map_err(|e| e.to_string())
+}
+
+/// Encrypt every entry to the recipients of its target directory and write it.
+///
+/// Returns the number of secrets written before the first failure along with
+/// the error, if any.
+pub fn write(import: &Import, paths: &[PathBuf], root: &Path) -> (usize, Result<(), String>) {
+    for (written, (entry, path)) in import.entries.iter().zip(paths).enumerate() {
+        if let Err(e) = write_entry(entry, path, root) {
+            return (written, Err(format!("{}: {}", path.display(), e)));
+        }
+        debug!("Imported '{}'", path.display());
+    }
+    (import.entries.len(), Ok(()))
+}
+
+/// Outcome of an import, shown to the user once it is done.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub imported: usize,
+    /// Items of the source that were not imported.
+    pub skipped: Vec<String>,
+    /// Error that stopped the import early, if any.
+    pub error: Option<String>,
+}
+
+/// Entries read from a database, with the paths they will be written to.
+#[derive(Debug, Clone)]
+pub struct Preview {
+    import: Import,
+    paths: Vec<PathBuf>,
+}
+
+/// State of the import panel.
+pub struct State {
+    /// Directory the secrets are imported into.
+    pub target: PathBuf,
+    /// Extension of the secrets written, see [`plan`].
+    extension: &'static str,
+    format: Format,
+    file: Option<PathBuf>,
+    key_file: Option<PathBuf>,
+    password: Zeroizing<String>,
+    preview: Option<Preview>,
+    /// Whether the selected file is being read in the background.
+    opening: bool,
+    /// Whether the previewed entries are being written in the background.
+    importing: bool,
+}
+
+/// Messages for import panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    FormatSelected(Format),
+    PickFile,
+    PickKeyFile,
+    ClearKeyFile,
+    PasswordChanged(String),
+    /// Read the selected file and preview its entries.
+    Open,
+    /// The file was read in the background.
+    Opened(PathBuf, Result<Preview, String>),
+    /// Write the previewed entries to the store.
+    Import,
+    /// The previewed entries were written in the background.
+    Imported(Summary),
+    Close,
+}
+
+impl State {
+    pub fn new(target: PathBuf, extension: &'static str) -> Self {
+        Self {
+            target,
+            extension,
+            format: Format::KeePass,
+            file: None,
+            key_file: None,
+            password: Zeroizing::new(String::new()),
+            preview: None,
+            opening: false,
+            importing: false,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            // Changing the source drops a read in progress
+            Message::FormatSelected(format) => {
+                self.opening = false;
+                self.format = format;
+                self.file = None;
+                self.preview = None;
+            }
+            Message::PickFile => {
+                if let Some(file) = rfd::FileDialog::new()
+                    .add_filter(self.format.to_string(), self.format.extensions())
+                    .pick_file()
+                {
+                    self.opening = false;
+                    self.file = Some(file);
+                    self.preview = None;
+                }
+            }
+            Message::PickKeyFile => {
+                if let Some(file) = rfd::FileDialog::new().pick_file() {
+                    self.opening = false;
+                    self.key_file = Some(file);
+                    self.preview = None;
+                }
+            }
+            Message::ClearKeyFile => {
+                self.opening = false;
+                self.key_file = None;
+                self.preview = None;
+            }
+            Message::PasswordChanged(password) => {
+                self.opening = false;
+                self.password = Zeroizing::new(password);
+                self.preview = None;
+            }
+            Message::Imported(_) => self.importing = false,
+            Message::Open | Message::Opened(..) | Message::Import | Message::Close => {}
+        }
+    }
+
+    /// Read the selected file in the background, see [`State::opened`].
+    ///
+    /// Deriving the key of a KeePass database takes a while on purpose.
+    pub fn open(&mut self) -> Task<Message> {
+        let Some(file) = self.file.clone() else {
+            return Task::none();
+        };
+        self.opening = true;
+        self.preview = None;
+        let format = self.format;
+        let password = self.password.clone();
+        let key_file = self.key_file.clone();
+        let target = self.target.clone();
+        let extension = self.extension;
+        Task::perform(
+            async move {
+                let preview = read_preview(format, &file, &password, key_file.as_deref())
+                    .map(|import| {
+                        let paths = plan(&import, &target, extension);
+                        Preview { import, paths }
+                    })
+                    .map_err(|e| e.to_string());
+                (file, preview)
+            },
+            |(file, preview)| Message::Opened(file, preview),
+        )
+    }
+
+    /// Show the entries read from `file`. Returns the error of the read, unless
+    /// the source changed meanwhile.
+    pub fn opened(&mut self, file: &Path, result: Result<Preview, String>) -> Result<(), String> {
+        if !self.opening || self.file.as_deref() != Some(file) {
+            debug!("Ignoring the entries of '{}'", file.display());
+            return Ok(());
+        }
+        self.opening = false;
+        self.preview = Some(result?);
+        Ok(())
+    }
+
+    /// Write the previewed entries in the background.
+    pub fn import(&mut self, root: &Path) -> Task<Message> {
+        let Some(preview) = self.preview.take() else {
+            return Task::none();
+        };
+        self.password = Zeroizing::new(String::new());
+        self.importing = true;
+        let root = root.to_path_buf();
+        Task::perform(
+            async move {
+                let (imported, result) = write(&preview.import, &preview.paths, &root);
+                Summary {
+                    imported,
+                    skipped: preview.import.skipped,
+                    error: result.err(),
+                }
+            },
+            Message::Imported,
+        )
+    }
+}
+
+/// Read the entries of an export or database.
+fn read_preview(
+    format: Format,
+    file: &Path,
+    password: &str,
+    key_file: Option<&Path>,
+) -> Result<Import, ImportError> {
+    let import = match format {
+        Format::KeePass => keepass::read(file, password, key_file)?,
+        Format::Bitwarden => bitwarden::read(file)?,
+        Format::OnePassword => onepassword::read_1pux(file)?,
+        Format::OnePasswordCsv => onepassword::read_csv(file)?,
+    };
+    debug!(
+        "Read {} entries from '{}'",
+        import.entries.len(),
+        file.display()
+    );
+    Ok(import)
+}
+
+/// Rows of the preview tree: directories once, followed by their secrets.
+fn preview_rows(preview: &Preview, target: &Path) -> Vec<(usize, String)> {
+    let mut relative: Vec<&Path> = preview
+        .paths
+        .iter()
+        .map(|path| path.strip_prefix(target).unwrap_or(path))
+        .collect();
+    relative.sort();
+
+    let mut rows = Vec::new();
+    let mut shown = BTreeSet::new();
+    for path in relative {
+        let components: Vec<String> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        for depth in 0..components.len() - 1 {
+            if shown.insert(components[..=depth].to_vec()) {
+                rows.push((depth, format!("{}/", components[depth])));
+            }
+        }
+        rows.push((
+            components.len() - 1,
+            components[components.len() - 1].clone(),
+        ));
+    }
+    rows
+}
+
+/// Renders the import panel: source selection and a preview of the secrets to
+/// be created.
+///
+/// # Arguments
+/// * `state` - The current import panel state
+/// * `on_message` - Function to wrap import messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let header = widget::row![
+        widget::text(format!("Import into {}", state.target.display())).size(16),
+        widget::horizontal_space(),
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let format_row = widget::row![
+        widget::text("Source").size(14),
+        widget::pick_list(Format::ALL, Some(state.format), Message::FormatSelected),
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let file_label = state
+        .file
+        .as_ref()
+        .map(|f| f.display().to_string())
+        .unwrap_or_else(|| "No file selected".to_string());
+    let file_row = widget::row![
+        widget::text(file_label).size(14).width(Length::Fill),
+        widget::button(widget::text("Choose file...").size(14)).on_press(Message::PickFile),
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let open_button = widget::button(widget::text("Open").size(14)).on_press_maybe(
+        state
+            .file
+            .as_ref()
+            .filter(|_| !state.opening)
+            .map(|_| Message::Open),
+    );
+
+    let mut form = widget::column![format_row, file_row].spacing(10);
+    if state.format.is_encrypted() {
+        let key_label = state
+            .key_file
+            .as_ref()
+            .map(|f| f.display().to_string())
+            .unwrap_or_else(|| "No key file".to_string());
+        let key_row = widget::row![
+            widget::text(key_label).size(14).width(Length::Fill),
+            widget::button(widget::text("Clear").size(14))
+                .on_press_maybe(state.key_file.as_ref().map(|_| Message::ClearKeyFile)),
+            widget::button(widget::text("Choose key file...").size(14))
+                .on_press(Message::PickKeyFile),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center);
+
+        let password = widget::text_input("Master password", &state.password)
+            .secure(true)
+            .on_input(Message::PasswordChanged)
+            .on_submit(Message::Open);
+        let password_row = widget::row![password, open_button]
+            .spacing(8)
+            .align_y(iced::Alignment::Center);
+
+        form = form.push(key_row).push(password_row);
+    } else {
+        form = form.push(widget::row![widget::horizontal_space(), open_button]);
+    }
+
+    if state.opening {
+        form = form.push(widget::text("Opening...").size(14));
+    }
+    if state.importing {
+        form = form.push(widget::text("Importing...").size(14));
+    }
+    if let Some(preview) = &state.preview {
+        let mut tree = widget::column![].spacing(2);
+        for (depth, label) in preview_rows(preview, &state.target) {
+            tree = tree.push(widget::row![
+                widget::Space::with_width(depth as f32 * 24.0),
+                widget::text(label).size(14)
+            ]);
+        }
+        let mut info = format!("{} secrets will be created.", preview.paths.len());
+        if !preview.import.skipped.is_empty() {
+            info.push_str(&format!(
+                " {} items will be skipped.",
+                preview.import.skipped.len()
+            ));
+        }
+        let import_button = widget::button(widget::text("Import").size(14))
+            .on_press_maybe((!preview.paths.is_empty()).then_some(Message::Import));
+
+        form = form
+            .push(
+                widget::row![
+                    widget::text(info).size(14),
+                    widget::horizontal_space(),
+                    import_button
+                ]
+                .align_y(iced::Alignment::Center),
+            )
+            .push(widget::scrollable(tree.width(Length::Fill)).height(Length::Fill));
+    }
+
+    let content: Element<'a, Message> = widget::column![header, form]
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
@@ -0,0 +1,292 @@
+//! Creating a new password store, like `pass init` followed by `pass git init`.
+
+use crate::crypto::{self, KeyInfo};
+use crate::git;
+use crate::recipients::{self, GPG_ID_FILE, GPG_ID_SIGNATURE_FILE};
+use crate::setup::{self, Store};
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::{debug, error};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Commit message of the first commit, as used by pass.
+const INITIAL_COMMIT_MESSAGE: &str = "Add current contents of password store.";
+const GITATTRIBUTES_FILE: &str = ".gitattributes";
+/// Shows the decrypted content of secrets in `git diff` and `git log -p`.
+const DIFF_TEXTCONV: &str =
+    "gpg -d --quiet --yes --compress-algo=none --no-encrypt-to --batch --use-agent";
+
+/// Create a store in `dir` encrypted to `recipients`, with a git repository
+/// holding an initial commit and `remote` as its origin if given.
+///
+/// If any step fails, the files written so far are removed again, so that
+/// creating the store can be retried once the problem is fixed.
+pub fn create(dir: &Path, recipients: &[String], remote: Option<&str>) -> Result<(), String> {
+    if dir.join(GPG_ID_FILE).exists() {
+        return Err(format!("'{}' already is a password store", dir.display()));
+    }
+    if dir.join(".git").exists() {
+        return Err(format!("'{}' already is a git repository", dir.display()));
+    }
+    let created_dir = !dir.exists();
+    let new_files: Vec<PathBuf> = [
+        GPG_ID_FILE,
+        GPG_ID_SIGNATURE_FILE,
+        GITATTRIBUTES_FILE,
+        ".git",
+    ]
+    .iter()
+    .map(|name| dir.join(name))
+    .filter(|path| !path.exists())
+    .collect();
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let result = populate(dir, recipients, remote);
+    if result.is_err() {
+        clean_up(dir, created_dir, &new_files);
+    }
+    result
+}
+
+/// Write the recipients and git repository of a new store in `dir`.
+fn populate(dir: &Path, recipients: &[String], remote: Option<&str>) -> Result<(), String> {
+    recipients::write_gpg_id(&crypto::GPG, dir, recipients).map_err(|e| e.to_string())?;
+    fs::write(dir.join(GITATTRIBUTES_FILE), "*.gpg diff=gpg\n").map_err(|e| e.to_string())?;
+    git::init(dir, INITIAL_COMMIT_MESSAGE).map_err(|e| e.to_string())?;
+    git::set_config(dir, "diff.gpg.binary", "true").map_err(|e| e.to_string())?;
+    git::set_config(dir, "diff.gpg.textconv", DIFF_TEXTCONV).map_err(|e| e.to_string())?;
+    if let Some(remote) = remote {
+        git::add_remote(dir, remote).map_err(|e| e.to_string())?;
+    }
+    debug!(
+        "Created a store for {} recipients in '{}'",
+        recipients.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Remove the `new_files` of a store that could not be created, and
+/// `dir` itself if it was `created` for the store.
+fn clean_up(dir: &Path, created: bool, new_files: &[PathBuf]) {
+    for path in new_files {
+        let result = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else if path.exists() {
+            fs::remove_file(path)
+        } else {
+            continue;
+        };
+        if let Err(e) = result {
+            error!("Failed to remove '{}': {}", path.display(), e);
+        }
+    }
+    if created {
+        // Fails if anything else was put there meanwhile, which is kept
+        if let Err(e) = fs::remove_dir(dir) {
+            debug!("Did not remove '{}': {}", dir.display(), e);
+        }
+    }
+}
+
+/// Messages for new store form interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    PickDirectory,
+    Toggle(usize, bool),
+    RemoteChanged(String),
+    /// The keys to encrypt to were listed in the background.
+    KeysListed(Result<Vec<KeyInfo>, String>),
+    Create,
+    /// The store was created in the background. Handled by the parent once
+    /// it succeeded.
+    Created(Result<Store, String>),
+    /// Return to the previous screen.
+    Back,
+}
+
+/// State of the new store form.
+pub struct State {
+    dir: Option<PathBuf>,
+    keys: Vec<(KeyInfo, bool)>,
+    /// Whether the keys are being listed in the background.
+    listing: bool,
+    remote: String,
+    /// Whether the store is being created in the background.
+    creating: bool,
+    error: Option<String>,
+}
+
+impl State {
+    /// An empty form, listing the keys in the background.
+    pub fn new() -> (Self, Task<Message>) {
+        let state = Self {
+            dir: None,
+            keys: Vec::new(),
+            listing: true,
+            remote: String::new(),
+            creating: false,
+            error: None,
+        };
+        let task = Task::perform(
+            async { crypto::encryption_keys().map_err(|e| e.to_string()) },
+            Message::KeysListed,
+        );
+        (state, task)
+    }
+
+    fn recipients(&self) -> Vec<String> {
+        self.keys
+            .iter()
+            .filter(|(_, selected)| *selected)
+            .map(|(key, _)| key.fingerprint.clone())
+            .collect()
+    }
+
+    /// Creates the store in the background, see [`Message::Created`].
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::PickDirectory => {
+                if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                    self.dir = Some(dir);
+                }
+            }
+            Message::Toggle(index, selected) => {
+                if let Some(key) = self.keys.get_mut(index) {
+                    key.1 = selected;
+                }
+            }
+            Message::RemoteChanged(remote) => self.remote = remote,
+            // A form opened again may get the keys of the previous listing
+            Message::KeysListed(_) if !self.listing => {}
+            Message::KeysListed(result) => {
+                self.listing = false;
+                match result {
+                    Ok(keys) => self.keys = keys.into_iter().map(|key| (key, false)).collect(),
+                    Err(e) => {
+                        error!("Failed to list keys: {}", e);
+                        self.error = Some(format!("Could not list the keys in the keyring: {}", e));
+                    }
+                }
+            }
+            Message::Create => {
+                let Some(dir) = self.dir.clone() else {
+                    return Task::none();
+                };
+                if self.creating {
+                    return Task::none();
+                }
+                let recipients = self.recipients();
+                let remote = Some(self.remote.trim().to_string()).filter(|r| !r.is_empty());
+                if let Some(Err(e)) = remote.as_deref().map(setup::validate_url) {
+                    self.error = Some(e);
+                    return Task::none();
+                }
+                self.creating = true;
+                self.error = None;
+                return Task::perform(
+                    async move {
+                        create(&dir, &recipients, remote.as_deref()).map_err(|e| {
+                            error!("Failed to create a store in '{}': {}", dir.display(), e);
+                            e
+                        })?;
+                        Ok(Store { root: dir, remote })
+                    },
+                    Message::Created,
+                );
+            }
+            Message::Created(result) => {
+                self.creating = false;
+                if let Err(e) = result {
+                    self.error = Some(e);
+                }
+            }
+            Message::Back => {}
+        }
+        Task::none()
+    }
+}
+
+/// Renders the new store form: directory, recipients and optional remote.
+///
+/// # Arguments
+/// * `state` - The current new store form state
+/// * `on_message` - Function to wrap new store messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let title = widget::text("Create a new store").size(24);
+
+    let dir_label = match &state.dir {
+        Some(dir) => dir.display().to_string(),
+        None => "No directory chosen".to_string(),
+    };
+    let dir_row = widget::row![
+        widget::text(dir_label).size(14).width(Length::Fill),
+        widget::button(widget::text("Choose...").size(14)).on_press(Message::PickDirectory)
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let mut keys = widget::column![].spacing(4);
+    for (index, (key, selected)) in state.keys.iter().enumerate() {
+        keys = keys.push(
+            widget::checkbox(key.to_string(), *selected)
+                .on_toggle(move |selected| Message::Toggle(index, selected)),
+        );
+    }
+    if state.listing {
+        keys = keys.push(widget::text("Listing keys...").size(14));
+    } else if state.keys.is_empty() {
+        keys = keys.push(widget::text("No keys to encrypt to found in the keyring.").size(14));
+    }
+
+    let remote = widget::text_input("git@github.com:team/store.git", &state.remote)
+        .on_input(Message::RemoteChanged)
+        .padding(8);
+
+    let error = state.error.as_ref().map(|e| {
+        widget::text(e)
+            .size(14)
+            .color(iced::Color::from_rgb(0.7, 0.1, 0.1))
+    });
+
+    let can_create =
+        !state.creating && state.dir.is_some() && state.keys.iter().any(|(_, selected)| *selected);
+    let buttons = widget::row![
+        widget::button(widget::text("Back").size(14))
+            .on_press_maybe((!state.creating).then_some(Message::Back)),
+        widget::horizontal_space(),
+        widget::button(
+            widget::text(if state.creating {
+                "Creating..."
+            } else {
+                "Create"
+            })
+            .size(14)
+        )
+        .on_press_maybe(can_create.then_some(Message::Create))
+    ];
+
+    let content: Element<'a, Message> = widget::column![
+        title,
+        widget::text("Directory of the store:").size(16),
+        dir_row,
+        widget::text("Encrypt secrets to:").size(16),
+        widget::scrollable(keys).height(Length::Fixed(200.0)),
+        widget::text("Remote repository (optional):").size(16),
+        remote
+    ]
+    .push_maybe(error)
+    .push(buttons)
+    .spacing(10)
+    .padding(20)
+    .width(500)
+    .into();
+    widget::container(content.map(on_message))
+        .center_x(Length::Fill)
+        .center_y(Length::Fill)
+        .into()
+}
@@ -0,0 +1,48 @@
+use iced::futures::Stream;
+use iced::task;
+use iced::Task;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Tells blocking work running in the background that it is no longer needed.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A running background task. Dropping the job cancels it.
+///
+/// Aborting the task alone only takes effect once its stream yields, which
+/// blocking work like decryption does not do, so the work is also given a
+/// flag to check between steps.
+pub struct Job {
+    handle: task::Handle,
+    flag: CancelFlag,
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.flag.cancel();
+        self.handle.abort();
+    }
+}
+
+/// Run the stream created by `make` as a task that can be cancelled with
+/// the returned job.
+pub fn spawn<T, S>(make: impl FnOnce(CancelFlag) -> S) -> (Task<T>, Job)
+where
+    T: Send + 'static,
+    S: Stream<Item = T> + Send + 'static,
+{
+    let flag = CancelFlag::default();
+    let (task, handle) = Task::stream(make(flag.clone())).abortable();
+    (task, Job { handle, flag })
+}
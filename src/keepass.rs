@@ -0,0 +1,1048 @@
+use crate::import::{Import, ImportedEntry};
+use crate::secret::{self, Secret};
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecryptMut, BlockEncrypt, KeyInit, KeyIvInit, StreamCipher};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use log::{debug, warn};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::mem;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const SIGNATURE_1: u32 = 0x9AA2_D903;
+const SIGNATURE_2: u32 = 0xB54B_FB67;
+
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+const KDF_AES: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+
+/// Fixed nonce of the Salsa20 inner stream of KDBX 3.1.
+const SALSA20_NONCE: [u8; 8] = [0xE8, 0x30, 0x09, 0x4B, 0x97, 0x20, 0x5D, 0x2A];
+const INNER_STREAM_SALSA20: u32 = 2;
+const INNER_STREAM_CHACHA20: u32 = 3;
+
+#[derive(Debug)]
+pub enum KeePassError {
+    IoError(std::io::Error),
+    NotKeePass,
+    Unsupported(String),
+    InvalidKey,
+    Corrupted(String),
+}
+
+impl fmt::Display for KeePassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeePassError::IoError(e) => write!(f, "Could not read file: {}", e),
+            KeePassError::NotKeePass => write!(f, "Not a KeePass database"),
+            KeePassError::Unsupported(what) => write!(f, "Unsupported database: {}", what),
+            KeePassError::InvalidKey => write!(f, "Wrong master password or key file"),
+            KeePassError::Corrupted(what) => write!(f, "Corrupted database: {}", what),
+        }
+    }
+}
+
+impl From<std::io::Error> for KeePassError {
+    fn from(e: std::io::Error) -> Self {
+        KeePassError::IoError(e)
+    }
+}
+
+fn corrupted(what: &str) -> KeePassError {
+    KeePassError::Corrupted(what.to_string())
+}
+
+/// Reads little-endian values from a byte slice.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], KeePassError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| corrupted("unexpected end of data"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, KeePassError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, KeePassError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, KeePassError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+}
+
+/// Outer header fields shared by KDBX 3.1 and 4.
+#[derive(Default)]
+struct Header {
+    cipher: Vec<u8>,
+    compressed: bool,
+    master_seed: Vec<u8>,
+    encryption_iv: Vec<u8>,
+    // KDBX 3.1 only
+    transform_seed: Vec<u8>,
+    transform_rounds: u64,
+    protected_stream_key: Vec<u8>,
+    stream_start_bytes: Vec<u8>,
+    inner_stream: u32,
+    // KDBX 4 only
+    kdf_parameters: HashMap<String, Vec<u8>>,
+}
+
+/// Read the header fields. KDBX 3.1 uses 16-bit field sizes, KDBX 4 32-bit ones.
+fn read_header(reader: &mut Reader, major: u16) -> Result<Header, KeePassError> {
+    let mut header = Header::default();
+    loop {
+        let id = reader.u8()?;
+        let size = if major >= 4 {
+            reader.u32()? as usize
+        } else {
+            reader.u16()? as usize
+        };
+        let data = reader.bytes(size)?;
+        match id {
+            0 => break,
+            2 => header.cipher = data.to_vec(),
+            3 => header.compressed = data.first().is_some_and(|flag| *flag == 1),
+            4 => header.master_seed = data.to_vec(),
+            5 => header.transform_seed = data.to_vec(),
+            6 => {
+                header.transform_rounds = u64::from_le_bytes(
+                    data.try_into()
+                        .map_err(|_| corrupted("invalid transform rounds"))?,
+                )
+            }
+            7 => header.encryption_iv = data.to_vec(),
+            8 => header.protected_stream_key = data.to_vec(),
+            9 => header.stream_start_bytes = data.to_vec(),
+            10 => {
+                header.inner_stream = u32::from_le_bytes(
+                    data.try_into()
+                        .map_err(|_| corrupted("invalid inner stream id"))?,
+                )
+            }
+            11 => header.kdf_parameters = read_variant_dictionary(data)?,
+            _ => debug!("Ignoring KeePass header field {}", id),
+        }
+    }
+    Ok(header)
+}
+
+/// Read a KDBX 4 variant dictionary, keeping the raw value bytes.
+fn read_variant_dictionary(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, KeePassError> {
+    let mut reader = Reader::new(data);
+    let _version = reader.u16()?;
+    let mut values = HashMap::new();
+    loop {
+        let value_type = reader.u8()?;
+        if value_type == 0 {
+            break;
+        }
+        let name_len = reader.u32()? as usize;
+        let name = String::from_utf8_lossy(reader.bytes(name_len)?).to_string();
+        let value_len = reader.u32()? as usize;
+        values.insert(name, reader.bytes(value_len)?.to_vec());
+    }
+    Ok(values)
+}
+
+fn dictionary_u64(dict: &HashMap<String, Vec<u8>>, key: &str) -> Result<u64, KeePassError> {
+    let value = dict
+        .get(key)
+        .ok_or_else(|| corrupted(&format!("missing KDF parameter {}", key)))?;
+    match value.len() {
+        4 => Ok(u32::from_le_bytes(value[..].try_into().unwrap()) as u64),
+        8 => Ok(u64::from_le_bytes(value[..].try_into().unwrap())),
+        _ => Err(corrupted(&format!("invalid KDF parameter {}", key))),
+    }
+}
+
+/// Derive the 32-byte key contributed by a key file.
+fn key_file_key(path: &Path) -> Result<Zeroizing<Vec<u8>>, KeePassError> {
+    let content = Zeroizing::new(fs::read(path)?);
+
+    // XML key files (version 1.0 base64, version 2.0 hex)
+    if let Ok(text) = std::str::from_utf8(&content) {
+        if let Ok(doc) = roxmltree::Document::parse(text) {
+            if let Some(data) = doc.descendants().find(|n| n.has_tag_name("Data")) {
+                let value: String = data
+                    .text()
+                    .unwrap_or_default()
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect();
+                let version = doc
+                    .descendants()
+                    .find(|n| n.has_tag_name("Version"))
+                    .and_then(|n| n.text())
+                    .unwrap_or("1.0");
+                let key = if version.starts_with('2') {
+                    decode_hex(&value)
+                } else {
+                    base64::engine::general_purpose::STANDARD
+                        .decode(&value)
+                        .ok()
+                };
+                return key
+                    .map(Zeroizing::new)
+                    .ok_or_else(|| corrupted("invalid key file"));
+            }
+        }
+        if content.len() == 64 {
+            if let Some(key) = decode_hex(text) {
+                return Ok(Zeroizing::new(key));
+            }
+        }
+    }
+
+    if content.len() == 32 {
+        return Ok(Zeroizing::new(content.to_vec()));
+    }
+    Ok(Zeroizing::new(Sha256::digest(&content[..]).to_vec()))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn composite_key(
+    password: &str,
+    key_file: Option<&Path>,
+) -> Result<Zeroizing<Vec<u8>>, KeePassError> {
+    let mut hasher = Sha256::new();
+    if !password.is_empty() {
+        hasher.update(Sha256::digest(password.as_bytes()));
+    }
+    if let Some(key_file) = key_file {
+        hasher.update(&key_file_key(key_file)?[..]);
+    }
+    Ok(Zeroizing::new(hasher.finalize().to_vec()))
+}
+
+fn aes_kdf(key: &[u8], seed: &[u8], rounds: u64) -> Result<Zeroizing<Vec<u8>>, KeePassError> {
+    let cipher = aes::Aes256::new_from_slice(seed).map_err(|_| corrupted("invalid KDF seed"))?;
+    let mut block = Zeroizing::new(key.to_vec());
+    for _ in 0..rounds {
+        for half in block.chunks_exact_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(half));
+        }
+    }
+    Ok(Zeroizing::new(Sha256::digest(&block[..]).to_vec()))
+}
+
+fn argon2_kdf(
+    key: &[u8],
+    dict: &HashMap<String, Vec<u8>>,
+    algorithm: argon2::Algorithm,
+) -> Result<Zeroizing<Vec<u8>>, KeePassError> {
+    let salt = dict
+        .get("S")
+        .ok_or_else(|| corrupted("missing Argon2 salt"))?;
+    let memory_kib = dictionary_u64(dict, "M")? / 1024;
+    let iterations = dictionary_u64(dict, "I")?;
+    let parallelism = dictionary_u64(dict, "P")?;
+    let version = match dictionary_u64(dict, "V")? {
+        0x10 => argon2::Version::V0x10,
+        _ => argon2::Version::V0x13,
+    };
+
+    let params = argon2::Params::new(
+        memory_kib as u32,
+        iterations as u32,
+        parallelism as u32,
+        Some(32),
+    )
+    .map_err(|e| corrupted(&format!("invalid Argon2 parameters: {}", e)))?;
+    let mut output = Zeroizing::new(vec![0u8; 32]);
+    argon2::Argon2::new(algorithm, version, params)
+        .hash_password_into(key, salt, &mut output)
+        .map_err(|e| corrupted(&format!("Argon2 failed: {}", e)))?;
+    Ok(output)
+}
+
+fn decrypt_payload(
+    cipher: &[u8],
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, KeePassError> {
+    let mut buffer = Zeroizing::new(data.to_vec());
+    if cipher == CIPHER_AES256 {
+        // A wrong key shows up as invalid padding, a truncated file must not
+        if !data.len().is_multiple_of(16) {
+            return Err(corrupted("truncated encrypted data"));
+        }
+        let decryptor = cbc::Decryptor::<aes::Aes256>::new_from_slices(key, iv)
+            .map_err(|_| corrupted("invalid encryption IV"))?;
+        let len = decryptor
+            .decrypt_padded_mut::<Pkcs7>(&mut buffer)
+            .map_err(|_| KeePassError::InvalidKey)?
+            .len();
+        buffer.truncate(len);
+        Ok(buffer)
+    } else if cipher == CIPHER_CHACHA20 {
+        let mut decryptor = chacha20::ChaCha20::new_from_slices(key, iv)
+            .map_err(|_| corrupted("invalid encryption IV"))?;
+        decryptor.apply_keystream(&mut buffer);
+        Ok(buffer)
+    } else {
+        Err(KeePassError::Unsupported(
+            "only AES-256 and ChaCha20 encryption are supported".to_string(),
+        ))
+    }
+}
+
+fn decompress(data: &[u8]) -> Result<Zeroizing<Vec<u8>>, KeePassError> {
+    let mut output = Zeroizing::new(Vec::new());
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut output)
+        .map_err(|e| corrupted(&format!("invalid compressed data: {}", e)))?;
+    Ok(output)
+}
+
+/// Key stream used to hide protected values (passwords) inside the XML.
+enum InnerStream {
+    Salsa20(salsa20::Salsa20),
+    ChaCha20(chacha20::ChaCha20),
+}
+
+impl InnerStream {
+    fn new(id: u32, key: &[u8]) -> Result<Self, KeePassError> {
+        match id {
+            INNER_STREAM_SALSA20 => {
+                let key = Sha256::digest(key);
+                Ok(InnerStream::Salsa20(salsa20::Salsa20::new(
+                    &key,
+                    GenericArray::from_slice(&SALSA20_NONCE),
+                )))
+            }
+            INNER_STREAM_CHACHA20 => {
+                let hash = Sha512::digest(key);
+                let cipher = chacha20::ChaCha20::new_from_slices(&hash[..32], &hash[32..44])
+                    .map_err(|_| corrupted("invalid inner stream key"))?;
+                Ok(InnerStream::ChaCha20(cipher))
+            }
+            other => Err(KeePassError::Unsupported(format!(
+                "inner stream cipher {}",
+                other
+            ))),
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        match self {
+            InnerStream::Salsa20(cipher) => cipher.apply_keystream(data),
+            InnerStream::ChaCha20(cipher) => cipher.apply_keystream(data),
+        }
+    }
+}
+
+/// Decrypt a KDBX 3.1 payload and return the XML and its inner stream.
+fn read_kdbx3(
+    header: &Header,
+    payload: &[u8],
+    composite: &[u8],
+) -> Result<(Zeroizing<Vec<u8>>, InnerStream), KeePassError> {
+    let transformed = aes_kdf(composite, &header.transform_seed, header.transform_rounds)?;
+    let mut hasher = Sha256::new();
+    hasher.update(&header.master_seed);
+    hasher.update(&transformed[..]);
+    let master_key = Zeroizing::new(hasher.finalize().to_vec());
+
+    let decrypted = decrypt_payload(&header.cipher, &master_key, &header.encryption_iv, payload)?;
+    if !decrypted.starts_with(&header.stream_start_bytes) {
+        return Err(KeePassError::InvalidKey);
+    }
+
+    // Hashed block stream: index, SHA-256 of the block, size, data
+    let mut reader = Reader::new(&decrypted[header.stream_start_bytes.len()..]);
+    let mut content = Zeroizing::new(Vec::new());
+    loop {
+        let _index = reader.u32()?;
+        let hash = reader.bytes(32)?;
+        let size = reader.u32()? as usize;
+        if size == 0 {
+            break;
+        }
+        let block = reader.bytes(size)?;
+        if Sha256::digest(block)[..] != hash[..] {
+            return Err(corrupted("block hash mismatch"));
+        }
+        content.extend_from_slice(block);
+    }
+
+    let xml = if header.compressed {
+        decompress(&content)?
+    } else {
+        content
+    };
+    let inner = InnerStream::new(header.inner_stream, &header.protected_stream_key)?;
+    Ok((xml, inner))
+}
+
+/// Decrypt a KDBX 4 payload and return the XML and its inner stream.
+fn read_kdbx4(
+    header: &Header,
+    header_bytes: &[u8],
+    rest: &[u8],
+    composite: &[u8],
+) -> Result<(Zeroizing<Vec<u8>>, InnerStream), KeePassError> {
+    let mut reader = Reader::new(rest);
+    let header_hash = reader.bytes(32)?;
+    let header_hmac = reader.bytes(32)?;
+    if Sha256::digest(header_bytes)[..] != header_hash[..] {
+        return Err(corrupted("header hash mismatch"));
+    }
+
+    let kdf = header
+        .kdf_parameters
+        .get("$UUID")
+        .ok_or_else(|| corrupted("missing KDF"))?;
+    let transformed = if kdf[..] == KDF_AES {
+        let seed = header
+            .kdf_parameters
+            .get("S")
+            .ok_or_else(|| corrupted("missing KDF seed"))?;
+        let rounds = dictionary_u64(&header.kdf_parameters, "R")?;
+        aes_kdf(composite, seed, rounds)?
+    } else if kdf[..] == KDF_ARGON2D {
+        argon2_kdf(
+            composite,
+            &header.kdf_parameters,
+            argon2::Algorithm::Argon2d,
+        )?
+    } else if kdf[..] == KDF_ARGON2ID {
+        argon2_kdf(
+            composite,
+            &header.kdf_parameters,
+            argon2::Algorithm::Argon2id,
+        )?
+    } else {
+        return Err(KeePassError::Unsupported(
+            "unknown key derivation".to_string(),
+        ));
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(&header.master_seed);
+    hasher.update(&transformed[..]);
+    let master_key = Zeroizing::new(hasher.finalize().to_vec());
+
+    let mut hasher = Sha512::new();
+    hasher.update(&header.master_seed);
+    hasher.update(&transformed[..]);
+    hasher.update([1u8]);
+    let hmac_base = Zeroizing::new(hasher.finalize().to_vec());
+    let block_key = |index: u64| {
+        let mut hasher = Sha512::new();
+        hasher.update(index.to_le_bytes());
+        hasher.update(&hmac_base[..]);
+        Zeroizing::new(hasher.finalize().to_vec())
+    };
+
+    // A wrong key shows up as a header HMAC mismatch
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&block_key(u64::MAX)).unwrap();
+    mac.update(header_bytes);
+    mac.verify_slice(header_hmac)
+        .map_err(|_| KeePassError::InvalidKey)?;
+
+    // HMAC block stream: HMAC, size, data
+    let mut encrypted = Vec::new();
+    for index in 0u64.. {
+        let hmac = reader.bytes(32)?;
+        let size_bytes = reader.bytes(4)?;
+        let size = u32::from_le_bytes(size_bytes.try_into().unwrap()) as usize;
+        let block = reader.bytes(size)?;
+
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&block_key(index)).unwrap();
+        mac.update(&index.to_le_bytes());
+        mac.update(size_bytes);
+        mac.update(block);
+        mac.verify_slice(hmac)
+            .map_err(|_| corrupted("block HMAC mismatch"))?;
+
+        if size == 0 {
+            break;
+        }
+        encrypted.extend_from_slice(block);
+    }
+
+    let decrypted = decrypt_payload(
+        &header.cipher,
+        &master_key,
+        &header.encryption_iv,
+        &encrypted,
+    )?;
+    let content = if header.compressed {
+        decompress(&decrypted)?
+    } else {
+        decrypted
+    };
+
+    // Inner header with the protected stream cipher and key
+    let mut reader = Reader::new(&content);
+    let mut stream_id = 0;
+    let mut stream_key = Zeroizing::new(Vec::new());
+    loop {
+        let id = reader.u8()?;
+        let size = reader.u32()? as usize;
+        let data = reader.bytes(size)?;
+        match id {
+            0 => break,
+            1 => {
+                stream_id = u32::from_le_bytes(
+                    data.try_into()
+                        .map_err(|_| corrupted("invalid inner stream id"))?,
+                )
+            }
+            2 => stream_key = Zeroizing::new(data.to_vec()),
+            // Binary attachments are not imported
+            _ => {}
+        }
+    }
+
+    let xml = Zeroizing::new(reader.rest().to_vec());
+    let inner = InnerStream::new(stream_id, &stream_key)?;
+    Ok((xml, inner))
+}
+
+/// Map the KeePass XML to pass-format entries.
+fn read_xml(xml: &str, mut inner: InnerStream) -> Result<Import, KeePassError> {
+    let doc =
+        roxmltree::Document::parse(xml).map_err(|e| corrupted(&format!("invalid XML: {}", e)))?;
+
+    // Protected values must be decrypted in document order, including those
+    // of history entries that are not imported
+    let mut protected: HashMap<roxmltree::NodeId, Zeroizing<String>> = HashMap::new();
+    for node in doc
+        .descendants()
+        .filter(|n| n.has_tag_name("Value") && n.attribute("Protected") == Some("True"))
+    {
+        let mut data = base64::engine::general_purpose::STANDARD
+            .decode(node.text().unwrap_or_default())
+            .map_err(|_| corrupted("invalid protected value"))?;
+        inner.apply(&mut data);
+        let value = Zeroizing::new(String::from_utf8_lossy(&data).to_string());
+        zeroize::Zeroize::zeroize(&mut data);
+        protected.insert(node.id(), value);
+    }
+
+    let recycle_bin = doc
+        .descendants()
+        .find(|n| n.has_tag_name("RecycleBinUUID"))
+        .and_then(|n| n.text())
+        .filter(|uuid| !uuid.is_empty() && *uuid != "AAAAAAAAAAAAAAAAAAAAAA==");
+
+    let root_group = doc
+        .root_element()
+        .children()
+        .find(|n| n.has_tag_name("Root"))
+        .and_then(|root| root.children().find(|n| n.has_tag_name("Group")))
+        .ok_or_else(|| corrupted("missing root group"))?;
+
+    let mut import = Import::default();
+    read_group(root_group, &[], recycle_bin, &protected, &mut import);
+    Ok(import)
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    node.children()
+        .find(|n| n.has_tag_name(tag))
+        .and_then(|n| n.text())
+}
+
+fn read_group(
+    group: roxmltree::Node,
+    folder: &[String],
+    recycle_bin: Option<&str>,
+    protected: &HashMap<roxmltree::NodeId, Zeroizing<String>>,
+    import: &mut Import,
+) {
+    for child in group.children() {
+        if child.has_tag_name("Group") {
+            if recycle_bin.is_some() && child_text(child, "UUID") == recycle_bin {
+                debug!("Skipping recycle bin");
+                continue;
+            }
+            let mut child_folder = folder.to_vec();
+            child_folder.push(child_text(child, "Name").unwrap_or("Unnamed").to_string());
+            read_group(child, &child_folder, recycle_bin, protected, import);
+        } else if child.has_tag_name("Entry") {
+            read_entry(child, folder, protected, import);
+        }
+    }
+}
+
+fn read_entry(
+    entry: roxmltree::Node,
+    folder: &[String],
+    protected: &HashMap<roxmltree::NodeId, Zeroizing<String>>,
+    import: &mut Import,
+) {
+    let mut title = String::new();
+    let mut secret = Secret::new("");
+    let mut username = Zeroizing::new(String::new());
+    let mut url = Zeroizing::new(String::new());
+    let mut notes = Zeroizing::new(String::new());
+    let mut totp_secret: Option<Zeroizing<String>> = None;
+    let mut custom: Vec<(String, Zeroizing<String>)> = Vec::new();
+
+    for string in entry.children().filter(|n| n.has_tag_name("String")) {
+        let Some(key) = child_text(string, "Key") else {
+            continue;
+        };
+        let Some(value_node) = string.children().find(|n| n.has_tag_name("Value")) else {
+            continue;
+        };
+        let mut value = match protected.get(&value_node.id()) {
+            Some(value) => value.clone(),
+            None => Zeroizing::new(value_node.text().unwrap_or_default().to_string()),
+        };
+
+        // Values are moved out of their `Zeroizing` only into the secret,
+        // which clears them when dropped
+        match key {
+            "Title" => title = value.to_string(),
+            "Password" => secret.password = mem::take(&mut *value),
+            "UserName" => username = value,
+            "URL" => url = value,
+            "Notes" => notes = value,
+            "otp" if value.starts_with("otpauth://") => secret.otp = Some(mem::take(&mut *value)),
+            "TimeOtp-Secret-Base32" => totp_secret = Some(value),
+            _ => custom.push((key.to_string(), value)),
+        }
+    }
+
+    if secret.otp.is_none() {
+        if let Some(totp_secret) = totp_secret {
+            secret.otp = Some(secret::otp_uri(&title, &totp_secret));
+        }
+    }
+
+    let attachments = entry
+        .children()
+        .filter(|n| n.has_tag_name("Binary"))
+        .count();
+    if attachments > 0 {
+        warn!("Skipping {} attachments of '{}'", attachments, title);
+        import
+            .skipped
+            .push(format!("{}: {} attachments", title, attachments));
+    }
+
+    secret.push_field("username", mem::take(&mut *username));
+    secret.push_field("url", mem::take(&mut *url));
+    for (key, mut value) in custom {
+        secret.push_field(key, mem::take(&mut *value));
+    }
+    secret.push_field("notes", mem::take(&mut *notes));
+
+    import.entries.push(ImportedEntry {
+        folder: folder.to_vec(),
+        name: title,
+        secret,
+    });
+}
+
+/// Read and decrypt a KeePass KDBX 3.1 or 4 database.
+pub fn read(path: &Path, password: &str, key_file: Option<&Path>) -> Result<Import, KeePassError> {
+    let data = Zeroizing::new(fs::read(path)?);
+    let mut reader = Reader::new(&data);
+
+    if reader.u32()? != SIGNATURE_1 || reader.u32()? != SIGNATURE_2 {
+        return Err(KeePassError::NotKeePass);
+    }
+    let _minor = reader.u16()?;
+    let major = reader.u16()?;
+    if major != 3 && major != 4 {
+        return Err(KeePassError::Unsupported(format!("KDBX version {}", major)));
+    }
+    debug!("Reading KDBX {} database '{}'", major, path.display());
+
+    let header = read_header(&mut reader, major)?;
+    let header_bytes = &data[..reader.position];
+    let composite = composite_key(password, key_file)?;
+
+    let (xml, inner) = if major >= 4 {
+        read_kdbx4(&header, header_bytes, reader.rest(), &composite)?
+    } else {
+        read_kdbx3(&header, reader.rest(), &composite)?
+    };
+
+    let xml = std::str::from_utf8(&xml).map_err(|_| corrupted("invalid UTF-8 in XML"))?;
+    read_xml(xml, inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncryptMut;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    const PASSWORD: &str = "correct horse";
+    const RECYCLE_BIN: &str = "AAAAAAAAAAAAAAAAAAAAAw==";
+
+    /// XML of the fixture databases, with protected values hidden by `inner`.
+    fn database_xml(inner: &mut InnerStream) -> String {
+        let mut protect = |value: &str| {
+            let mut data = value.as_bytes().to_vec();
+            inner.apply(&mut data);
+            base64::engine::general_purpose::STANDARD.encode(data)
+        };
+        // Protected values are hidden in document order
+        let mail = protect("hunter2");
+        let totp = protect("JBSW Y3DP");
+        let history = protect("old password");
+        let vpn = protect("vpn-secret");
+        let deleted = protect("deleted");
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile>
+  <Meta><RecycleBinUUID>{RECYCLE_BIN}</RecycleBinUUID></Meta>
+  <Root>
+    <Group>
+      <UUID>AAAAAAAAAAAAAAAAAAAAAQ==</UUID>
+      <Name>Root</Name>
+      <Entry>
+        <String><Key>Title</Key><Value>Mail</Value></String>
+        <String><Key>UserName</Key><Value>alice</Value></String>
+        <String><Key>Password</Key><Value Protected="True">{mail}</Value></String>
+        <String><Key>URL</Key><Value>https://mail.example.com</Value></String>
+        <String><Key>TimeOtp-Secret-Base32</Key><Value Protected="True">{totp}</Value></String>
+        <Binary><Key>codes.txt</Key><Value Ref="0"/></Binary>
+        <History>
+          <Entry>
+            <String><Key>Password</Key><Value Protected="True">{history}</Value></String>
+          </Entry>
+        </History>
+      </Entry>
+      <Group>
+        <UUID>AAAAAAAAAAAAAAAAAAAAAg==</UUID>
+        <Name>Work</Name>
+        <Entry>
+          <String><Key>Title</Key><Value>VPN</Value></String>
+          <String><Key>Password</Key><Value Protected="True">{vpn}</Value></String>
+          <String><Key>PIN</Key><Value>1234</Value></String>
+          <String><Key>Notes</Key><Value>line one
+line two</Value></String>
+        </Entry>
+      </Group>
+      <Group>
+        <UUID>{RECYCLE_BIN}</UUID>
+        <Name>Recycle Bin</Name>
+        <Entry>
+          <String><Key>Title</Key><Value>Old</Value></String>
+          <String><Key>Password</Key><Value Protected="True">{deleted}</Value></String>
+        </Entry>
+      </Group>
+    </Group>
+  </Root>
+</KeePassFile>"#
+        )
+    }
+
+    fn check_entries(import: &Import) {
+        assert_eq!(import.entries.len(), 2);
+        let mail = &import.entries[0];
+        assert!(mail.folder.is_empty());
+        assert_eq!(mail.name, "Mail");
+        assert_eq!(
+            mail.secret.to_text(),
+            "hunter2\nusername: alice\nurl: https://mail.example.com\n\
+             otpauth://totp/Mail?secret=JBSWY3DP\n"
+        );
+        let vpn = &import.entries[1];
+        assert_eq!(vpn.folder, ["Work"]);
+        assert_eq!(vpn.name, "VPN");
+        assert_eq!(
+            vpn.secret.to_text(),
+            "vpn-secret\nPIN: 1234\nnotes: line one\n  line two\n"
+        );
+        assert_eq!(import.skipped, ["Mail: 1 attachments"]);
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("defira-keepass-{}-{}", std::process::id(), name))
+    }
+
+    /// Read `data` as a database file named `name`.
+    fn read_database(
+        name: &str,
+        data: &[u8],
+        password: &str,
+        key_file: Option<&Path>,
+    ) -> Result<Import, KeePassError> {
+        let path = temp_path(name);
+        fs::write(&path, data).unwrap();
+        let result = read(&path, password, key_file);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn header_field(out: &mut Vec<u8>, major: u16, id: u8, data: &[u8]) {
+        out.push(id);
+        if major >= 4 {
+            out.extend((data.len() as u32).to_le_bytes());
+        } else {
+            out.extend((data.len() as u16).to_le_bytes());
+        }
+        out.extend_from_slice(data);
+    }
+
+    fn signature(major: u16) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(SIGNATURE_1.to_le_bytes());
+        out.extend(SIGNATURE_2.to_le_bytes());
+        out.extend(1u16.to_le_bytes());
+        out.extend(major.to_le_bytes());
+        out
+    }
+
+    fn variant(out: &mut Vec<u8>, value_type: u8, name: &str, value: &[u8]) {
+        out.push(value_type);
+        out.extend((name.len() as u32).to_le_bytes());
+        out.extend(name.as_bytes());
+        out.extend((value.len() as u32).to_le_bytes());
+        out.extend(value);
+    }
+
+    /// A KDBX 3.1 database with AES-KDF, AES-256, gzip compression and a
+    /// Salsa20 inner stream.
+    fn kdbx3(password: &str, key_file: Option<&Path>) -> Vec<u8> {
+        let master_seed = [1u8; 32];
+        let transform_seed = [2u8; 32];
+        let rounds = 100u64;
+        let iv = [3u8; 16];
+        let stream_key = [4u8; 32];
+        let start_bytes = [5u8; 32];
+
+        let mut out = signature(3);
+        header_field(&mut out, 3, 2, &CIPHER_AES256);
+        header_field(&mut out, 3, 3, &1u32.to_le_bytes());
+        header_field(&mut out, 3, 4, &master_seed);
+        header_field(&mut out, 3, 5, &transform_seed);
+        header_field(&mut out, 3, 6, &rounds.to_le_bytes());
+        header_field(&mut out, 3, 7, &iv);
+        header_field(&mut out, 3, 8, &stream_key);
+        header_field(&mut out, 3, 9, &start_bytes);
+        header_field(&mut out, 3, 10, &INNER_STREAM_SALSA20.to_le_bytes());
+        header_field(&mut out, 3, 0, b"\r\n\r\n");
+
+        let mut inner = InnerStream::new(INNER_STREAM_SALSA20, &stream_key).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(database_xml(&mut inner).as_bytes())
+            .unwrap();
+        let content = encoder.finish().unwrap();
+
+        // Hashed block stream, ended by an empty block
+        let mut payload = start_bytes.to_vec();
+        payload.extend(0u32.to_le_bytes());
+        payload.extend(Sha256::digest(&content));
+        payload.extend((content.len() as u32).to_le_bytes());
+        payload.extend(&content);
+        payload.extend(1u32.to_le_bytes());
+        payload.extend([0u8; 32]);
+        payload.extend(0u32.to_le_bytes());
+
+        let composite = composite_key(password, key_file).unwrap();
+        let transformed = aes_kdf(&composite, &transform_seed, rounds).unwrap();
+        let master_key = Sha256::new()
+            .chain_update(master_seed)
+            .chain_update(&transformed[..])
+            .finalize();
+        let mut buffer = payload.clone();
+        buffer.resize(payload.len() + 16, 0);
+        let encrypted = cbc::Encryptor::<aes::Aes256>::new_from_slices(&master_key, &iv)
+            .unwrap()
+            .encrypt_padded_mut::<Pkcs7>(&mut buffer, payload.len())
+            .unwrap();
+        out.extend_from_slice(encrypted);
+        out
+    }
+
+    /// A KDBX 4 database with Argon2id, ChaCha20, an attachment in the inner
+    /// header and a ChaCha20 inner stream.
+    fn kdbx4(password: &str, key_file: Option<&Path>) -> Vec<u8> {
+        let master_seed = [6u8; 32];
+        let iv = [7u8; 12];
+        let salt = [8u8; 32];
+        let stream_key = [9u8; 64];
+
+        let mut kdf = 0x0100u16.to_le_bytes().to_vec();
+        variant(&mut kdf, 0x42, "$UUID", &KDF_ARGON2ID);
+        variant(&mut kdf, 0x42, "S", &salt);
+        variant(&mut kdf, 0x05, "M", &(64 * 1024u64).to_le_bytes());
+        variant(&mut kdf, 0x05, "I", &2u64.to_le_bytes());
+        variant(&mut kdf, 0x04, "P", &1u32.to_le_bytes());
+        variant(&mut kdf, 0x04, "V", &0x13u32.to_le_bytes());
+        kdf.push(0);
+
+        let mut out = signature(4);
+        header_field(&mut out, 4, 2, &CIPHER_CHACHA20);
+        header_field(&mut out, 4, 3, &0u32.to_le_bytes());
+        header_field(&mut out, 4, 4, &master_seed);
+        header_field(&mut out, 4, 7, &iv);
+        header_field(&mut out, 4, 11, &kdf);
+        header_field(&mut out, 4, 0, b"\r\n\r\n");
+        let header_bytes = out.clone();
+
+        let composite = composite_key(password, key_file).unwrap();
+        let parameters = read_variant_dictionary(&kdf).unwrap();
+        let transformed = argon2_kdf(&composite, &parameters, argon2::Algorithm::Argon2id).unwrap();
+        let master_key = Sha256::new()
+            .chain_update(master_seed)
+            .chain_update(&transformed[..])
+            .finalize();
+        let hmac_base = Sha512::new()
+            .chain_update(master_seed)
+            .chain_update(&transformed[..])
+            .chain_update([1u8])
+            .finalize();
+        let hmac = |index: u64, data: &[u8]| {
+            let key = Sha512::new()
+                .chain_update(index.to_le_bytes())
+                .chain_update(hmac_base)
+                .finalize();
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).unwrap();
+            mac.update(data);
+            mac.finalize().into_bytes()
+        };
+        out.extend(Sha256::digest(&header_bytes));
+        out.extend(hmac(u64::MAX, &header_bytes));
+
+        let mut content = Vec::new();
+        header_field(&mut content, 4, 1, &INNER_STREAM_CHACHA20.to_le_bytes());
+        header_field(&mut content, 4, 2, &stream_key);
+        header_field(&mut content, 4, 3, b"\x01backup codes");
+        header_field(&mut content, 4, 0, &[]);
+        let mut inner = InnerStream::new(INNER_STREAM_CHACHA20, &stream_key).unwrap();
+        content.extend(database_xml(&mut inner).as_bytes());
+        chacha20::ChaCha20::new_from_slices(&master_key, &iv)
+            .unwrap()
+            .apply_keystream(&mut content);
+
+        // HMAC block stream, ended by an empty block
+        let blocks: [&[u8]; 2] = [&content, &[]];
+        for (index, block) in blocks.into_iter().enumerate() {
+            let index = index as u64;
+            let size = (block.len() as u32).to_le_bytes();
+            let mut data = index.to_le_bytes().to_vec();
+            data.extend(size);
+            data.extend(block);
+            out.extend(hmac(index, &data));
+            out.extend(size);
+            out.extend(block);
+        }
+        out
+    }
+
+    #[test]
+    fn reads_kdbx3_with_aes_kdf() {
+        let import = read_database("kdbx3", &kdbx3(PASSWORD, None), PASSWORD, None).unwrap();
+        check_entries(&import);
+    }
+
+    #[test]
+    fn reads_kdbx4_with_argon2id() {
+        let import = read_database("kdbx4", &kdbx4(PASSWORD, None), PASSWORD, None).unwrap();
+        check_entries(&import);
+    }
+
+    #[test]
+    fn reads_database_with_key_file() {
+        let key_file = temp_path("key.keyx");
+        fs::write(
+            &key_file,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<KeyFile>
+  <Meta><Version>2.0</Version></Meta>
+  <Key>
+    <Data Hash="0000">
+      00112233 44556677 8899AABB CCDDEEFF
+      00112233 44556677 8899AABB CCDDEEFF
+    </Data>
+  </Key>
+</KeyFile>"#,
+        )
+        .unwrap();
+        let data = kdbx4(PASSWORD, Some(&key_file));
+
+        let with_key = read_database("key-file", &data, PASSWORD, Some(&key_file));
+        let without_key = read_database("no-key-file", &data, PASSWORD, None);
+        fs::remove_file(&key_file).unwrap();
+        check_entries(&with_key.unwrap());
+        assert!(matches!(without_key, Err(KeePassError::InvalidKey)));
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let kdbx3 = read_database("wrong-3", &kdbx3(PASSWORD, None), "wrong", None);
+        assert!(matches!(kdbx3, Err(KeePassError::InvalidKey)));
+        let kdbx4 = read_database("wrong-4", &kdbx4(PASSWORD, None), "wrong", None);
+        assert!(matches!(kdbx4, Err(KeePassError::InvalidKey)));
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let data = kdbx3(PASSWORD, None);
+        let result = read_database("truncated-3", &data[..data.len() - 7], PASSWORD, None);
+        assert!(matches!(result, Err(KeePassError::Corrupted(_))));
+
+        let data = kdbx4(PASSWORD, None);
+        let result = read_database("truncated-4", &data[..data.len() - 10], PASSWORD, None);
+        assert!(matches!(result, Err(KeePassError::Corrupted(_))));
+        let result = read_database("truncated-header", &data[..40], PASSWORD, None);
+        assert!(matches!(result, Err(KeePassError::Corrupted(_))));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let result = read_database("other", b"not a database at all", PASSWORD, None);
+        assert!(matches!(result, Err(KeePassError::NotKeePass)));
+    }
+}
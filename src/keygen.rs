@@ -0,0 +1,340 @@
+//! Generating a GPG key for a new team member: the key pair, the public key
+//! to send to the store admin and a revocation certificate to keep safe.
+
+use crate::crypto;
+use crate::export;
+use crate::strength;
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Passphrases below this strength score are refused.
+const MIN_SCORE: u8 = 3;
+const DAY: u64 = 24 * 60 * 60;
+
+/// How long the new key is valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    OneYear,
+    TwoYears,
+    FiveYears,
+    Never,
+}
+
+impl Expiry {
+    const ALL: [Expiry; 4] = [
+        Expiry::OneYear,
+        Expiry::TwoYears,
+        Expiry::FiveYears,
+        Expiry::Never,
+    ];
+
+    fn duration(self) -> Option<Duration> {
+        let years = match self {
+            Expiry::OneYear => 1,
+            Expiry::TwoYears => 2,
+            Expiry::FiveYears => 5,
+            Expiry::Never => return None,
+        };
+        Some(Duration::from_secs(years * 365 * DAY))
+    }
+}
+
+impl fmt::Display for Expiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expiry::OneYear => write!(f, "1 year"),
+            Expiry::TwoYears => write!(f, "2 years"),
+            Expiry::FiveYears => write!(f, "5 years"),
+            Expiry::Never => write!(f, "Never"),
+        }
+    }
+}
+
+/// Messages for key generation panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    NameChanged(String),
+    EmailChanged(String),
+    ExpirySelected(Expiry),
+    PassphraseChanged(String),
+    ConfirmationChanged(String),
+    Generate,
+    Generated(Result<String, String>),
+    /// Save the public key to send to the store admin.
+    SavePublicKey,
+    SaveRevocation,
+    Close,
+}
+
+/// State of the key generation panel.
+pub struct State {
+    name: String,
+    email: String,
+    expiry: Expiry,
+    passphrase: Zeroizing<String>,
+    confirmation: Zeroizing<String>,
+    generating: bool,
+    /// Fingerprint of the generated key.
+    fingerprint: Option<String>,
+    /// Files written for the generated key.
+    saved: Vec<String>,
+    error: Option<String>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            email: String::new(),
+            expiry: Expiry::TwoYears,
+            passphrase: Zeroizing::new(String::new()),
+            confirmation: Zeroizing::new(String::new()),
+            generating: false,
+            fingerprint: None,
+            saved: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+impl State {
+    fn user_id(&self) -> String {
+        format!("{} <{}>", self.name.trim(), self.email.trim())
+    }
+
+    /// Why the form cannot be submitted yet, if it cannot.
+    fn problem(&self) -> Option<&'static str> {
+        if self.name.trim().is_empty() {
+            Some("Enter your name.")
+        } else if !self.email.contains('@') {
+            Some("Enter your email address.")
+        } else if self.passphrase.is_empty() {
+            Some("Choose a passphrase to protect the key.")
+        } else if strength::score(&self.passphrase) < MIN_SCORE {
+            Some("The passphrase is too easy to guess. Use a longer one.")
+        } else if self.passphrase != self.confirmation {
+            Some("The passphrases do not match.")
+        } else {
+            None
+        }
+    }
+
+    /// Ask where to save `data` and write it there, readable only by the
+    /// owner if it is `private`.
+    fn save(&mut self, name: String, data: std::io::Result<Vec<u8>>, private: bool) {
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        let Some(destination) = rfd::FileDialog::new().set_file_name(name).save_file() else {
+            return;
+        };
+        let result = if private {
+            export::create_private(&destination).and_then(|mut file| file.write_all(&data))
+        } else {
+            fs::write(&destination, data)
+        };
+        match result {
+            Ok(()) => self.saved.push(destination.display().to_string()),
+            Err(e) => {
+                error!("Failed to write '{}': {}", destination.display(), e);
+                self.error = Some(format!(
+                    "Could not write '{}': {}",
+                    destination.display(),
+                    e
+                ));
+            }
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::NameChanged(name) => self.name = name,
+            Message::EmailChanged(email) => self.email = email,
+            Message::ExpirySelected(expiry) => self.expiry = expiry,
+            Message::PassphraseChanged(passphrase) => self.passphrase = Zeroizing::new(passphrase),
+            Message::ConfirmationChanged(confirmation) => {
+                self.confirmation = Zeroizing::new(confirmation)
+            }
+            Message::Generate => {
+                if self.generating || self.problem().is_some() {
+                    return Task::none();
+                }
+                self.generating = true;
+                self.error = None;
+                let user_id = self.user_id();
+                let expires = self.expiry.duration();
+                let passphrase = self.passphrase.clone();
+                return Task::perform(
+                    async move {
+                        crypto::generate_key(&user_id, expires, &passphrase)
+                            .map_err(|e| e.to_string())
+                    },
+                    Message::Generated,
+                );
+            }
+            Message::Generated(result) => {
+                self.generating = false;
+                self.passphrase = Zeroizing::new(String::new());
+                self.confirmation = Zeroizing::new(String::new());
+                match result {
+                    Ok(fingerprint) => self.fingerprint = Some(fingerprint),
+                    Err(e) => {
+                        error!("Failed to generate a key: {}", e);
+                        self.error = Some(format!("Could not generate the key: {}", e));
+                    }
+                }
+            }
+            Message::SavePublicKey => {
+                if let Some(fingerprint) = self.fingerprint.clone() {
+                    let data = crypto::export_public_key(&fingerprint)
+                        .map_err(|e| std::io::Error::other(e.to_string()));
+                    self.save(format!("{}.asc", self.email.trim()), data, false);
+                }
+            }
+            Message::SaveRevocation => {
+                if let Some(fingerprint) = self.fingerprint.clone() {
+                    let data = crypto::revocation_certificate(&fingerprint);
+                    // Anyone with the certificate can revoke the key
+                    self.save(format!("{}-revocation.asc", self.email.trim()), data, true);
+                }
+            }
+            Message::Close => {}
+        }
+        Task::none()
+    }
+}
+
+fn view_form(state: &State) -> Element<'_, Message> {
+    let info = widget::text(
+        "Creates a modern key (ed25519 for signing, cv25519 for encryption) in \
+         your GnuPG keyring.",
+    )
+    .size(14);
+    let expiry = widget::row![
+        widget::text("Expires after").size(14),
+        widget::pick_list(Expiry::ALL, Some(state.expiry), Message::ExpirySelected)
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+    let strength = (!state.passphrase.is_empty()).then(|| {
+        widget::text(format!(
+            "Passphrase strength: {} of 4",
+            strength::score(&state.passphrase)
+        ))
+        .size(12)
+    });
+    let hint = state.problem().map(|p| widget::text(p).size(12));
+
+    let label = if state.generating {
+        "Generating..."
+    } else {
+        "Generate key"
+    };
+    let generate = widget::button(widget::text(label).size(14)).on_press_maybe(
+        (!state.generating && state.problem().is_none()).then_some(Message::Generate),
+    );
+
+    widget::column![
+        info,
+        widget::text_input("Full name", &state.name).on_input(Message::NameChanged),
+        widget::text_input("Email address", &state.email).on_input(Message::EmailChanged),
+        expiry,
+        widget::text_input("Passphrase", &state.passphrase)
+            .secure(true)
+            .on_input(Message::PassphraseChanged),
+        widget::text_input("Repeat passphrase", &state.confirmation)
+            .secure(true)
+            .on_input(Message::ConfirmationChanged)
+    ]
+    .push_maybe(strength)
+    .push_maybe(hint)
+    .push(widget::row![widget::horizontal_space(), generate])
+    .spacing(10)
+    .into()
+}
+
+fn view_result<'a>(state: &'a State, fingerprint: &'a str) -> Element<'a, Message> {
+    let info = widget::text(
+        "Your key is ready. Send the public key to the admin of the store so \
+         that they can add you as a recipient. Keep the revocation certificate \
+         somewhere safe outside this computer: it lets you revoke the key if \
+         it is lost or stolen.",
+    )
+    .size(14);
+    let mut saved = widget::column![].spacing(4);
+    for file in &state.saved {
+        saved = saved.push(widget::text(format!("Saved {}", file)).size(12));
+    }
+
+    widget::column![
+        info,
+        widget::text(fingerprint)
+            .size(14)
+            .font(iced::Font::MONOSPACE),
+        widget::row![
+            widget::button(widget::text("Save public key...").size(14))
+                .on_press(Message::SavePublicKey),
+            widget::button(widget::text("Save revocation certificate...").size(14))
+                .on_press(Message::SaveRevocation)
+        ]
+        .spacing(10),
+        saved
+    ]
+    .spacing(10)
+    .into()
+}
+
+/// Renders the key generation panel: the form, then the files to save.
+///
+/// # Arguments
+/// * `state` - The current key generation panel state
+/// * `on_message` - Function to wrap key generation messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let close_label = if state.fingerprint.is_some() {
+        "Done"
+    } else {
+        "Cancel"
+    };
+    let close_button = widget::button(widget::text(close_label).size(14))
+        .on_press_maybe((!state.generating).then_some(Message::Close));
+    let header = widget::row![
+        widget::text("Generate a GPG key").size(24),
+        widget::horizontal_space(),
+        close_button
+    ]
+    .align_y(iced::Alignment::Center);
+
+    let body = match &state.fingerprint {
+        Some(fingerprint) => view_result(state, fingerprint),
+        None => view_form(state),
+    };
+    let error = state.error.as_ref().map(|e| {
+        widget::text(e)
+            .size(14)
+            .color(iced::Color::from_rgb(0.7, 0.1, 0.1))
+    });
+
+    let content: Element<'a, Message> = widget::column![header, body]
+        .push_maybe(error)
+        .spacing(20)
+        .padding(20)
+        .width(500)
+        .into();
+    widget::container(content.map(on_message))
+        .center_x(Length::Fill)
+        .center_y(Length::Fill)
+        .into()
+}
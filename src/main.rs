@@ -1,65 +1,142 @@
+mod access;
+mod age;
 mod assets;
+mod audit;
+mod backup;
+mod bitwarden;
+mod breach;
+mod confirm_popup;
+mod conflict;
 mod context_menu;
 mod crypto;
+mod editor;
 mod error_popup;
+mod export;
 mod file_explorer;
+mod fsck;
+mod git;
+mod history;
+mod import;
+mod init;
+mod job;
+mod keepass;
+mod keygen;
+mod merge;
+mod onepassword;
+mod packets;
+mod plaintext;
+mod recipients;
+mod search;
+mod secret;
 mod setup;
+mod stores;
+mod strength;
+mod summary_popup;
+mod tree;
+mod workspace;
 
-use iced::Element;
+use iced::{window, Element, Subscription, Task};
 
 pub enum Screen {
-    Setup(setup::State),
-    FileExplorer(file_explorer::State),
+    Setup(Box<setup::State>),
+    /// The explorers of the configured stores.
+    Stores,
 }
 
 pub struct AppState {
     screen: Screen,
-    github_url: Option<String>,
+    stores: workspace::State,
 }
 
 #[derive(Debug, Clone)]
 pub enum AppMessage {
     Setup(setup::Message),
-    FileExplorer(file_explorer::FileAction),
+    Workspace(workspace::Message),
+    CloseRequested(window::Id),
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self {
-            screen: Screen::Setup(setup::State::default()),
-            github_url: None,
-        }
+impl AppState {
+    /// Open the configured stores, or show the setup if there are none.
+    fn new() -> (Self, Task<AppMessage>) {
+        let (stores, task) = workspace::State::new(stores::load());
+        let screen = if stores.is_empty() {
+            Screen::Setup(Box::default())
+        } else {
+            Screen::Stores
+        };
+        (Self { screen, stores }, task.map(AppMessage::Workspace))
     }
 }
 
-fn update(state: &mut AppState, message: AppMessage) {
+fn update(state: &mut AppState, message: AppMessage) -> Task<AppMessage> {
     match message {
+        AppMessage::Setup(setup::Message::Finished(store)) => {
+            state.screen = Screen::Stores;
+            return state
+                .stores
+                .add(stores::StoreConfig::new(store.root, store.remote))
+                .map(AppMessage::Workspace);
+        }
+        AppMessage::Setup(setup::Message::Cancel) => {
+            if !state.stores.is_empty() {
+                state.screen = Screen::Stores;
+            }
+        }
         AppMessage::Setup(msg) => {
             if let Screen::Setup(ref mut setup_state) = state.screen {
-                if let Some(url) = setup::update(setup_state, msg) {
-                    state.github_url = Some(url);
-                    state.screen = Screen::FileExplorer(file_explorer::State::default());
-                }
+                return setup::update(setup_state, msg).map(AppMessage::Setup);
+            }
+        }
+        AppMessage::Workspace(workspace::Message::AddStore) => {
+            state.screen = Screen::Setup(Box::new(setup::State::adding()));
+        }
+        AppMessage::Workspace(msg) => {
+            let task = state.stores.update(msg).map(AppMessage::Workspace);
+            if state.stores.is_empty() {
+                state.screen = Screen::Setup(Box::default());
             }
+            return task;
         }
-        AppMessage::FileExplorer(action) => {
-            if let Screen::FileExplorer(ref mut explorer_state) = state.screen {
-                file_explorer::update(explorer_state, action);
+        AppMessage::CloseRequested(id) => {
+            // The explorers may hold unsaved changes and decide themselves when to close
+            if state.stores.has_unsaved_changes() {
+                state.screen = Screen::Stores;
             }
+            return state.stores.close_requested(id).map(AppMessage::Workspace);
         }
     }
+    Task::none()
 }
 
 fn view(state: &AppState) -> Element<'_, AppMessage> {
     match &state.screen {
         Screen::Setup(setup_state) => setup::view(setup_state).map(AppMessage::Setup),
-        Screen::FileExplorer(explorer_state) => {
-            file_explorer::view(explorer_state).map(AppMessage::FileExplorer)
-        }
+        Screen::Stores => workspace::view(&state.stores).map(AppMessage::Workspace),
     }
 }
 
+fn subscription(state: &AppState) -> Subscription<AppMessage> {
+    let screen_subscription = match &state.screen {
+        Screen::Setup(_) => Subscription::none(),
+        Screen::Stores => workspace::subscription(&state.stores).map(AppMessage::Workspace),
+    };
+
+    Subscription::batch([
+        window::close_requests().map(AppMessage::CloseRequested),
+        screen_subscription,
+    ])
+}
+
 fn main() -> iced::Result {
     env_logger::init();
-    iced::run("defira", update, view)
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "merge-driver") {
+        std::process::exit(merge::run_driver(&args[2..]));
+    }
+
+    iced::application("defira", update, view)
+        .subscription(subscription)
+        .exit_on_close_request(false)
+        .run_with(AppState::new)
 }
@@ -0,0 +1,345 @@
+use crate::crypto::{self, CryptoBackend};
+use crate::recipients;
+use log::{debug, error};
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Marks the start of our side of a conflict.
+const OURS_MARKER: &str = "<<<<<<< ours\n";
+/// Separates our side from theirs in a conflict.
+const SEPARATOR_MARKER: &str = "=======\n";
+/// Marks the end of their side of a conflict.
+const THEIRS_MARKER: &str = ">>>>>>> theirs\n";
+
+/// Outcome of a three-way merge.
+pub struct MergeResult {
+    /// The merged text, with conflict markers around conflicting hunks.
+    pub text: Zeroizing<String>,
+    /// Number of conflicting hunks.
+    pub conflicts: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+/// A change of one side against the base: lines `start..end` of the base
+/// replaced by `lines`.
+struct Change<'a> {
+    side: Side,
+    start: usize,
+    end: usize,
+    lines: &'a [&'a str],
+}
+
+fn changes<'a>(side: Side, base: &[&'a str], other: &'a [&'a str]) -> Vec<Change<'a>> {
+    capture_diff_slices(Algorithm::Myers, base, other)
+        .iter()
+        .map(|op| op.as_tag_tuple())
+        .filter(|(tag, _, _)| *tag != DiffTag::Equal)
+        .map(|(_, old, new)| Change {
+            side,
+            start: old.start,
+            end: old.end,
+            lines: &other[new],
+        })
+        .collect()
+}
+
+/// Apply the changes of one side to `base[start..end]`.
+fn apply_side<'a>(
+    side: Side,
+    group: &[Change<'a>],
+    base: &[&'a str],
+    start: usize,
+    end: usize,
+) -> Vec<&'a str> {
+    let mut lines = Vec::new();
+    let mut position = start;
+    for change in group.iter().filter(|c| c.side == side) {
+        lines.extend_from_slice(&base[position..change.start]);
+        lines.extend_from_slice(change.lines);
+        position = change.end;
+    }
+    lines.extend_from_slice(&base[position..end]);
+    lines
+}
+
+/// Merge the changes from `base` to `ours` and from `base` to `theirs`, line by line.
+///
+/// Changes of both sides touching the same or adjacent lines of the base are
+/// conflicts unless both sides made the same change.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    if ours == theirs || theirs == base {
+        return MergeResult {
+            text: Zeroizing::new(ours.to_string()),
+            conflicts: 0,
+        };
+    }
+    if ours == base {
+        return MergeResult {
+            text: Zeroizing::new(theirs.to_string()),
+            conflicts: 0,
+        };
+    }
+
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let mut all = changes(Side::Ours, &base_lines, &our_lines);
+    all.extend(changes(Side::Theirs, &base_lines, &their_lines));
+    all.sort_by_key(|c| (c.start, c.end));
+
+    let mut text = Zeroizing::new(String::new());
+    let mut conflicts = 0;
+    let mut position = 0;
+    let mut i = 0;
+    while i < all.len() {
+        // Group changes touching overlapping or adjacent base lines
+        let start = all[i].start;
+        let mut end = all[i].end;
+        let mut j = i + 1;
+        while j < all.len() && all[j].start <= end {
+            end = end.max(all[j].end);
+            j += 1;
+        }
+        let group = &all[i..j];
+
+        text.extend(base_lines[position..start].iter().copied());
+        let ours = apply_side(Side::Ours, group, &base_lines, start, end);
+        let theirs = apply_side(Side::Theirs, group, &base_lines, start, end);
+        let both_sides = group.iter().any(|c| c.side == Side::Ours)
+            && group.iter().any(|c| c.side == Side::Theirs);
+
+        if !both_sides || ours == theirs {
+            let resolved = if group[0].side == Side::Ours {
+                ours
+            } else {
+                theirs
+            };
+            text.extend(resolved);
+        } else {
+            conflicts += 1;
+            text.push_str(OURS_MARKER);
+            push_lines(&mut text, &ours);
+            text.push_str(SEPARATOR_MARKER);
+            push_lines(&mut text, &theirs);
+            text.push_str(THEIRS_MARKER);
+        }
+
+        position = end;
+        i = j;
+    }
+    text.extend(base_lines[position..].iter().copied());
+
+    MergeResult { text, conflicts }
+}
+
+/// Append lines inside a conflict, making sure the marker after them starts on its own line.
+fn push_lines(text: &mut String, lines: &[&str]) {
+    text.extend(lines.iter().copied());
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+fn decrypt_file(backend: &dyn CryptoBackend, path: &Path) -> Result<Zeroizing<String>, String> {
+    let encrypted = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    // git passes an empty ancestor when both sides added the file
+    if encrypted.is_empty() {
+        return Ok(Zeroizing::new(String::new()));
+    }
+    backend
+        .decrypt_text(&encrypted)
+        .map(Zeroizing::new)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Merge the three versions and write a clean result to `current`.
+///
+/// Returns the number of conflicting hunks; `current` is only written if there are none.
+fn merge_files(ancestor: &str, current: &str, other: &str, path: &str) -> Result<usize, String> {
+    // The versions are in temporary files, the secret's name tells the backend
+    let backend = crypto::backend(Path::new(path));
+    let base = decrypt_file(backend, Path::new(ancestor))?;
+    let ours = decrypt_file(backend, Path::new(current))?;
+    let theirs = decrypt_file(backend, Path::new(other))?;
+
+    let merged = merge3(&base, &ours, &theirs);
+    if merged.conflicts > 0 {
+        return Ok(merged.conflicts);
+    }
+
+    // git runs merge drivers from the top of the work tree
+    let root = std::env::current_dir().map_err(|e| e.to_string())?;
+    let recipients = recipients::resolve(&root.join(path), &root).map_err(|e| e.to_string())?;
+    let ciphertext = backend
+        .encrypt(merged.text.as_bytes(), &recipients)
+        .map_err(|e| e.to_string())?;
+    fs::write(current, ciphertext).map_err(|e| format!("{}: {}", current, e))?;
+    Ok(0)
+}
+
+/// Entry point of `defira merge-driver %O %A %B %P`.
+///
+/// Decrypts the ancestor (`%O`), current (`%A`) and other (`%B`) versions of
+/// a secret and merges them. A clean merge is encrypted to the recipients of
+/// `%P` and written to `%A`. On conflicts `%A` is left untouched so that git
+/// reports the file as conflicting, to be resolved in the app.
+///
+/// Returns the exit code for git: 0 on a clean merge, 1 otherwise.
+///
+/// Enable it in the store with:
+///
+/// ```text
+/// git config merge.defira.driver "defira merge-driver %O %A %B %P"
+/// echo "*.gpg merge=defira" >> .gitattributes
+/// ```
+pub fn run_driver(args: &[String]) -> i32 {
+    let [ancestor, current, other, path] = args else {
+        eprintln!("usage: defira merge-driver <ancestor> <current> <other> <path>");
+        return 2;
+    };
+    debug!("Merging '{}'", path);
+
+    match merge_files(ancestor, current, other, path) {
+        Ok(0) => {
+            debug!("Merged '{}' cleanly", path);
+            0
+        }
+        Ok(conflicts) => {
+            eprintln!("defira: {} conflicting hunks in '{}'", conflicts, path);
+            1
+        }
+        Err(e) => {
+            error!("Failed to merge '{}': {}", path, e);
+            eprintln!("defira: failed to merge '{}': {}", path, e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "password\nuser: alice\nurl: example.com\nnotes\n";
+
+    fn clean(base: &str, ours: &str, theirs: &str) -> String {
+        let merged = merge3(base, ours, theirs);
+        assert_eq!(
+            merged.conflicts, 0,
+            "unexpected conflict:\n{}",
+            *merged.text
+        );
+        merged.text.to_string()
+    }
+
+    #[test]
+    fn takes_changes_of_one_side() {
+        let changed = "secret\nuser: alice\nurl: example.com\nnotes\n";
+        assert_eq!(clean(BASE, changed, BASE), changed);
+        assert_eq!(clean(BASE, BASE, changed), changed);
+    }
+
+    #[test]
+    fn merges_changes_of_both_sides() {
+        let ours = "secret\nuser: alice\nurl: example.com\nnotes\n";
+        let theirs = "password\nuser: alice\nurl: example.com\nmore notes\n";
+        assert_eq!(
+            clean(BASE, ours, theirs),
+            "secret\nuser: alice\nurl: example.com\nmore notes\n"
+        );
+    }
+
+    #[test]
+    fn identical_changes_are_not_conflicts() {
+        let both = "secret\nuser: bob\nurl: example.com\nnotes\n";
+        assert_eq!(clean(BASE, both, both), both);
+
+        // The same change among different ones
+        let ours = "secret\nuser: alice\nurl: example.com\nnotes\n";
+        let theirs = "secret\nuser: alice\nurl: example.com\nmore notes\n";
+        assert_eq!(
+            clean(BASE, ours, theirs),
+            "secret\nuser: alice\nurl: example.com\nmore notes\n"
+        );
+    }
+
+    #[test]
+    fn adjacent_changes_are_conflicts() {
+        // Like git, changes to neighbouring lines cannot be told apart from
+        // changes to the same hunk
+        let ours = "password\nuser: bob\nurl: example.com\nnotes\n";
+        let theirs = "password\nuser: alice\nurl: example.org\nnotes\n";
+        let merged = merge3(BASE, ours, theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            *merged.text,
+            "password\n<<<<<<< ours\nuser: bob\nurl: example.com\n=======\n\
+             user: alice\nurl: example.org\n>>>>>>> theirs\nnotes\n"
+        );
+    }
+
+    #[test]
+    fn changes_apart_by_one_line_merge() {
+        let ours = "password\nuser: bob\nurl: example.com\nnotes\n";
+        let theirs = "password\nuser: alice\nurl: example.com\nmore notes\n";
+        assert_eq!(
+            clean(BASE, ours, theirs),
+            "password\nuser: bob\nurl: example.com\nmore notes\n"
+        );
+    }
+
+    #[test]
+    fn different_changes_to_a_line_conflict() {
+        let ours = "ours\nuser: alice\nurl: example.com\nnotes\n";
+        let theirs = "theirs\nuser: alice\nurl: example.com\nnotes\n";
+        let merged = merge3(BASE, ours, theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            *merged.text,
+            "<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n\
+             user: alice\nurl: example.com\nnotes\n"
+        );
+    }
+
+    #[test]
+    fn insertions_at_start_and_end() {
+        let ours = format!("first\n{}", BASE);
+        let theirs = format!("{}last\n", BASE);
+        assert_eq!(
+            clean(BASE, &ours, &theirs),
+            format!("first\n{}last\n", BASE)
+        );
+
+        // Different lines inserted at the same place conflict
+        let merged = merge3(BASE, &format!("{}ours\n", BASE), &theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert!(merged
+            .text
+            .ends_with("notes\n<<<<<<< ours\nours\n=======\nlast\n>>>>>>> theirs\n"));
+    }
+
+    #[test]
+    fn conflict_markers_start_on_their_own_line() {
+        // The last lines have no newline
+        let merged = merge3("a\nb", "a\nours", "a\ntheirs");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            *merged.text,
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n"
+        );
+    }
+
+    #[test]
+    fn both_sides_adding_the_file() {
+        assert_eq!(clean("", "same\n", "same\n"), "same\n");
+        assert_eq!(merge3("", "ours\n", "theirs\n").conflicts, 1);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::import::{Import, ImportError, ImportedEntry};
+use crate::secret::{self, Secret};
+use log::{debug, warn};
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Name of the JSON document inside a 1PUX archive.
+const EXPORT_DATA: &str = "export.data";
+
+fn string<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Text of a section field value such as `{"concealed": "..."}` or `{"email": {"email_address": "..."}}`.
+fn field_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Object(object) => object
+            .values()
+            .map(field_text)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
+
+fn read_1pux_item(item: &Value, folder: &[String], import: &mut Import) {
+    let overview = item.get("overview").unwrap_or(&Value::Null);
+    let details = item.get("details").unwrap_or(&Value::Null);
+    let name = string(overview, "title").to_string();
+
+    if string(item, "state") == "archived" {
+        import.skipped.push(format!("{}: archived", name));
+        return;
+    }
+
+    let mut secret = Secret::new(string(details, "password"));
+    let mut username = String::new();
+    let mut custom = Vec::new();
+
+    for field in array(details, "loginFields") {
+        let value = string(field, "value").to_string();
+        match string(field, "designation") {
+            "username" => username = value,
+            "password" => secret.password = value,
+            _ => custom.push((string(field, "name").to_string(), value)),
+        }
+    }
+
+    secret.push_field("username", username);
+    secret.push_field("url", string(overview, "url"));
+    for url in array(overview, "urls").iter().skip(1) {
+        secret.push_field("url", string(url, "url"));
+    }
+    for (key, value) in custom {
+        secret.push_field(key, value);
+    }
+
+    for section in array(details, "sections") {
+        for field in array(section, "fields") {
+            let title = match string(field, "title") {
+                "" => string(field, "id"),
+                title => title,
+            };
+            let Some((kind, value)) = field
+                .get("value")
+                .and_then(Value::as_object)
+                .and_then(|v| v.iter().next())
+            else {
+                continue;
+            };
+            match kind.as_str() {
+                "totp" if secret.otp.is_none() => {
+                    let totp = field_text(value);
+                    if !totp.is_empty() {
+                        secret.otp = Some(secret::otp_uri(&name, &totp));
+                    }
+                }
+                "passkey" => import.skipped.push(format!("{}: passkey", name)),
+                "file" => import.skipped.push(format!("{}: attachment", name)),
+                _ => secret.push_field(title, field_text(value)),
+            }
+        }
+    }
+
+    if details.get("passkey").is_some() {
+        import.skipped.push(format!("{}: passkey", name));
+    }
+    if details.get("documentAttributes").is_some() {
+        warn!("Skipping document of '{}'", name);
+        import.skipped.push(format!("{}: attachment", name));
+    }
+    secret.push_field("notes", string(details, "notesPlain"));
+
+    import.entries.push(ImportedEntry {
+        folder: folder.to_vec(),
+        name,
+        secret,
+    });
+}
+
+/// Read a 1Password 1PUX export. Every vault becomes a directory, below a
+/// directory per account if the export holds several accounts.
+pub fn read_1pux(path: &Path) -> Result<Import, ImportError> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+    let mut data = Zeroizing::new(String::new());
+    archive.by_name(EXPORT_DATA)?.read_to_string(&mut data)?;
+    let export: Value = serde_json::from_str(&data)?;
+
+    let accounts = array(&export, "accounts");
+    if accounts.is_empty() {
+        return Err(ImportError::Invalid(
+            "no accounts in 1PUX export".to_string(),
+        ));
+    }
+
+    let mut import = Import::default();
+    for account in accounts {
+        let mut folder = Vec::new();
+        if accounts.len() > 1 {
+            let attrs = account.get("attrs").unwrap_or(&Value::Null);
+            folder.push(string(attrs, "accountName").to_string());
+        }
+        for vault in array(account, "vaults") {
+            let attrs = vault.get("attrs").unwrap_or(&Value::Null);
+            folder.push(string(attrs, "name").to_string());
+            let items = array(vault, "items");
+            debug!(
+                "1Password vault '{}' with {} items",
+                folder.join("/"),
+                items.len()
+            );
+            for item in items {
+                read_1pux_item(item, &folder, &mut import);
+            }
+            folder.pop();
+        }
+    }
+    Ok(import)
+}
+
+/// Split CSV text into records, following RFC 4180 quoting.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+/// Read a 1Password CSV export. The export has no vault information, so all
+/// items are placed directly in the target directory.
+pub fn read_csv(path: &Path) -> Result<Import, ImportError> {
+    let data = Zeroizing::new(fs::read_to_string(path)?);
+    let mut records = parse_csv(&data).into_iter();
+    let Some(header) = records.next() else {
+        return Err(ImportError::Invalid("empty CSV file".to_string()));
+    };
+    let header: Vec<String> = header.iter().map(|h| h.trim().to_lowercase()).collect();
+    if !header.iter().any(|h| h == "title" || h == "name") {
+        return Err(ImportError::Invalid(
+            "CSV file has no title column".to_string(),
+        ));
+    }
+
+    let mut import = Import::default();
+    for mut record in records {
+        if record.iter().all(|value| value.trim().is_empty()) {
+            continue;
+        }
+        let mut name = String::new();
+        let mut secret = Secret::new("");
+        let mut username = String::new();
+        let mut url = String::new();
+        let mut notes = String::new();
+        let mut custom = Vec::new();
+        let mut archived = false;
+
+        for (column, value) in header.iter().zip(record.iter_mut()) {
+            let value = std::mem::take(value);
+            match column.as_str() {
+                "title" | "name" => name = value,
+                "password" | "login password" => secret.password = value,
+                "username" | "login username" => username = value,
+                "url" | "website" | "login url" => url = value,
+                "otpauth" | "one-time password" | "otp" if !value.is_empty() => {
+                    secret.otp = Some(value)
+                }
+                "notes" | "notesplain" => notes = value,
+                "archived" => archived = value.eq_ignore_ascii_case("true"),
+                "favorite" | "type" => {}
+                _ => custom.push((column.clone(), value)),
+            }
+        }
+
+        if archived {
+            import.skipped.push(format!("{}: archived", name));
+            continue;
+        }
+        if let Some(otp) = secret.otp.take() {
+            secret.otp = Some(secret::otp_uri(&name, &otp));
+        }
+        secret.push_field("username", username);
+        secret.push_field("url", url);
+        for (key, value) in custom {
+            secret.push_field(key, value);
+        }
+        secret.push_field("notes", notes);
+
+        import.entries.push(ImportedEntry {
+            folder: Vec::new(),
+            name,
+            secret,
+        });
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoted_commas() {
+        assert_eq!(
+            parse_csv("title,url\n\"Mail, work\",https://example.com\n"),
+            [["title", "url"], ["Mail, work", "https://example.com"]]
+        );
+    }
+
+    #[test]
+    fn parses_doubled_quotes() {
+        assert_eq!(
+            parse_csv("title,password\nMail,\"say \"\"hi\"\"\"\n"),
+            [["title", "password"], ["Mail", "say \"hi\""]]
+        );
+        assert_eq!(parse_csv("\"\"\"\"\n"), [["\""]]);
+    }
+
+    #[test]
+    fn parses_crlf_line_endings() {
+        assert_eq!(
+            parse_csv("\u{feff}title,username\r\nMail,alice\r\nBank,bob"),
+            [["title", "username"], ["Mail", "alice"], ["Bank", "bob"]]
+        );
+    }
+
+    #[test]
+    fn parses_multi_line_notes() {
+        assert_eq!(
+            parse_csv("title,notes\nMail,\"first line\nsecond, line\"\nBank,\n"),
+            [
+                ["title", "notes"],
+                ["Mail", "first line\nsecond, line"],
+                ["Bank", ""]
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_empty_fields() {
+        assert_eq!(parse_csv(",a,\n"), [["", "a", ""]]);
+        assert!(parse_csv("").is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+//! Reading the recipients of an OpenPGP message without decrypting it.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Public-key encrypted session key packet, one per recipient.
+const PKESK_TAG: u8 = 1;
+/// Symmetric-key encrypted session key packet.
+const SKESK_TAG: u8 = 3;
+const MARKER_TAG: u8 = 10;
+/// Bytes read from the start of a file. The session key packets come first
+/// and take well below 1 KiB per recipient.
+const HEADER_LIMIT: u64 = 16 * 1024;
+
+/// Key ID used in place of the recipient for hidden recipients (`gpg --throw-keyids`).
+pub const WILDCARD_KEY_ID: u64 = 0;
+
+/// Split the header of the next packet off `data`, returning its tag, body
+/// length and the rest. The length is `None` for packets of indeterminate or
+/// partial length, which session key packets never have. Returns `None` for
+/// invalid or truncated headers.
+fn packet_header(data: &[u8]) -> Option<(u8, Option<usize>, &[u8])> {
+    let (&header, rest) = data.split_first()?;
+    if header & 0x80 == 0 {
+        return None;
+    }
+
+    let (tag, length, rest) = if header & 0x40 != 0 {
+        // New format
+        let tag = header & 0x3f;
+        let (&first, rest) = rest.split_first()?;
+        match first {
+            0..=191 => (tag, Some(first as usize), rest),
+            192..=223 => {
+                let (&second, rest) = rest.split_first()?;
+                let length = ((first as usize - 192) << 8) + second as usize + 192;
+                (tag, Some(length), rest)
+            }
+            255 => {
+                let bytes = rest.get(..4)?;
+                let length = u32::from_be_bytes(bytes.try_into().ok()?) as usize;
+                (tag, Some(length), &rest[4..])
+            }
+            // Partial body length
+            _ => (tag, None, rest),
+        }
+    } else {
+        // Old format
+        let tag = (header >> 2) & 0x0f;
+        let size = match header & 0x03 {
+            0 => 1,
+            1 => 2,
+            2 => 4,
+            // Indeterminate length
+            _ => return Some((tag, None, rest)),
+        };
+        let bytes = rest.get(..size)?;
+        let length = bytes.iter().fold(0usize, |n, b| (n << 8) | *b as usize);
+        (tag, Some(length), &rest[size..])
+    };
+    Some((tag, length, rest))
+}
+
+/// The key ID in the body of a version 3 or 6 session key packet.
+fn key_id(body: &[u8]) -> Option<u64> {
+    match *body.first()? {
+        3 => Some(u64::from_be_bytes(body.get(1..9)?.try_into().ok()?)),
+        6 => {
+            let length = *body.get(1)? as usize;
+            if length == 0 {
+                return Some(WILDCARD_KEY_ID);
+            }
+            let fingerprint = body.get(3..2 + length)?;
+            // Version 6 key IDs are the start of the fingerprint, version 4
+            // key IDs the end
+            let id = match body[2] {
+                6 => fingerprint.get(..8)?,
+                _ => fingerprint.get(fingerprint.len().checked_sub(8)?..)?,
+            };
+            Some(u64::from_be_bytes(id.try_into().ok()?))
+        }
+        _ => None,
+    }
+}
+
+/// Key IDs of the recipients of the binary OpenPGP message in `data`.
+///
+/// Returns `None` if the recipients cannot be told, e.g. because the
+/// message is ASCII armored or ends before its encrypted data.
+pub fn recipients(mut data: &[u8]) -> Option<Vec<u64>> {
+    let mut ids = Vec::new();
+    loop {
+        let (tag, length, rest) = packet_header(data)?;
+        // The encrypted data follows the session key packets, and may be
+        // longer than the header read or use partial body lengths
+        if !matches!(tag, PKESK_TAG | SKESK_TAG | MARKER_TAG) {
+            return Some(ids);
+        }
+        let body = rest.get(..length?)?;
+        if tag == PKESK_TAG {
+            ids.push(key_id(body)?);
+        }
+        data = &rest[body.len()..];
+    }
+}
+
+/// Key IDs of the recipients of the secret at `path`, see [`recipients`].
+pub fn file_recipients(path: &Path) -> Option<Vec<u64>> {
+    let mut header = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(HEADER_LIMIT)
+        .read_to_end(&mut header)
+        .ok()?;
+    recipients(&header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new format packet with a one byte length.
+    fn packet(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0xc0 | tag, body.len() as u8];
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    fn v3_pkesk(id: u64) -> Vec<u8> {
+        let mut body = vec![3];
+        body.extend_from_slice(&id.to_be_bytes());
+        // Algorithm and a short encrypted session key
+        body.extend_from_slice(&[1, 0, 8, 0xaa]);
+        packet(PKESK_TAG, &body)
+    }
+
+    /// Encrypted data with a partial body length, as gpg writes for larger
+    /// plaintexts.
+    fn partial_data() -> Vec<u8> {
+        let mut packet = vec![0xc0 | 18, 0xec];
+        packet.extend_from_slice(&[0; 64]);
+        packet
+    }
+
+    #[test]
+    fn key_id_of_v3_packet() {
+        let mut body = vec![3];
+        body.extend_from_slice(&0x0123_4567_89ab_cdef_u64.to_be_bytes());
+        assert_eq!(key_id(&body), Some(0x0123_4567_89ab_cdef));
+        assert_eq!(key_id(&body[..5]), None);
+    }
+
+    #[test]
+    fn key_id_of_v6_packet() {
+        // Version 4 key: the key ID is the end of the 20 byte fingerprint
+        let mut body = vec![6, 21, 4];
+        body.extend(1..=20);
+        assert_eq!(key_id(&body), Some(0x0d0e_0f10_1112_1314));
+
+        // Version 6 key: the key ID is the start of the 32 byte fingerprint
+        let mut body = vec![6, 33, 6];
+        body.extend(1..=32);
+        assert_eq!(key_id(&body), Some(0x0102_0304_0506_0708));
+
+        assert_eq!(key_id(&body[..10]), None);
+    }
+
+    #[test]
+    fn key_id_of_wildcard_packet() {
+        assert_eq!(key_id(&[3, 0, 0, 0, 0, 0, 0, 0, 0]), Some(WILDCARD_KEY_ID));
+        assert_eq!(key_id(&[6, 0]), Some(WILDCARD_KEY_ID));
+    }
+
+    #[test]
+    fn key_id_of_unknown_version() {
+        assert_eq!(key_id(&[5, 0, 0, 0, 0, 0, 0, 0, 0]), None);
+        assert_eq!(key_id(&[]), None);
+    }
+
+    #[test]
+    fn recipients_before_encrypted_data() {
+        let mut data = packet(MARKER_TAG, b"PGP");
+        data.extend(v3_pkesk(1));
+        data.extend(v3_pkesk(WILDCARD_KEY_ID));
+        data.extend(packet(SKESK_TAG, &[4, 9, 3]));
+        data.extend(packet(18, &[1, 2, 3]));
+        assert_eq!(recipients(&data), Some(vec![1, WILDCARD_KEY_ID]));
+    }
+
+    #[test]
+    fn recipients_with_old_format_packets() {
+        let pkesk = v3_pkesk(7);
+        let mut data = vec![0x80 | (PKESK_TAG << 2), pkesk.len() as u8 - 2];
+        data.extend_from_slice(&pkesk[2..]);
+        // Encrypted data of indeterminate length
+        data.extend_from_slice(&[0x80 | (9 << 2) | 3, 0, 0]);
+        assert_eq!(recipients(&data), Some(vec![7]));
+    }
+
+    #[test]
+    fn recipients_before_partial_length_data() {
+        let mut data = v3_pkesk(42);
+        data.extend(partial_data());
+        assert_eq!(recipients(&data), Some(vec![42]));
+    }
+
+    #[test]
+    fn recipients_before_data_longer_than_header() {
+        let mut data = v3_pkesk(42);
+        // A 1 MiB packet, cut off where the header stops being read
+        data.extend_from_slice(&[0xc0 | 18, 0xff, 0, 0x10, 0, 0, 1, 2, 3]);
+        assert_eq!(recipients(&data), Some(vec![42]));
+    }
+
+    #[test]
+    fn no_recipients_for_truncated_or_armored_messages() {
+        let data = v3_pkesk(42);
+        assert_eq!(recipients(&data), None);
+        assert_eq!(recipients(&data[..data.len() - 1]), None);
+        assert_eq!(recipients(b"-----BEGIN PGP MESSAGE-----"), None);
+        // A session key packet of partial length is invalid
+        let mut data = vec![0xc0 | PKESK_TAG, 0xe0];
+        data.extend(v3_pkesk(42));
+        assert_eq!(recipients(&data), None);
+    }
+}
@@ -0,0 +1,246 @@
+//! Unencrypted files in the store: finding them and encrypting them in place.
+
+use crate::age;
+use crate::crypto::{self, CryptoError};
+use crate::recipients::{self, RecipientsError};
+use log::debug;
+use std::fmt;
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// File in the store root listing plaintext files that are expected, one
+/// pattern per line. `*` matches any characters, and patterns without a `/`
+/// match the file name in any directory.
+pub const ALLOWLIST_FILE: &str = ".defira-allowlist";
+
+/// Plaintext files every store may contain.
+const ALWAYS_ALLOWED: &[&str] = &[
+    recipients::GPG_ID_FILE,
+    recipients::GPG_ID_SIGNATURE_FILE,
+    age::RECIPIENTS_FILE,
+    ALLOWLIST_FILE,
+    ".gitattributes",
+    ".gitignore",
+];
+
+#[derive(Debug)]
+pub enum PlaintextError {
+    IoError(std::io::Error),
+    Recipients(RecipientsError),
+    Crypto(CryptoError),
+    Exists(PathBuf),
+    /// A symbolic link or something else that is not a regular file.
+    NotRegularFile(PathBuf),
+}
+
+impl fmt::Display for PlaintextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaintextError::IoError(e) => write!(f, "I/O error: {}", e),
+            PlaintextError::Recipients(e) => write!(f, "{}", e),
+            PlaintextError::Crypto(e) => write!(f, "{}", e),
+            PlaintextError::Exists(path) => write!(f, "'{}' already exists", path.display()),
+            PlaintextError::NotRegularFile(path) => {
+                write!(f, "'{}' is not a regular file", path.display())
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for PlaintextError {
+    fn from(e: std::io::Error) -> Self {
+        PlaintextError::IoError(e)
+    }
+}
+
+impl From<RecipientsError> for PlaintextError {
+    fn from(e: RecipientsError) -> Self {
+        PlaintextError::Recipients(e)
+    }
+}
+
+impl From<CryptoError> for PlaintextError {
+    fn from(e: CryptoError) -> Self {
+        PlaintextError::Crypto(e)
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any characters.
+fn matches(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .any(|i| matches(rest, &text[i..]))
+        }
+    }
+}
+
+/// Whether the file at `relative` (with forward slashes) is allowed to be plaintext.
+fn is_allowed(allowlist: &[String], relative: &str) -> bool {
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    ALWAYS_ALLOWED.contains(&name)
+        || allowlist.iter().any(|pattern| {
+            if pattern.contains('/') {
+                matches(pattern.trim_start_matches('/'), relative)
+            } else {
+                matches(pattern, name)
+            }
+        })
+}
+
+fn read_allowlist(root: &Path) -> Vec<String> {
+    fs::read_to_string(root.join(ALLOWLIST_FILE))
+        .map(|text| {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn collect(root: &Path, dir: &Path, allowlist: &[String], files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        // Symbolic links are not followed, they could point outside of the
+        // store or back to one of its directories
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                collect(root, &path, allowlist, files);
+            }
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+        if crypto::backend_of(&path).is_some() {
+            continue;
+        }
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if !is_allowed(allowlist, &relative) {
+            files.push(path);
+        }
+    }
+}
+
+/// Unencrypted files in the store that are not on the allowlist, sorted by path.
+pub fn scan(root: &Path) -> Vec<PathBuf> {
+    let allowlist = read_allowlist(root);
+    let mut files = Vec::new();
+    collect(root, root, &allowlist, &mut files);
+    files.sort();
+    debug!(
+        "Found {} unencrypted files in '{}'",
+        files.len(),
+        root.display()
+    );
+    files
+}
+
+/// Overwrite a file with zeros before removing it, so that its content does
+/// not linger in the freed blocks. This is best effort: journaling and
+/// copy-on-write file systems may still keep old copies.
+fn shred(path: &Path) -> Result<(), PlaintextError> {
+    check_regular_file(path)?;
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+    let zeros = [0u8; 4096];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        remaining -= n as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    Ok(fs::remove_file(path)?)
+}
+
+/// Refuse symbolic links and anything else that is not a regular file, so
+/// that files outside of the store are never read or overwritten.
+fn check_regular_file(path: &Path) -> Result<(), PlaintextError> {
+    if fs::symlink_metadata(path)?.file_type().is_file() {
+        Ok(())
+    } else {
+        Err(PlaintextError::NotRegularFile(path.to_path_buf()))
+    }
+}
+
+/// Encrypt the plaintext file at `path` to the recipients of its directory
+/// as `<name>.gpg` (or `.age` in an age store), then shred the original.
+///
+/// Returns the path of the new secret.
+pub fn encrypt_in_place(root: &Path, path: &Path) -> Result<PathBuf, PlaintextError> {
+    check_regular_file(path)?;
+    let backend = crypto::store_backend(root);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(backend.extension());
+    let target = path.with_file_name(name);
+    // A dangling link also counts, writing would follow it
+    if fs::symlink_metadata(&target).is_ok() {
+        return Err(PlaintextError::Exists(target));
+    }
+
+    let data = Zeroizing::new(fs::read(path)?);
+    let recipients = recipients::resolve(&target, root)?;
+    let ciphertext = backend.encrypt(&data, &recipients)?;
+    fs::write(&target, ciphertext)?;
+    shred(path)?;
+    debug!("Encrypted '{}' to '{}'", path.display(), target.display());
+    Ok(target)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn skips_symbolic_links() {
+        let root = std::env::temp_dir().join(format!("defira-plaintext-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/notes.txt"), "plaintext").unwrap();
+        // A link back to the root would make a naive walk loop forever
+        symlink(&root, root.join("dir/loop")).unwrap();
+        symlink(root.join("dir/notes.txt"), root.join("link.txt")).unwrap();
+
+        assert_eq!(scan(&root), vec![root.join("dir/notes.txt")]);
+        assert!(matches!(
+            encrypt_in_place(&root, &root.join("link.txt")),
+            Err(PlaintextError::NotRegularFile(_))
+        ));
+        assert!(matches!(
+            shred(&root.join("link.txt")),
+            Err(PlaintextError::NotRegularFile(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(root.join("dir/notes.txt")).unwrap(),
+            "plaintext"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
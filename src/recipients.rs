@@ -0,0 +1,204 @@
+use crate::crypto::{self, CryptoBackend, CryptoError};
+use log::debug;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the file listing the recipients of a directory, as used by pass.
+pub const GPG_ID_FILE: &str = ".gpg-id";
+/// Detached signature of a `.gpg-id` file, next to it.
+pub const GPG_ID_SIGNATURE_FILE: &str = ".gpg-id.sig";
+/// Fingerprints of the keys trusted to sign `.gpg-id` files, separated by
+/// spaces. Same variable as pass; signatures are only checked if it is set.
+pub const SIGNING_KEY_VAR: &str = "PASSWORD_STORE_SIGNING_KEY";
+
+#[derive(Debug)]
+pub enum RecipientsError {
+    NotFound(PathBuf),
+    Empty(PathBuf),
+    IoError(PathBuf, std::io::Error),
+    Unsigned(PathBuf),
+    UntrustedSignature(PathBuf),
+    Crypto(PathBuf, CryptoError),
+}
+
+impl fmt::Display for RecipientsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipientsError::NotFound(path) => {
+                write!(f, "No recipients file found for '{}'", path.display())
+            }
+            RecipientsError::Empty(path) => {
+                write!(f, "'{}' does not list any recipients", path.display())
+            }
+            RecipientsError::IoError(path, e) => {
+                write!(f, "Could not read '{}': {}", path.display(), e)
+            }
+            RecipientsError::Unsigned(path) => {
+                write!(f, "'{}' has no signature", path.display())
+            }
+            RecipientsError::UntrustedSignature(path) => write!(
+                f,
+                "'{}' is not signed by a key listed in {}",
+                path.display(),
+                SIGNING_KEY_VAR
+            ),
+            RecipientsError::Crypto(path, e) => write!(f, "'{}': {}", path.display(), e),
+        }
+    }
+}
+
+/// Find the `.gpg-id` file that applies to `path`, or the recipients file of
+/// the backend of the secret at `path`, e.g. `.age-recipients`. Directories
+/// use the recipients file of the store's backend.
+///
+/// Starting at the directory containing `path` (or `path` itself if it is a
+/// directory), walk up towards `root` and return the first file found.
+/// Directories above `root` are never considered.
+pub fn find_gpg_id(path: &Path, root: &Path) -> Option<PathBuf> {
+    let file_name = crypto::backend_of(path)
+        .unwrap_or_else(|| crypto::store_backend(root))
+        .recipients_file();
+    let mut dir = if path.is_dir() {
+        Some(path)
+    } else {
+        path.parent()
+    };
+
+    while let Some(current) = dir {
+        if !current.starts_with(root) {
+            break;
+        }
+        let candidate = current.join(file_name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+/// Fingerprints of the keys trusted to sign `.gpg-id` files, from
+/// [`SIGNING_KEY_VAR`]. Empty if signatures are not used.
+pub fn signing_keys() -> Vec<String> {
+    env::var(SIGNING_KEY_VAR)
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_uppercase)
+        .collect()
+}
+
+fn signature_path(gpg_id: &Path) -> PathBuf {
+    gpg_id.with_file_name(GPG_ID_SIGNATURE_FILE)
+}
+
+/// Check that `content` of `gpg_id` is signed by one of `trusted`.
+fn verify(gpg_id: &Path, content: &[u8], trusted: &[String]) -> Result<(), RecipientsError> {
+    let signature_path = signature_path(gpg_id);
+    let signature = match fs::read(&signature_path) {
+        Ok(signature) => signature,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(RecipientsError::Unsigned(gpg_id.to_path_buf()))
+        }
+        Err(e) => return Err(RecipientsError::IoError(signature_path, e)),
+    };
+    let signers = crypto::verify_detached(&signature, content)
+        .map_err(|e| RecipientsError::Crypto(gpg_id.to_path_buf(), e))?;
+    if !signers
+        .iter()
+        .any(|signer| trusted.contains(&signer.to_uppercase()))
+    {
+        return Err(RecipientsError::UntrustedSignature(gpg_id.to_path_buf()));
+    }
+    debug!("Verified the signature of '{}'", gpg_id.display());
+    Ok(())
+}
+
+/// Read the recipients listed in a `.gpg-id` or other recipients file,
+/// checking the signature of a `.gpg-id` if [`SIGNING_KEY_VAR`] is set.
+///
+/// One recipient per line; blank lines and `#` comments are ignored.
+pub fn read_gpg_id(gpg_id: &Path) -> Result<Vec<String>, RecipientsError> {
+    let content = fs::read_to_string(gpg_id)
+        .map_err(|e| RecipientsError::IoError(gpg_id.to_path_buf(), e))?;
+    let trusted = signing_keys();
+    if !trusted.is_empty() && gpg_id.file_name().is_some_and(|name| name == GPG_ID_FILE) {
+        verify(gpg_id, content.as_bytes(), &trusted)?;
+    }
+
+    let recipients: Vec<String> = content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if recipients.is_empty() {
+        return Err(RecipientsError::Empty(gpg_id.to_path_buf()));
+    }
+    Ok(recipients)
+}
+
+/// Resolve the recipients a secret at `path` must be encrypted to.
+pub fn resolve(path: &Path, root: &Path) -> Result<Vec<String>, RecipientsError> {
+    let gpg_id =
+        find_gpg_id(path, root).ok_or_else(|| RecipientsError::NotFound(path.to_path_buf()))?;
+    debug!(
+        "Using recipients from '{}' for '{}'",
+        gpg_id.display(),
+        path.display()
+    );
+    read_gpg_id(&gpg_id)
+}
+
+/// Write the recipients file of `backend` in `dir`. A `.gpg-id` is signed
+/// with the first key of [`SIGNING_KEY_VAR`] that has a secret key.
+///
+/// Returns the path of the recipients file.
+pub fn write_gpg_id(
+    backend: &dyn CryptoBackend,
+    dir: &Path,
+    recipients: &[String],
+) -> Result<PathBuf, RecipientsError> {
+    let gpg_id = dir.join(backend.recipients_file());
+    let mut content = recipients.join("\n");
+    content.push('\n');
+    if gpg_id.file_name().is_some_and(|name| name != GPG_ID_FILE) {
+        // Only `.gpg-id` files have signatures
+        fs::write(&gpg_id, &content).map_err(|e| RecipientsError::IoError(gpg_id.clone(), e))?;
+        debug!(
+            "Wrote {} recipients to '{}'",
+            recipients.len(),
+            gpg_id.display()
+        );
+        return Ok(gpg_id);
+    }
+
+    let signers = signing_keys();
+    let signature_path = signature_path(&gpg_id);
+    // Sign first so that a failure leaves the old, still valid pair in place
+    let signature = if signers.is_empty() {
+        None
+    } else {
+        let signature = crypto::sign_detached(content.as_bytes(), &signers)
+            .map_err(|e| RecipientsError::Crypto(gpg_id.clone(), e))?;
+        Some(signature)
+    };
+
+    fs::write(&gpg_id, &content).map_err(|e| RecipientsError::IoError(gpg_id.clone(), e))?;
+    match signature {
+        Some(signature) => fs::write(&signature_path, signature)
+            .map_err(|e| RecipientsError::IoError(signature_path.clone(), e))?,
+        // A signature of the old content would no longer match
+        None if signature_path.exists() => fs::remove_file(&signature_path)
+            .map_err(|e| RecipientsError::IoError(signature_path.clone(), e))?,
+        None => {}
+    }
+    debug!(
+        "Wrote {} recipients to '{}'",
+        recipients.len(),
+        gpg_id.display()
+    );
+    Ok(gpg_id)
+}
@@ -0,0 +1,350 @@
+use crate::crypto;
+use crate::job::{self, CancelFlag, Job};
+use crate::secret::Secret;
+use crate::tree;
+use iced::futures::{SinkExt, Stream};
+use iced::widget;
+use iced::{Element, Length, Task};
+use log::{debug, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use zeroize::Zeroizing;
+
+/// Upper bound for the number of secrets decrypted in parallel.
+const MAX_WORKERS: usize = 4;
+/// Longest excerpt of a matching value shown in the results.
+const MAX_EXCERPT_CHARS: usize = 80;
+
+/// How field values are matched against the query.
+#[derive(Debug, Clone)]
+enum Matcher {
+    /// Case-insensitive substring, stored in lower case.
+    Substring(String),
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    fn new(query: &str, use_regex: bool) -> Result<Self, regex::Error> {
+        if use_regex {
+            regex::RegexBuilder::new(query)
+                .case_insensitive(true)
+                .build()
+                .map(Matcher::Regex)
+        } else {
+            Ok(Matcher::Substring(query.to_lowercase()))
+        }
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Substring(query) => text.to_lowercase().contains(query),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// A field of a secret matching the query.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub path: PathBuf,
+    pub field: String,
+    /// First matching line of the field value, shortened.
+    pub excerpt: String,
+}
+
+/// Fields of the secret at `path` matching `matcher`. The password and the
+/// OTP seed are never searched, so they never end up in the results.
+fn search_file(path: &Path, matcher: &Matcher) -> Result<Vec<Hit>, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let plaintext = crypto::backend(path)
+        .decrypt_text(&data)
+        .map_err(|e| e.to_string())?;
+    let plaintext = Zeroizing::new(plaintext);
+    let secret = Secret::parse(&plaintext);
+
+    Ok(secret
+        .fields
+        .iter()
+        .filter_map(|(key, value)| {
+            let line = value.lines().find(|line| matcher.is_match(line))?;
+            let mut excerpt: String = line.trim().chars().take(MAX_EXCERPT_CHARS).collect();
+            if line.trim().chars().count() > MAX_EXCERPT_CHARS {
+                excerpt.push('\u{2026}');
+            }
+            Some(Hit {
+                path: path.to_path_buf(),
+                field: key.clone(),
+                excerpt,
+            })
+        })
+        .collect())
+}
+
+/// Search every secret below `roots` on a pool of worker threads, yielding
+/// progress and matches as they are found.
+fn run(roots: Vec<PathBuf>, matcher: Matcher, cancel: CancelFlag) -> impl Stream<Item = Message> {
+    iced::stream::channel(64, move |mut output| async move {
+        let files = roots
+            .iter()
+            .map(|root| tree::secret_files(root))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|files| files.concat());
+        let files = match files {
+            Ok(files) => Arc::new(files),
+            Err(e) => {
+                let _ = output
+                    .send(Message::Failed(format!(
+                        "Could not list the secrets: {}",
+                        e
+                    )))
+                    .await;
+                return;
+            }
+        };
+        let total = files.len();
+        let next = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = mpsc::channel();
+
+        let workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_WORKERS);
+        debug!("Searching {} secrets with {} workers", total, workers);
+        for _ in 0..workers {
+            let (files, matcher, cancel, next, sender) = (
+                files.clone(),
+                matcher.clone(),
+                cancel.clone(),
+                next.clone(),
+                sender.clone(),
+            );
+            thread::spawn(move || {
+                while !cancel.is_cancelled() {
+                    let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let result = search_file(path, &matcher).unwrap_or_else(|e| {
+                        warn!("Skipping '{}' in search: {}", path.display(), e);
+                        Vec::new()
+                    });
+                    if sender.send(result).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut done = 0;
+        while let Ok(hits) = receiver.recv() {
+            done += 1;
+            if !hits.is_empty() && output.send(Message::Found(hits)).await.is_err() {
+                return;
+            }
+            let _ = output.send(Message::Progress(done, total)).await;
+        }
+        let _ = output.send(Message::Finished).await;
+    })
+}
+
+/// Messages for search panel interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    QueryChanged(String),
+    UseRegex(bool),
+    Start,
+    Cancel,
+    /// Number of secrets searched so far, out of the total.
+    Progress(usize, usize),
+    Found(Vec<Hit>),
+    Finished,
+    Failed(String),
+    /// Show the secret of a result.
+    Open(PathBuf),
+    Close,
+}
+
+/// State of the content search panel.
+pub struct State {
+    /// Name and root of every store searched.
+    stores: Vec<(String, PathBuf)>,
+    query: String,
+    use_regex: bool,
+    /// Cancels the running search when dropped.
+    running: Option<Job>,
+    progress: (usize, usize),
+    hits: Vec<Hit>,
+    /// Whether a search has finished since the query was entered.
+    finished: bool,
+    error: Option<String>,
+}
+
+impl State {
+    pub fn new(stores: Vec<(String, PathBuf)>) -> Self {
+        Self {
+            stores,
+            query: String::new(),
+            use_regex: false,
+            running: None,
+            progress: (0, 0),
+            hits: Vec::new(),
+            finished: false,
+            error: None,
+        }
+    }
+
+    fn matcher(&self) -> Result<Matcher, String> {
+        Matcher::new(&self.query, self.use_regex).map_err(|e| e.to_string())
+    }
+
+    /// Start a search, cancelling the one in progress if any.
+    pub fn start(&mut self) -> Task<Message> {
+        if self.query.is_empty() {
+            return Task::none();
+        }
+        let matcher = match self.matcher() {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.error = Some(e);
+                return Task::none();
+            }
+        };
+        let roots = self.stores.iter().map(|(_, root)| root.clone()).collect();
+        let (task, job) = job::spawn(|cancel| run(roots, matcher, cancel));
+        self.running = Some(job);
+        self.progress = (0, 0);
+        self.hits.clear();
+        self.finished = false;
+        self.error = None;
+        task
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::QueryChanged(query) => {
+                self.query = query;
+                self.error = None;
+            }
+            Message::UseRegex(use_regex) => {
+                self.use_regex = use_regex;
+                self.error = None;
+            }
+            Message::Cancel => {
+                debug!("Search cancelled");
+                self.running = None;
+            }
+            Message::Progress(done, total) => self.progress = (done, total),
+            Message::Found(mut hits) => self.hits.append(&mut hits),
+            Message::Finished => {
+                debug!("Search found {} matches", self.hits.len());
+                self.running = None;
+                self.finished = true;
+            }
+            Message::Failed(e) => {
+                self.running = None;
+                self.error = Some(e);
+            }
+            Message::Start | Message::Open(_) | Message::Close => {}
+        }
+    }
+}
+
+fn view_hits(state: &State) -> Element<'_, Message> {
+    let mut results = widget::column![].spacing(2);
+    for hit in &state.hits {
+        let store = state
+            .stores
+            .iter()
+            .find(|(_, root)| hit.path.starts_with(root));
+        let name = store
+            .and_then(|(_, root)| hit.path.strip_prefix(root).ok())
+            .unwrap_or(&hit.path)
+            .with_extension("");
+        // Results are only told apart by store when searching several
+        let name = match store {
+            Some((store, _)) if state.stores.len() > 1 => {
+                format!("{}: {}", store, name.display())
+            }
+            _ => name.display().to_string(),
+        };
+        let label = widget::column![
+            widget::text(name).size(14),
+            widget::text(format!("{}: {}", hit.field, hit.excerpt)).size(12),
+        ];
+        results = results.push(
+            widget::button(label)
+                .on_press(Message::Open(hit.path.clone()))
+                .style(widget::button::text)
+                .width(Length::Fill),
+        );
+    }
+    widget::scrollable(results).height(Length::Fill).into()
+}
+
+/// Renders the search panel: the query, the progress and the matching fields.
+///
+/// # Arguments
+/// * `state` - The current search panel state
+/// * `on_message` - Function to wrap search messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let close_button = widget::button(widget::text("X").size(14)).on_press(Message::Close);
+    let title = if state.stores.len() > 1 {
+        "Search secrets in all stores"
+    } else {
+        "Search secrets"
+    };
+    let header = widget::row![
+        widget::text(title).size(16),
+        widget::horizontal_space(),
+        close_button
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let action = if state.running.is_some() {
+        widget::button(widget::text("Cancel").size(14)).on_press(Message::Cancel)
+    } else {
+        widget::button(widget::text("Search").size(14))
+            .on_press_maybe((!state.query.is_empty()).then_some(Message::Start))
+    };
+    let query = widget::row![
+        widget::text_input("Username, URL, note...", &state.query)
+            .on_input(Message::QueryChanged)
+            .on_submit(Message::Start),
+        widget::checkbox("Regex", state.use_regex).on_toggle(Message::UseRegex),
+        action
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let status = if let Some(e) = &state.error {
+        Some(
+            widget::text(e)
+                .size(14)
+                .color(iced::Color::from_rgb(0.7, 0.1, 0.1)),
+        )
+    } else if state.running.is_some() {
+        let (done, total) = state.progress;
+        Some(widget::text(format!("Searched {} of {} secrets...", done, total)).size(14))
+    } else if state.finished {
+        Some(widget::text(format!("{} matching fields.", state.hits.len())).size(14))
+    } else {
+        None
+    };
+
+    let content: Element<'a, Message> = widget::column![header, query]
+        .push_maybe(status)
+        .push(view_hits(state))
+        .spacing(10)
+        .padding(10)
+        .height(Length::Fill)
+        .width(Length::Fill)
+        .into();
+    content.map(on_message)
+}
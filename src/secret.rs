@@ -0,0 +1,146 @@
+use zeroize::Zeroize;
+
+/// Structured content of a secret in pass format.
+///
+/// ```text
+/// <password>
+/// username: alice
+/// url: https://example.com
+/// otpauth://totp/Example:alice?secret=...
+/// notes: first line of the notes
+///   continuation lines are indented by two spaces
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Secret {
+    pub password: String,
+    /// `key: value` fields in display order. Values may span several lines.
+    pub fields: Vec<(String, String)>,
+    /// TOTP secret as an `otpauth://` URI, as used by pass-otp.
+    pub otp: Option<String>,
+}
+
+impl Secret {
+    pub fn new(password: impl Into<String>) -> Self {
+        Self {
+            password: password.into(),
+            fields: Vec::new(),
+            otp: None,
+        }
+    }
+
+    /// Parse a secret in pass format, the inverse of [`Secret::to_text`].
+    ///
+    /// Lines that are not `key: value` fields, continuations or an
+    /// `otpauth://` URI are collected into a `notes` field.
+    pub fn parse(text: &str) -> Self {
+        let mut lines = text.lines();
+        let mut secret = Secret::new(lines.next().unwrap_or_default());
+        let mut notes = Vec::new();
+
+        for line in lines {
+            if let Some(continuation) = line.strip_prefix("  ") {
+                if let Some((_, value)) = secret.fields.last_mut() {
+                    value.push('\n');
+                    value.push_str(continuation);
+                    continue;
+                }
+            }
+            if line.starts_with("otpauth://") && secret.otp.is_none() {
+                secret.otp = Some(line.to_string());
+                continue;
+            }
+            match line.split_once(':') {
+                Some((key, value)) if is_field_key(key, value) => {
+                    secret
+                        .fields
+                        .push((key.to_string(), value.trim_start().to_string()));
+                }
+                _ if line.trim().is_empty() => {}
+                _ => notes.push(line),
+            }
+        }
+
+        if !notes.is_empty() {
+            let notes = notes.join("\n");
+            match secret.fields.iter_mut().find(|(key, _)| key == "notes") {
+                Some((_, value)) => {
+                    value.push('\n');
+                    value.push_str(&notes);
+                }
+                None => secret.fields.push(("notes".to_string(), notes)),
+            }
+        }
+        secret
+    }
+
+    /// Add a field, skipping empty values.
+    pub fn push_field(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let value = value.into();
+        if !value.trim().is_empty() {
+            self.fields.push((key.into(), value));
+        }
+    }
+
+    /// Render the secret in pass format.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&self.password);
+        text.push('\n');
+
+        for (key, value) in &self.fields {
+            let mut lines = value.lines();
+            text.push_str(&format!("{}: {}\n", key, lines.next().unwrap_or_default()));
+            for line in lines {
+                text.push_str("  ");
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+
+        if let Some(otp) = &self.otp {
+            text.push_str(otp);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Whether `key: value` is a field rather than free text like a URL or a sentence.
+fn is_field_key(key: &str, value: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 40
+        && !key.starts_with(char::is_whitespace)
+        && !value.starts_with("//")
+}
+
+/// Turn a TOTP secret into an `otpauth://` URI, leaving existing URIs untouched.
+pub fn otp_uri(label: &str, totp: &str) -> String {
+    let totp = totp.trim();
+    if totp.starts_with("otpauth://") {
+        return totp.to_string();
+    }
+    let label: String = label
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    let totp: String = totp.chars().filter(|c| !c.is_whitespace()).collect();
+    format!("otpauth://totp/{}?secret={}", label, totp.to_uppercase())
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.password.zeroize();
+        for (_, value) in self.fields.iter_mut() {
+            value.zeroize();
+        }
+        if let Some(otp) = self.otp.as_mut() {
+            otp.zeroize();
+        }
+    }
+}
@@ -1,60 +1,537 @@
+use crate::age;
+use crate::crypto::{self, CryptoBackend};
+use crate::git;
+use crate::init;
+use crate::keygen;
 use iced::widget;
-use iced::{Element, Length};
+use iced::{Element, Length, Task};
+use log::{debug, error};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A store ready to be opened: cloned, already cloned before, or created.
+#[derive(Debug, Clone)]
+pub struct Store {
+    pub root: PathBuf,
+    pub remote: Option<String>,
+}
+
+/// Steps of the setup wizard, in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Url,
+    Gpg,
+    Access,
+    Directory,
+    Cloning,
+}
+
+impl Step {
+    fn title(self) -> &'static str {
+        match self {
+            Step::Url => "Step 1 of 4: Repository",
+            Step::Gpg => "Step 2 of 4: Encryption keys",
+            Step::Access => "Step 3 of 4: Repository access",
+            Step::Directory | Step::Cloning => "Step 4 of 4: Local copy",
+        }
+    }
+
+    fn previous(self) -> Self {
+        match self {
+            Step::Url | Step::Gpg => Step::Url,
+            Step::Access => Step::Gpg,
+            Step::Directory | Step::Cloning => Step::Access,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum Message {
     UrlChanged(String),
+    /// Continue from the URL step.
     Submit,
+    CheckGpg,
+    /// The keys to decrypt with were listed in the background.
+    GpgChecked(Result<Vec<String>, String>),
+    /// Show the form for a new GPG key.
+    GenerateKey,
+    Keygen(keygen::Message),
+    /// Continue from the GnuPG step.
+    Next,
+    CheckAccess,
+    AccessChecked(Result<(), String>),
+    PickDirectory,
+    Clone,
+    Cloned(Result<(), String>),
+    Back,
+    /// Show the form for a new store.
+    CreateStore,
+    Init(init::Message),
+    /// The store to open. Handled by the parent.
+    Finished(Store),
+    /// Return to the open stores without adding one. Handled by the parent.
+    Cancel,
 }
 
 pub struct State {
     pub github_url: String,
+    step: Step,
+    /// Fingerprints of the GPG secret keys and age recipients of the user,
+    /// or why there is nothing to decrypt with. `None` while checking.
+    gpg: Option<Result<Vec<String>, String>>,
+    /// Whether the repository access is being tested.
+    checking: bool,
+    directory: PathBuf,
+    error: Option<String>,
+    /// Set while creating a new store instead of entering a URL.
+    create: Option<init::State>,
+    /// Set while generating a GPG key.
+    keygen: Option<keygen::State>,
+    /// Whether stores are open already, so that the setup can be cancelled.
+    cancellable: bool,
 }
 
 impl Default for State {
     fn default() -> Self {
+        // Same default location as pass
+        let home = std::env::var("HOME").unwrap_or(".".to_string());
         Self {
             github_url: String::new(),
+            step: Step::Url,
+            gpg: None,
+            checking: false,
+            directory: PathBuf::from(home).join(".password-store"),
+            error: None,
+            create: None,
+            keygen: None,
+            cancellable: false,
         }
     }
 }
 
-/// Returns Some(url) when the user submits, signaling a screen transition
-pub fn update(state: &mut State, message: Message) -> Option<String> {
+impl State {
+    /// Set up a store in addition to the open ones.
+    pub fn adding() -> Self {
+        Self {
+            cancellable: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Check that `url` is a git URL defira can use: HTTPS, SSH (`ssh://` or
+/// `user@host:path`) or `file://`.
+pub fn validate_url(url: &str) -> Result<(), String> {
+    if url.is_empty() {
+        return Err("Enter the URL of the repository.".to_string());
+    }
+    if url.chars().any(char::is_whitespace) {
+        return Err("The URL must not contain spaces.".to_string());
+    }
+
+    if let Some(rest) = url.strip_prefix("https://") {
+        return match rest.split_once('/') {
+            Some((host, path)) if !host.is_empty() && !path.trim_matches('/').is_empty() => Ok(()),
+            _ => Err(
+                "Add the path of the repository, e.g. https://github.com/team/store.git"
+                    .to_string(),
+            ),
+        };
+    }
+    if url.starts_with("http://") {
+        return Err("Plain HTTP is not encrypted. Use an https:// or SSH URL.".to_string());
+    }
+    if let Some(rest) = url.strip_prefix("ssh://") {
+        return match rest.split_once('/') {
+            Some((host, path)) if !host.is_empty() && !path.is_empty() => Ok(()),
+            _ => Err("Add the host and path, e.g. ssh://git@github.com/team/store.git".to_string()),
+        };
+    }
+    if let Some(path) = url.strip_prefix("file://") {
+        return if Path::new(path).is_dir() {
+            Ok(())
+        } else {
+            Err(format!("The directory '{}' does not exist.", path))
+        };
+    }
+    // scp-like SSH syntax: [user@]host:path
+    match url.split_once(':') {
+        Some((host, path)) if !host.is_empty() && !host.contains('/') && !path.is_empty() => Ok(()),
+        _ => Err("Use an https://, ssh:// or file:// URL, or git@host:path for SSH.".to_string()),
+    }
+}
+
+const NO_SECRET_KEY: &str = "GnuPG works, but there is no secret key to decrypt with. \
+    Generate one with \"Generate a key...\" or import yours with `gpg --import`, \
+    then check again. For a store encrypted with age, add your identity to \
+    ~/.passage/identities instead.";
+
+/// Find the GPG secret keys and age identities the user can decrypt with,
+/// explaining how to fix it if there are none. Which backend the store uses
+/// is only known once it is cloned, so either is enough.
+fn check_gpg() -> Result<Vec<String>, String> {
+    let gpg = crypto::GPG.own_recipients();
+    let age = age::AGE.own_recipients().unwrap_or_else(|e| {
+        debug!("No age identity: {}", e);
+        Vec::new()
+    });
+    match gpg {
+        Ok(mut keys) if !keys.is_empty() || !age.is_empty() => {
+            keys.extend(age);
+            Ok(keys)
+        }
+        Err(_) if !age.is_empty() => Ok(age),
+        Ok(_) => Err(NO_SECRET_KEY.to_string()),
+        Err(e) => {
+            error!("Failed to use GnuPG: {}", e);
+            Err(format!(
+                "GnuPG is not available: {}. Install GnuPG, e.g. with \
+                 `sudo apt-get install gnupg`, then check again.",
+                e
+            ))
+        }
+    }
+}
+
+fn check_keys() -> Task<Message> {
+    Task::perform(async { check_gpg() }, Message::GpgChecked)
+}
+
+/// Add a hint on how to fix a failed `git ls-remote` or `git clone`.
+fn explain_git_error(error: &str) -> String {
+    let hint = if error.contains("Permission denied (publickey)") {
+        "The server rejected your SSH key. Add your public key to your account \
+         or load it into ssh-agent."
+    } else if error.contains("Host key verification failed") {
+        "The server's SSH host key is not known yet. Connect once with ssh to \
+         accept it."
+    } else if error.contains("terminal prompts disabled")
+        || error.contains("Authentication failed")
+        || error.contains("could not read Username")
+    {
+        "The server asked for credentials. Set up a git credential helper or \
+         use an SSH URL."
+    } else if error.contains("Could not resolve host") {
+        "Check the host name and your network connection."
+    } else if error.contains("not found")
+        || error.contains("does not appear to be a git repository")
+    {
+        "Check the URL. The repository does not exist or you have no access to it."
+    } else {
+        return error.to_string();
+    };
+    format!("{}\n\n{}", hint, error)
+}
+
+/// Whether `dir` already holds a clone of `url`. Fails if `dir` holds
+/// anything else, as cloning needs an empty or missing directory.
+fn existing_clone(dir: &Path, url: &str) -> Result<bool, String> {
+    let mut entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(format!("Could not read '{}': {}", dir.display(), e)),
+    };
+    if entries.next().is_none() {
+        return Ok(false);
+    }
+    if dir.join(".git").exists() && git::remote_url(dir).is_ok_and(|remote| remote == url) {
+        return Ok(true);
+    }
+    Err(format!(
+        "'{}' is not empty. Choose an empty directory or an existing clone of {}.",
+        dir.display(),
+        url
+    ))
+}
+
+fn check_access(url: String) -> Task<Message> {
+    Task::perform(
+        async move { git::check_access(&url).map_err(|e| explain_git_error(&e.to_string())) },
+        Message::AccessChecked,
+    )
+}
+
+fn finished(state: &State) -> Task<Message> {
+    Task::done(Message::Finished(Store {
+        root: state.directory.clone(),
+        remote: Some(state.github_url.trim().to_string()),
+    }))
+}
+
+/// Advances the wizard. Emits [`Message::Finished`] once there is a store to open.
+pub fn update(state: &mut State, message: Message) -> Task<Message> {
     match message {
         Message::UrlChanged(url) => {
             state.github_url = url;
-            None
+            state.error = None;
+        }
+        Message::Submit => match validate_url(state.github_url.trim()) {
+            Ok(()) => {
+                state.error = None;
+                state.step = Step::Gpg;
+                state.gpg = None;
+                return check_keys();
+            }
+            Err(e) => state.error = Some(e),
+        },
+        Message::CheckGpg => {
+            state.gpg = None;
+            return check_keys();
+        }
+        Message::GpgChecked(result) => state.gpg = Some(result),
+        Message::GenerateKey => state.keygen = Some(keygen::State::default()),
+        Message::Keygen(keygen::Message::Close) => {
+            state.keygen = None;
+            state.gpg = None;
+            return check_keys();
         }
-        Message::Submit => Some(state.github_url.clone()),
+        Message::Keygen(msg) => {
+            if let Some(keygen) = &mut state.keygen {
+                return keygen.update(msg).map(Message::Keygen);
+            }
+        }
+        Message::Next => {
+            if state.gpg.as_ref().is_some_and(Result::is_ok) {
+                state.step = Step::Access;
+                return update(state, Message::CheckAccess);
+            }
+        }
+        Message::CheckAccess => {
+            state.checking = true;
+            state.error = None;
+            return check_access(state.github_url.trim().to_string());
+        }
+        Message::AccessChecked(result) => {
+            state.checking = false;
+            // The user may have gone back in the meantime
+            if state.step != Step::Access {
+                return Task::none();
+            }
+            match result {
+                Ok(()) => state.step = Step::Directory,
+                Err(e) => state.error = Some(e),
+            }
+        }
+        Message::PickDirectory => {
+            if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                state.directory = dir;
+                state.error = None;
+            }
+        }
+        Message::Clone => {
+            let url = state.github_url.trim().to_string();
+            match existing_clone(&state.directory, &url) {
+                Ok(true) => {
+                    debug!(
+                        "Using the existing clone in '{}'",
+                        state.directory.display()
+                    );
+                    return finished(state);
+                }
+                Ok(false) => {
+                    state.step = Step::Cloning;
+                    state.error = None;
+                    let dir = state.directory.clone();
+                    return Task::perform(
+                        async move {
+                            git::clone(&url, &dir).map_err(|e| explain_git_error(&e.to_string()))
+                        },
+                        Message::Cloned,
+                    );
+                }
+                Err(e) => state.error = Some(e),
+            }
+        }
+        Message::Cloned(Ok(())) => return finished(state),
+        Message::Cloned(Err(e)) => {
+            error!("Failed to clone: {}", e);
+            state.step = Step::Directory;
+            state.error = Some(e);
+        }
+        Message::Back => {
+            state.step = state.step.previous();
+            state.error = None;
+        }
+        Message::CreateStore => {
+            let (create, task) = init::State::new();
+            state.create = Some(create);
+            return task.map(Message::Init);
+        }
+        Message::Init(init::Message::Back) => state.create = None,
+        Message::Init(init::Message::Created(Ok(store))) => {
+            return Task::done(Message::Finished(store));
+        }
+        Message::Init(msg) => {
+            if let Some(create) = &mut state.create {
+                return create.update(msg).map(Message::Init);
+            }
+        }
+        Message::Finished(_) | Message::Cancel => {}
     }
+    Task::none()
 }
 
-pub fn view(state: &State) -> Element<'_, Message> {
-    let title = widget::text("Welcome to Defira")
-        .size(32)
-        .width(Length::Fill)
-        .align_x(iced::Alignment::Center);
-
-    let label = widget::text("Enter your GitHub repository URL:")
-        .size(16)
-        .width(Length::Fill)
-        .align_x(iced::Alignment::Center);
+fn view_url(state: &State) -> Element<'_, Message> {
+    let label = widget::text("Enter the URL of your password store repository:").size(16);
 
     let input = widget::text_input("https://github.com/...", &state.github_url)
         .on_input(Message::UrlChanged)
         .on_submit(Message::Submit)
-        .padding(10)
-        .width(400);
+        .padding(10);
 
     let button = widget::button(widget::text("Continue"))
         .on_press(Message::Submit)
         .padding([10, 30]);
 
-    let content = widget::column![title, label, input, button]
+    let create_button = widget::button(widget::text("Create new store"))
+        .on_press(Message::CreateStore)
+        .style(widget::button::secondary)
+        .padding([10, 30]);
+
+    let cancel_button = state.cancellable.then(|| {
+        widget::button(widget::text("Cancel"))
+            .on_press(Message::Cancel)
+            .style(widget::button::secondary)
+            .padding([10, 30])
+    });
+
+    widget::column![
+        label,
+        input,
+        widget::row![create_button, widget::horizontal_space()]
+            .push_maybe(cancel_button)
+            .push(button)
+            .spacing(10)
+    ]
+    .spacing(20)
+    .into()
+}
+
+fn view_gpg(state: &State) -> Element<'_, Message> {
+    let status: Element<'_, Message> = match &state.gpg {
+        Some(Ok(keys)) => {
+            let mut list = widget::column![widget::text(format!(
+                "Found {} key(s) to decrypt with:",
+                keys.len()
+            ))
+            .size(16)]
+            .spacing(4);
+            for key in keys {
+                list = list.push(widget::text(key).size(14).font(iced::Font::MONOSPACE));
+            }
+            list.into()
+        }
+        // The error is shown below like on the other steps
+        Some(Err(_)) => widget::text("").into(),
+        None => widget::text("Looking for your keys...").size(16).into(),
+    };
+    let ready = state.gpg.as_ref().is_some_and(Result::is_ok);
+
+    widget::column![
+        status,
+        widget::row![
+            widget::button(widget::text("Back")).on_press(Message::Back),
+            widget::horizontal_space(),
+            widget::button(widget::text("Generate a key...")).on_press(Message::GenerateKey),
+            widget::button(widget::text("Check again"))
+                .on_press_maybe(state.gpg.is_some().then_some(Message::CheckGpg)),
+            widget::button(widget::text("Continue")).on_press_maybe(ready.then_some(Message::Next))
+        ]
+        .spacing(10)
+    ]
+    .spacing(20)
+    .into()
+}
+
+fn view_access(state: &State) -> Element<'_, Message> {
+    let status = if state.checking {
+        format!("Connecting to {}...", state.github_url.trim())
+    } else {
+        format!("Could not read {}.", state.github_url.trim())
+    };
+
+    widget::column![
+        widget::text(status).size(16),
+        widget::row![
+            widget::button(widget::text("Back")).on_press(Message::Back),
+            widget::horizontal_space(),
+            widget::button(widget::text("Retry"))
+                .on_press_maybe((!state.checking).then_some(Message::CheckAccess))
+        ]
+        .spacing(10)
+    ]
+    .spacing(20)
+    .into()
+}
+
+fn view_directory(state: &State) -> Element<'_, Message> {
+    let cloning = state.step == Step::Cloning;
+    let directory = widget::row![
+        widget::text(state.directory.display().to_string())
+            .size(14)
+            .width(Length::Fill),
+        widget::button(widget::text("Choose...").size(14))
+            .on_press_maybe((!cloning).then_some(Message::PickDirectory))
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let clone_label = if cloning { "Cloning..." } else { "Clone" };
+    widget::column![
+        widget::text("Choose where to keep the local copy of the store:").size(16),
+        directory,
+        widget::row![
+            widget::button(widget::text("Back"))
+                .on_press_maybe((!cloning).then_some(Message::Back)),
+            widget::horizontal_space(),
+            widget::button(widget::text(clone_label))
+                .on_press_maybe((!cloning).then_some(Message::Clone))
+        ]
+        .spacing(10)
+    ]
+    .spacing(20)
+    .into()
+}
+
+pub fn view(state: &State) -> Element<'_, Message> {
+    if let Some(create) = &state.create {
+        return init::view(create, Message::Init);
+    }
+    if let Some(keygen) = &state.keygen {
+        return keygen::view(keygen, Message::Keygen);
+    }
+
+    let title = widget::text("Welcome to Defira")
+        .size(32)
+        .width(Length::Fill)
+        .align_x(iced::Alignment::Center);
+
+    let step = widget::text(state.step.title()).size(20);
+
+    let body = match state.step {
+        Step::Url => view_url(state),
+        Step::Gpg => view_gpg(state),
+        Step::Access => view_access(state),
+        Step::Directory | Step::Cloning => view_directory(state),
+    };
+
+    let error = state
+        .error
+        .as_deref()
+        .or_else(|| match &state.gpg {
+            Some(Err(e)) if state.step == Step::Gpg => Some(e.as_str()),
+            _ => None,
+        })
+        .map(|e| {
+            widget::text(e)
+                .size(14)
+                .color(iced::Color::from_rgb(0.7, 0.1, 0.1))
+        });
+
+    let content = widget::column![title, step, body]
+        .push_maybe(error)
         .spacing(20)
-        .align_x(iced::Alignment::Center)
-        .width(Length::Fill);
+        .width(500);
 
     widget::container(content)
         .center_x(Length::Fill)
@@ -0,0 +1,105 @@
+//! The configured password stores, kept in `stores.json` in the user's
+//! configuration directory.
+
+use log::{debug, error};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "stores.json";
+
+/// A store shown in the explorer, with its own settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreConfig {
+    /// Name shown in the store switcher.
+    pub name: String,
+    pub root: PathBuf,
+    /// URL the store was cloned from or pushes to, if any.
+    pub remote: Option<String>,
+}
+
+impl StoreConfig {
+    /// A store named after its directory.
+    pub fn new(root: PathBuf, remote: Option<String>) -> Self {
+        let name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().trim_start_matches('.').to_string())
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| root.display().to_string());
+        Self { name, root, remote }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "root": self.root.to_string_lossy(),
+            "remote": self.remote,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let root = PathBuf::from(value.get("root")?.as_str()?);
+        let remote = value
+            .get("remote")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let mut store = Self::new(root, remote);
+        if let Some(name) = value.get("name").and_then(Value::as_str) {
+            store.name = name.to_string();
+        }
+        Some(store)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/defira/stores.json`, or below `~/.config`.
+fn config_path() -> PathBuf {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| {
+            let home = env::var("HOME").unwrap_or(".".to_string());
+            Path::new(&home).join(".config")
+        });
+    config_dir.join("defira").join(CONFIG_FILE)
+}
+
+/// The configured stores, empty if none are configured yet.
+pub fn load() -> Vec<StoreConfig> {
+    let path = config_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            error!("Failed to read '{}': {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    let stores: Vec<StoreConfig> = match serde_json::from_str::<Value>(&content) {
+        Ok(value) => value
+            .get("stores")
+            .and_then(Value::as_array)
+            .map(|stores| stores.iter().filter_map(StoreConfig::from_json).collect())
+            .unwrap_or_default(),
+        Err(e) => {
+            error!("Failed to parse '{}': {}", path.display(), e);
+            Vec::new()
+        }
+    };
+    debug!("Loaded {} stores from '{}'", stores.len(), path.display());
+    stores
+}
+
+/// Write the configured stores.
+pub fn save(stores: &[StoreConfig]) -> std::io::Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let config = json!({
+        "stores": stores.iter().map(StoreConfig::to_json).collect::<Vec<_>>(),
+    });
+    fs::write(&path, serde_json::to_string_pretty(&config)?)?;
+    debug!("Saved {} stores to '{}'", stores.len(), path.display());
+    Ok(())
+}
@@ -0,0 +1,352 @@
+//! Password strength estimation in the spirit of zxcvbn.
+//!
+//! A password is split into the cheapest sequence of patterns an attacker
+//! would try: common passwords and words, keyboard walks, sequences, repeats
+//! and years, with brute force for whatever is left. The estimated number of
+//! guesses is mapped to a score from 0 (trivial) to 4 (strong).
+
+/// Guesses per character that no pattern covers.
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+/// Minimum length of sequences, repeats and keyboard walks.
+const MIN_PATTERN_LENGTH: usize = 3;
+
+/// Frequently used passwords and words, most common first.
+const COMMON: &[&str] = &[
+    "password",
+    "123456",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "1234567890",
+    "michael",
+    "654321",
+    "superman",
+    "1qaz2wsx",
+    "7777777",
+    "121212",
+    "000000",
+    "qazwsx",
+    "123qwe",
+    "killer",
+    "trustno1",
+    "jordan",
+    "jennifer",
+    "zxcvbnm",
+    "asdfgh",
+    "hunter",
+    "buster",
+    "soccer",
+    "harley",
+    "batman",
+    "andrew",
+    "tigger",
+    "sunshine",
+    "iloveyou",
+    "2000",
+    "charlie",
+    "robert",
+    "thomas",
+    "hockey",
+    "ranger",
+    "daniel",
+    "starwars",
+    "112233",
+    "george",
+    "computer",
+    "michelle",
+    "jessica",
+    "pepper",
+    "1111",
+    "zxcvbn",
+    "555555",
+    "11111111",
+    "131313",
+    "freedom",
+    "777777",
+    "pass",
+    "maggie",
+    "159753",
+    "aaaaaa",
+    "ginger",
+    "princess",
+    "joshua",
+    "cheese",
+    "amanda",
+    "summer",
+    "love",
+    "ashley",
+    "nicole",
+    "chelsea",
+    "biteme",
+    "matthew",
+    "access",
+    "yankees",
+    "987654321",
+    "dallas",
+    "austin",
+    "thunder",
+    "taylor",
+    "matrix",
+    "welcome",
+    "admin",
+    "login",
+    "secret",
+    "winter",
+    "spring",
+    "autumn",
+    "changeme",
+    "default",
+    "guest",
+    "root",
+    "test",
+    "hello",
+    "flower",
+    "orange",
+    "banana",
+    "apple",
+    "company",
+    "office",
+    "server",
+    "github",
+    "google",
+    "facebook",
+    "linux",
+    "windows",
+];
+
+const KEYBOARD_ROWS: &[&str] = &[
+    "1234567890-=",
+    "qwertyuiop[]",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+    "qwertzuiopü",
+    "asdfghjklöä",
+    "yxcvbnm,.-",
+];
+
+/// A part of the password matched by a pattern, covering `start..end`.
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+}
+
+/// Undo common character substitutions like `p@ssw0rd`.
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        '8' => 'b',
+        c => c,
+    }
+}
+
+fn dictionary_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower.len() != chars.len() {
+        return;
+    }
+    let unleeted: Vec<char> = lower.iter().map(|c| unleet(*c)).collect();
+
+    for (rank, word) in COMMON.iter().enumerate() {
+        let word: Vec<char> = word.chars().collect();
+        for start in 0..chars.len().saturating_sub(word.len() - 1) {
+            let end = start + word.len();
+            let plain = lower[start..end] == word[..];
+            if plain || unleeted[start..end] == word[..] {
+                let mut guesses = (rank + 1) as f64;
+                if chars[start..end].iter().any(|c| c.is_uppercase()) {
+                    guesses *= 2.0;
+                }
+                if !plain {
+                    guesses *= 2.0;
+                }
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses,
+                });
+            }
+        }
+    }
+}
+
+/// Runs of at least `MIN_PATTERN_LENGTH` characters where `step` holds for
+/// every neighbouring pair.
+fn runs(chars: &[char], step: impl Fn(char, char) -> bool) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..=chars.len() {
+        if i == chars.len() || !step(chars[i - 1], chars[i]) {
+            if i - start >= MIN_PATTERN_LENGTH {
+                runs.push((start, i));
+            }
+            start = i;
+        }
+    }
+    runs
+}
+
+fn pattern_matches(chars: &[char], matches: &mut Vec<Match>) {
+    // aaaa, 1111
+    for (start, end) in runs(chars, |a, b| a == b) {
+        matches.push(Match {
+            start,
+            end,
+            guesses: 12.0 * (end - start) as f64,
+        });
+    }
+
+    // abcd, 4321
+    for delta in [1i32, -1] {
+        for (start, end) in runs(chars, |a, b| b as i32 - a as i32 == delta) {
+            matches.push(Match {
+                start,
+                end,
+                guesses: 26.0 * 2.0 * (end - start) as f64,
+            });
+        }
+    }
+
+    // qwerty, asdf
+    for row in KEYBOARD_ROWS {
+        let row: Vec<char> = row.chars().collect();
+        let position = |c: char| row.iter().position(|r| *r == c.to_ascii_lowercase());
+        let adjacent = |a: char, b: char| match (position(a), position(b)) {
+            (Some(x), Some(y)) => x.abs_diff(y) == 1,
+            _ => false,
+        };
+        for (start, end) in runs(chars, adjacent) {
+            matches.push(Match {
+                start,
+                end,
+                guesses: 40.0 * 2.0 * (end - start) as f64,
+            });
+        }
+    }
+
+    // 1987, 2024
+    for start in 0..chars.len().saturating_sub(3) {
+        let year: String = chars[start..start + 4].iter().collect();
+        if year
+            .parse::<u32>()
+            .is_ok_and(|y| (1900..=2049).contains(&y))
+        {
+            matches.push(Match {
+                start,
+                end: start + 4,
+                guesses: 150.0,
+            });
+        }
+    }
+}
+
+/// Estimated number of guesses needed to find `password`, as log10.
+pub fn guesses_log10(password: &str) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    if chars.is_empty() {
+        return 0.0;
+    }
+
+    let mut matches = Vec::new();
+    dictionary_matches(&chars, &mut matches);
+    pattern_matches(&chars, &mut matches);
+
+    // Cheapest cover of the first i characters, in log10 guesses
+    let mut best = vec![f64::INFINITY; chars.len() + 1];
+    best[0] = 0.0;
+    for end in 1..=chars.len() {
+        best[end] = best[end - 1] + BRUTEFORCE_CARDINALITY.log10();
+        for m in matches.iter().filter(|m| m.end == end) {
+            best[end] = best[end].min(best[m.start] + m.guesses.log10());
+        }
+    }
+    best[chars.len()]
+}
+
+/// Strength score from 0 (guessed instantly) to 4 (very hard to guess).
+pub fn score(password: &str) -> u8 {
+    match guesses_log10(password) {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_password_scores_zero() {
+        assert_eq!(guesses_log10(""), 0.0);
+        assert_eq!(score(""), 0);
+    }
+
+    #[test]
+    fn common_passwords_score_zero() {
+        for password in ["password", "123456", "qwerty", "letmein", "iloveyou"] {
+            assert_eq!(score(password), 0, "{}", password);
+        }
+    }
+
+    #[test]
+    fn substitutions_and_capitals_do_not_help_much() {
+        assert_eq!(score("P@ssw0rd"), 0);
+        assert_eq!(score("Password1"), 0);
+    }
+
+    #[test]
+    fn patterns_score_low() {
+        for password in [
+            "aaaaaaaaaaaa",
+            "abcdefgh",
+            "87654321",
+            "qwerty123",
+            "asdfghjkl",
+        ] {
+            assert!(score(password) <= 1, "{}", password);
+        }
+    }
+
+    #[test]
+    fn years_are_cheap() {
+        assert!(guesses_log10("1987") < guesses_log10("1387"));
+    }
+
+    #[test]
+    fn random_passwords_score_high() {
+        assert!(score("kT9#mQ2x") >= 3);
+        assert_eq!(score("x7#Kq9!vLm2$Tz8w"), 4);
+        assert_eq!(score("correct horse battery staple"), 4);
+    }
+
+    #[test]
+    fn longer_passwords_need_more_guesses() {
+        assert!(guesses_log10("kT9#mQ2x") < guesses_log10("kT9#mQ2xR4"));
+    }
+}
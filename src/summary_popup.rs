@@ -0,0 +1,107 @@
+use iced::widget;
+use iced::{Element, Length};
+
+/// Maximum height of the details list before it scrolls.
+const DETAILS_HEIGHT: f32 = 200.0;
+
+/// State for a modal summarizing the outcome of a longer operation.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The dialog title.
+    pub title: String,
+    /// One-line outcome, e.g. the number of imported secrets.
+    pub message: String,
+    /// Items worth pointing out, listed below the message.
+    pub details: Vec<String>,
+}
+
+impl State {
+    /// Create a new summary popup with the given title, message and details.
+    pub fn new(title: impl Into<String>, message: impl Into<String>, details: Vec<String>) -> Self {
+        Self {
+            title: title.into(),
+            message: message.into(),
+            details,
+        }
+    }
+}
+
+/// Messages for summary popup interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// User dismissed the summary (clicked OK or backdrop).
+    Dismiss,
+}
+
+/// Renders the summary popup modal.
+///
+/// # Arguments
+/// * `state` - The current summary popup state
+/// * `on_message` - Function to wrap summary popup messages into the parent message type
+pub fn view<'a, M>(state: &'a State, on_message: fn(Message) -> M) -> Element<'a, M>
+where
+    M: Clone + 'a,
+{
+    let title = widget::text(&state.title)
+        .size(20)
+        .width(Length::Fill)
+        .align_x(iced::alignment::Horizontal::Center);
+
+    let message = widget::text(&state.message)
+        .width(Length::Fill)
+        .align_x(iced::alignment::Horizontal::Center);
+
+    let mut details = widget::column![].spacing(2);
+    for detail in &state.details {
+        details = details.push(widget::text(detail).size(14));
+    }
+    let details = widget::scrollable(details.width(Length::Fill))
+        .height(Length::Shrink)
+        .width(Length::Fill);
+    let details = widget::container(details).max_height(DETAILS_HEIGHT);
+
+    let ok_button = widget::button(
+        widget::text("OK")
+            .align_x(iced::alignment::Horizontal::Center)
+            .width(Length::Fill),
+    )
+    .on_press(on_message(Message::Dismiss))
+    .padding(10)
+    .width(80);
+
+    let button_row = widget::row![
+        widget::horizontal_space(),
+        ok_button,
+        widget::horizontal_space()
+    ]
+    .align_y(iced::Alignment::Center);
+
+    let content = widget::column![title, message]
+        .push_maybe((!state.details.is_empty()).then_some(details))
+        .push(button_row)
+        .spacing(16)
+        .padding(24)
+        .width(400);
+
+    let modal = widget::container(content)
+        .style(|theme: &iced::Theme| widget::container::Style {
+            background: Some(iced::Background::Color(iced::Color::WHITE)),
+            text_color: Some(theme.palette().text),
+            border: iced::Border {
+                color: iced::Color::from_rgb(0.7, 0.7, 0.7),
+                width: 1.0,
+                radius: 8.0.into(),
+            },
+            shadow: iced::Shadow {
+                color: iced::Color::from_rgba(0.0, 0.0, 0.0, 0.3),
+                offset: iced::Vector::new(0.0, 4.0),
+                blur_radius: 16.0,
+            },
+        })
+        .width(Length::Shrink);
+
+    widget::container(modal)
+        .center_x(Length::Fill)
+        .center_y(Length::Fill)
+        .into()
+}
@@ -0,0 +1,363 @@
+use crate::crypto;
+use log::{error, trace};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single file or directory in the store tree.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub path: PathBuf,
+    pub name: String,
+    pub is_dir: bool,
+    /// Children of a directory, `None` until the directory is first expanded.
+    children: Option<Vec<Node>>,
+}
+
+/// A row in the flattened view of the tree, in display order.
+#[derive(Debug, Clone)]
+pub struct VisibleRow {
+    pub path: PathBuf,
+    pub depth: u16,
+    pub is_dir: bool,
+}
+
+/// In-memory model of the store directory tree.
+///
+/// Directory contents are read once, sorted, and cached. Subdirectories are
+/// loaded lazily the first time they are expanded, so rendering never touches
+/// the filesystem. The top-level entries are read in the background with
+/// [`reload`] and stored with [`Tree::set_nodes`].
+#[derive(Debug)]
+pub struct Tree {
+    root: PathBuf,
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    /// Create an empty tree rooted at `root`.
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Look up a node by path.
+    pub fn find(&self, path: &Path) -> Option<&Node> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut nodes = &self.nodes;
+        let mut found = None;
+        for component in relative.components() {
+            let node = nodes
+                .iter()
+                .find(|n| n.name.as_str() == component.as_os_str())?;
+            found = Some(node);
+            nodes = match &node.children {
+                Some(children) => children,
+                None => &EMPTY,
+            };
+        }
+        found
+    }
+
+    fn find_mut(&mut self, path: &Path) -> Option<&mut Node> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut components = relative.components().peekable();
+        let mut nodes = &mut self.nodes;
+        loop {
+            let component = components.next()?;
+            let node = nodes
+                .iter_mut()
+                .find(|n| n.name.as_str() == component.as_os_str())?;
+            if components.peek().is_none() {
+                return Some(node);
+            }
+            nodes = node.children.as_mut()?;
+        }
+    }
+
+    /// Whether `path` is a directory in the tree.
+    pub fn is_dir(&self, path: &Path) -> bool {
+        self.find(path).is_some_and(|n| n.is_dir)
+    }
+
+    /// Whether the children of the directory at `path` have been read.
+    pub fn is_loaded(&self, path: &Path) -> bool {
+        self.find(path).is_some_and(|n| n.children.is_some())
+    }
+
+    /// Store the children of a directory read in the background with [`read_children`].
+    pub fn set_children(&mut self, path: &Path, children: Vec<Node>) {
+        if let Some(node) = self.find_mut(path) {
+            if node.is_dir && node.children.is_none() {
+                node.children = Some(children);
+            }
+        }
+    }
+
+    /// The top-level entries, to re-read them with [`reload`].
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Replace the entries with those read by [`reload`], keeping the
+    /// directories loaded in the meantime.
+    pub fn set_nodes(&mut self, mut nodes: Vec<Node>) {
+        keep_loaded(&mut nodes, &self.nodes);
+        self.nodes = nodes;
+    }
+
+    /// Flatten the tree into display rows, descending into expanded directories.
+    pub fn visible_rows(&self, expanded: &HashSet<PathBuf>) -> Vec<VisibleRow> {
+        let mut rows = Vec::new();
+        collect_rows(&self.nodes, expanded, 0, &mut rows);
+        rows
+    }
+}
+
+static EMPTY: Vec<Node> = Vec::new();
+
+fn collect_rows(
+    nodes: &[Node],
+    expanded: &HashSet<PathBuf>,
+    depth: u16,
+    rows: &mut Vec<VisibleRow>,
+) {
+    for node in nodes {
+        rows.push(VisibleRow {
+            path: node.path.clone(),
+            depth,
+            is_dir: node.is_dir,
+        });
+        if node.is_dir && expanded.contains(&node.path) {
+            if let Some(children) = &node.children {
+                collect_rows(children, expanded, depth + 1, rows);
+            }
+        }
+    }
+}
+
+/// Read a directory again, also reloading subdirectories that were loaded in
+/// `old`, e.g. after a pull.
+pub fn reload(path: &Path, old: &[Node]) -> Vec<Node> {
+    let mut nodes = read_children(path);
+    for node in nodes.iter_mut().filter(|n| n.is_dir) {
+        let previous = old.iter().find(|n| n.path == node.path && n.is_dir);
+        if let Some(children) = previous.and_then(|n| n.children.as_ref()) {
+            node.children = Some(reload(&node.path, children));
+        }
+    }
+    nodes
+}
+
+/// Copy the children of directories loaded in `current` but not in `nodes`.
+fn keep_loaded(nodes: &mut [Node], current: &[Node]) {
+    for node in nodes.iter_mut().filter(|n| n.is_dir) {
+        let Some(previous) = current.iter().find(|n| n.path == node.path && n.is_dir) else {
+            continue;
+        };
+        match (&mut node.children, &previous.children) {
+            (Some(children), Some(previous)) => keep_loaded(children, previous),
+            (None, Some(previous)) => node.children = Some(previous.clone()),
+            _ => {}
+        }
+    }
+}
+
+/// Read and sort the non-hidden entries of a directory.
+pub fn read_children(path: &Path) -> Vec<Node> {
+    let entries = match fs::read_dir(path) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to read directory '{}': {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut nodes = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    "Failed to get entry from directory '{}': {}",
+                    path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Skip hidden files (starting with .)
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let entry_path = entry.path();
+        let is_dir = entry_path.is_dir();
+        trace!(
+            "Loaded {} '{}'",
+            if is_dir { "directory" } else { "file" },
+            entry_path.display()
+        );
+        nodes.push(Node {
+            path: entry_path,
+            name,
+            is_dir,
+            children: None,
+        });
+    }
+
+    nodes.sort_by(|a, b| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| natural_cmp(&a.name, &b.name))
+    });
+    nodes
+}
+
+/// Every secret below `dir`, recursively, skipping hidden files and
+/// directories like `.git`. Sorted by path.
+pub fn secret_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(secret_files(&path)?);
+        } else if crypto::backend_of(&path).is_some() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Compare two names case-insensitively, treating runs of digits as numbers
+/// so that `key2` sorts before `key10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_num = take_digits(&mut a_chars);
+                let b_num = take_digits(&mut b_chars);
+                let a_trimmed = a_num.trim_start_matches('0');
+                let b_trimmed = b_num.trim_start_matches('0');
+                let ordering = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().copied() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(
+            sorted(&["beta", "Alpha", "Gamma"]),
+            ["Alpha", "beta", "Gamma"]
+        );
+        // Names differing only in case still have a stable order
+        assert_eq!(natural_cmp("Mail", "mail"), Ordering::Less);
+        assert_eq!(natural_cmp("mail", "mail"), Ordering::Equal);
+    }
+
+    #[test]
+    fn compares_numbers_by_value() {
+        assert_eq!(sorted(&["a10", "a2", "a1"]), ["a1", "a2", "a10"]);
+        assert_eq!(
+            sorted(&["key10b", "key10a", "key9"]),
+            ["key9", "key10a", "key10b"]
+        );
+        assert_eq!(natural_cmp("2fa", "fa"), Ordering::Less);
+    }
+
+    #[test]
+    fn ignores_leading_zeros() {
+        assert_eq!(sorted(&["a10", "a009", "a1"]), ["a1", "a009", "a10"]);
+        // Equal numbers fall back to the plain order
+        assert_eq!(natural_cmp("a01", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("a01x", "a1y"), Ordering::Less);
+    }
+
+    #[test]
+    fn handles_non_ascii_names() {
+        assert_eq!(natural_cmp("ÉCLAIR2", "éclair10"), Ordering::Less);
+        assert_eq!(
+            sorted(&["Ärzte", "ärzte3", "Zoo"]),
+            ["Zoo", "Ärzte", "ärzte3"]
+        );
+        // Only ASCII digits are numbers
+        assert_eq!(natural_cmp("x²", "x10"), Ordering::Greater);
+    }
+
+    #[test]
+    fn lists_directories_first() {
+        let dir = std::env::temp_dir().join(format!("defira-tree-{}", std::process::id()));
+        fs::create_dir_all(dir.join("zeta")).unwrap();
+        fs::create_dir_all(dir.join("Alpha")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        for name in ["beta.gpg", "item10.gpg", "item2.gpg", ".gpg-id"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let nodes = read_children(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["Alpha", "zeta", "beta.gpg", "item2.gpg", "item10.gpg"]
+        );
+        assert!(nodes[0].is_dir && nodes[1].is_dir && !nodes[2].is_dir);
+    }
+}
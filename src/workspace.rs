@@ -0,0 +1,327 @@
+//! Several password stores open side by side, each in its own explorer with
+//! its own git remote and crypto backend. A bar above the explorer switches
+//! between them, and search spans all of them.
+
+use crate::crypto::{self, CryptoBackend};
+use crate::file_explorer::{self, FileAction};
+use crate::search;
+use crate::stores::{self, StoreConfig};
+use iced::widget;
+use iced::{window, Element, Length, Subscription, Task};
+use log::{debug, error};
+
+/// A configured store and its explorer.
+struct Workspace {
+    /// Stable identifier, so that messages of a removed store are dropped
+    /// instead of reaching another one.
+    id: usize,
+    config: StoreConfig,
+    /// Backend of the store, resolved once when it is opened.
+    backend: &'static dyn CryptoBackend,
+    explorer: Box<file_explorer::State>,
+}
+
+/// A request every store has to agree to, asked of one store after the other
+/// so that each can ask about its unsaved changes.
+#[derive(Debug, Clone, Copy)]
+enum Request {
+    Close(window::Id),
+    Lock,
+}
+
+/// Messages for store switcher interactions.
+#[derive(Debug, Clone)]
+pub enum Message {
+    Select(usize),
+    /// Set up another store. Handled by the parent.
+    AddStore,
+    Remove(usize),
+    Explorer(usize, FileAction),
+}
+
+/// The open stores and which one is shown.
+pub struct State {
+    workspaces: Vec<Workspace>,
+    /// Identifier of the store shown.
+    active: usize,
+    next_id: usize,
+    /// The request in progress and the stores that did not agree yet, the
+    /// one asked first.
+    request: Option<(Request, Vec<usize>)>,
+}
+
+impl State {
+    /// Open every configured store, showing the first.
+    pub fn new(configs: Vec<StoreConfig>) -> (Self, Task<Message>) {
+        let mut state = Self {
+            workspaces: Vec::new(),
+            active: 0,
+            next_id: 0,
+            request: None,
+        };
+        let mut tasks = Vec::new();
+        for config in configs {
+            tasks.push(state.open(config).1);
+        }
+        if let Some(first) = state.workspaces.first() {
+            state.active = first.id;
+        }
+        state.update_search_stores();
+        (state, Task::batch(tasks))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workspaces.is_empty()
+    }
+
+    /// Whether any store has changes that are not saved.
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.workspaces
+            .iter()
+            .any(|w| w.explorer.has_unsaved_changes())
+    }
+
+    /// Open a store, returning its identifier and the task reading it.
+    fn open(&mut self, config: StoreConfig) -> (usize, Task<Message>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        debug!(
+            "Opening store '{}' at '{}'",
+            config.name,
+            config.root.display()
+        );
+        let backend = crypto::store_backend(&config.root);
+        let (explorer, task) = file_explorer::State::new(config.root.clone());
+        self.workspaces.push(Workspace {
+            id,
+            config,
+            backend,
+            explorer: Box::new(explorer),
+        });
+        (id, task.map(move |action| Message::Explorer(id, action)))
+    }
+
+    fn get_mut(&mut self, id: usize) -> Option<&mut Workspace> {
+        self.workspaces.iter_mut().find(|w| w.id == id)
+    }
+
+    /// Let every explorer search all stores.
+    fn update_search_stores(&mut self) {
+        let stores: Vec<_> = self
+            .workspaces
+            .iter()
+            .map(|w| (w.config.name.clone(), w.config.root.clone()))
+            .collect();
+        for workspace in &mut self.workspaces {
+            workspace.explorer.set_search_stores(stores.clone());
+        }
+    }
+
+    fn save(&self) {
+        let configs: Vec<_> = self.workspaces.iter().map(|w| w.config.clone()).collect();
+        if let Err(e) = stores::save(&configs) {
+            error!("Failed to save the stores: {}", e);
+        }
+    }
+
+    /// Add a store and show it. A store that is already open is only shown.
+    pub fn add(&mut self, config: StoreConfig) -> Task<Message> {
+        if let Some(existing) = self
+            .workspaces
+            .iter()
+            .find(|w| w.config.root == config.root)
+        {
+            self.active = existing.id;
+            return Task::none();
+        }
+        let (id, task) = self.open(config);
+        self.active = id;
+        self.update_search_stores();
+        self.save();
+        task
+    }
+
+    /// Handle a request to close the window. The window is closed once
+    /// every store agreed to it.
+    pub fn close_requested(&mut self, id: window::Id) -> Task<Message> {
+        self.start(Request::Close(id))
+    }
+
+    /// Ask every store for `request`, replacing a request the user did not
+    /// agree to.
+    fn start(&mut self, request: Request) -> Task<Message> {
+        debug!("Asking {} stores for {:?}", self.workspaces.len(), request);
+        let ids = self.workspaces.iter().map(|w| w.id).collect();
+        self.request = Some((request, ids));
+        self.ask_next()
+    }
+
+    /// Ask the next store for the request in progress, or carry it out once
+    /// every store agreed.
+    fn ask_next(&mut self) -> Task<Message> {
+        let Some((request, remaining)) = &mut self.request else {
+            return Task::none();
+        };
+        let request = *request;
+        // Removed stores have nothing left to agree to
+        remaining.retain(|id| self.workspaces.iter().any(|w| w.id == *id));
+        let Some(&id) = remaining.first() else {
+            self.request = None;
+            return match request {
+                Request::Close(window) => window::close(window),
+                Request::Lock => {
+                    debug!("Every store is locked, clearing cached passphrases");
+                    crypto::forget_passphrases();
+                    Task::none()
+                }
+            };
+        };
+
+        // Show a store with unsaved changes so that the user can decide about them
+        if self
+            .get_mut(id)
+            .is_some_and(|w| w.explorer.has_unsaved_changes())
+        {
+            self.active = id;
+        }
+        let action = match request {
+            Request::Close(window) => FileAction::CloseRequested(window),
+            Request::Lock => FileAction::LockStore,
+        };
+        self.update(Message::Explorer(id, action))
+    }
+
+    /// A store agreed to the request in progress.
+    fn agreed(&mut self, id: usize) -> Task<Message> {
+        match &mut self.request {
+            Some((_, remaining)) if remaining.first() == Some(&id) => {
+                remaining.remove(0);
+                self.ask_next()
+            }
+            _ => Task::none(),
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Select(id) => self.active = id,
+            Message::Remove(id) => {
+                let Some(index) = self.workspaces.iter().position(|w| w.id == id) else {
+                    return Task::none();
+                };
+                let removed = self.workspaces.remove(index);
+                debug!("Removed store '{}'", removed.config.name);
+                if self.active == id {
+                    if let Some(first) = self.workspaces.first() {
+                        self.active = first.id;
+                    }
+                }
+                self.update_search_stores();
+                self.save();
+            }
+            // Search results in another store open in that store
+            Message::Explorer(id, FileAction::Search(search::Message::Open(path)))
+                if self
+                    .get_mut(id)
+                    .is_some_and(|w| !path.starts_with(&w.config.root)) =>
+            {
+                let Some(target) = self
+                    .workspaces
+                    .iter()
+                    .find(|w| path.starts_with(&w.config.root))
+                    .map(|w| w.id)
+                else {
+                    return Task::none();
+                };
+                self.active = target;
+                return self.update(Message::Explorer(
+                    target,
+                    FileAction::Search(search::Message::Open(path)),
+                ));
+            }
+            Message::Explorer(_, FileAction::Lock) => return self.start(Request::Lock),
+            Message::Explorer(id, FileAction::Locked | FileAction::ReadyToClose(_)) => {
+                return self.agreed(id);
+            }
+            Message::Explorer(id, action) => {
+                if let Some(workspace) = self.get_mut(id) {
+                    return file_explorer::update(&mut workspace.explorer, action)
+                        .map(move |action| Message::Explorer(id, action));
+                }
+            }
+            Message::AddStore => {}
+        }
+        Task::none()
+    }
+}
+
+pub fn subscription(state: &State) -> Subscription<Message> {
+    // Shortcuts apply to the store shown
+    match state.workspaces.iter().find(|w| w.id == state.active) {
+        Some(workspace) => {
+            let id = workspace.id;
+            file_explorer::subscription(&workspace.explorer)
+                .with(id)
+                .map(|(id, action)| Message::Explorer(id, action))
+        }
+        None => Subscription::none(),
+    }
+}
+
+/// Renders the store switcher above the explorer of the active store.
+pub fn view(state: &State) -> Element<'_, Message> {
+    let mut bar = widget::row![].spacing(4).align_y(iced::Alignment::Center);
+    for workspace in &state.workspaces {
+        let label = if workspace.backend.name() == crypto::GPG.name() {
+            workspace.config.name.clone()
+        } else {
+            format!("{} ({})", workspace.config.name, workspace.backend.name())
+        };
+        let style = if workspace.id == state.active {
+            widget::button::primary
+        } else {
+            widget::button::secondary
+        };
+        bar = bar.push(
+            widget::button(widget::text(label).size(14))
+                .on_press(Message::Select(workspace.id))
+                .style(style),
+        );
+    }
+    bar = bar.push(
+        widget::button(widget::text("Add store...").size(14))
+            .on_press(Message::AddStore)
+            .style(widget::button::text),
+    );
+
+    let active = state.workspaces.iter().find(|w| w.id == state.active);
+    if let Some(workspace) = active {
+        let remote = workspace.config.remote.as_deref().unwrap_or("No remote");
+        // Closing a store with unsaved changes would lose them
+        let remove = widget::button(widget::text("Remove").size(14))
+            .on_press_maybe(
+                (!workspace.explorer.has_unsaved_changes())
+                    .then_some(Message::Remove(workspace.id)),
+            )
+            .style(widget::button::text);
+        bar = bar
+            .push(widget::horizontal_space())
+            .push(widget::text(remote).size(12))
+            .push(remove);
+    }
+
+    let explorer: Element<'_, Message> = match active {
+        Some(workspace) => {
+            let id = workspace.id;
+            file_explorer::view(&workspace.explorer)
+                .map(move |action| Message::Explorer(id, action))
+        }
+        None => widget::text("No store configured").into(),
+    };
+
+    widget::column![
+        widget::container(bar).padding([4, 8]).width(Length::Fill),
+        explorer
+    ]
+    .into()
+}